@@ -9,6 +9,7 @@ import shutil
 ap = argparse.ArgumentParser()
 ap.add_argument("--no-split", action="store_true")
 ap.add_argument("--optimize", action="store_true")
+ap.add_argument("--split-after-bindgen", action="store_true")
 args = ap.parse_args()
 
 root_dir = os.path.dirname(__file__)
@@ -80,34 +81,73 @@ else:
     split_temp_dir = os.path.join(root_dir, "split_tmp")
     shutil.rmtree(split_temp_dir, ignore_errors=True)
 
-    subprocess.run(
-        [
-            "cargo",
-            "run",
-            "-p",
-            "wasm_split_cli",
-            "--",
-            target_path,
-            split_temp_dir,
-        ],
-        cwd=root_dir,
-        check=True,
-    )
-
-    subprocess.run(
-        [
-            "wasm-bindgen",
+    if args.split_after_bindgen:
+        subprocess.run(
+            [
+                "wasm-bindgen",
+                target_path,
+                "--out-dir",
+                pkg_dir,
+                "--out-name",
+                "main",
+                "--no-demangle",
+                "--target",
+                "web",
+                "--keep-lld-exports",
+            ],
+            cwd=root_dir,
+            check=True,
+        )
+
+        subprocess.run(
+            [
+                "cargo",
+                "run",
+                "-p",
+                "wasm_split_cli",
+                "--",
+                target_path,
+                split_temp_dir,
+                "--bindgen-output",
+                os.path.join(pkg_dir, "main_bg.wasm"),
+            ],
+            cwd=root_dir,
+            check=True,
+        )
+
+        shutil.copyfile(
             os.path.join(split_temp_dir, "main.wasm"),
-            "--out-dir",
-            pkg_dir,
-            "--no-demangle",
-            "--target",
-            "web",
-            "--keep-lld-exports",
-        ],
-        cwd=root_dir,
-        check=True,
-    )
+            os.path.join(pkg_dir, "main_bg.wasm"),
+        )
+    else:
+        subprocess.run(
+            [
+                "cargo",
+                "run",
+                "-p",
+                "wasm_split_cli",
+                "--",
+                target_path,
+                split_temp_dir,
+            ],
+            cwd=root_dir,
+            check=True,
+        )
+
+        subprocess.run(
+            [
+                "wasm-bindgen",
+                os.path.join(split_temp_dir, "main.wasm"),
+                "--out-dir",
+                pkg_dir,
+                "--no-demangle",
+                "--target",
+                "web",
+                "--keep-lld-exports",
+            ],
+            cwd=root_dir,
+            check=True,
+        )
 
     for name in os.listdir(split_temp_dir):
         if name == "main.wasm":
@@ -1,105 +1,382 @@
 use std::{
-    cell::Cell,
+    cell::{Cell, RefCell},
+    collections::HashMap,
     ffi::c_void,
     future::Future,
     pin::Pin,
     rc::Rc,
     task::{Context, Poll, Waker},
+    time::Duration,
 };
 
-pub use wasm_split_macros::wasm_split;
+pub use wasm_split_macros::{preload, wasm_split};
 
-pub type LoadCallbackFn = unsafe extern "C" fn(*const c_void, bool) -> ();
+/// Calling a generic split function with type parameters that are not listed
+/// in `instantiate(...)` does not compile:
+///
+/// ```compile_fail,E0277
+/// #[wasm_split::wasm_split(numbers, instantiate(u32, u8))]
+/// fn double<T: std::ops::Add<Output = T> + Copy>(value: T) -> T {
+///     value + value
+/// }
+///
+/// async fn call() -> i64 {
+///     double(1i64).await
+/// }
+/// ```
+#[cfg(doctest)]
+pub struct UnlistedInstantiation;
+
+/// Receives the status of a load: `LOAD_SUCCESS` or one of the
+/// `LOAD_*_FAILED` codes.
+pub type LoadCallbackFn = unsafe extern "C" fn(*const c_void, u32) -> ();
 pub type LoadFn = unsafe extern "C" fn(LoadCallbackFn, *const c_void) -> ();
 
-type Lazy = async_once_cell::Lazy<Option<()>, SplitLoaderFuture>;
+// Status codes reported by the JS loader. These must match the generated
+// `__wasm_split.js`.
+pub const LOAD_SUCCESS: u32 = 0;
+pub const LOAD_FETCH_FAILED: u32 = 1;
+pub const LOAD_INSTANTIATE_FAILED: u32 = 2;
+pub const LOAD_DEPENDENCY_FAILED: u32 = 3;
+pub const LOAD_INTEGRITY_FAILED: u32 = 4;
+
+/// Reason that a split module failed to load.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadErrorReason {
+    /// The module could not be fetched.
+    Fetch,
+    /// The module was fetched but could not be compiled or instantiated.
+    Instantiate,
+    /// A module that the module depends on failed to load.
+    Dependency,
+    /// The fetched module does not match the digest recorded when it was
+    /// split.
+    Integrity,
+    /// The loader reported an unrecognized status code.
+    Unknown(u32),
+}
+
+impl LoadErrorReason {
+    fn from_status(status: u32) -> Result<(), Self> {
+        match status {
+            LOAD_SUCCESS => Ok(()),
+            LOAD_FETCH_FAILED => Err(Self::Fetch),
+            LOAD_INSTANTIATE_FAILED => Err(Self::Instantiate),
+            LOAD_DEPENDENCY_FAILED => Err(Self::Dependency),
+            LOAD_INTEGRITY_FAILED => Err(Self::Integrity),
+            status => Err(Self::Unknown(status)),
+        }
+    }
+}
+
+/// Error returned by fallible split functions when the split module could not
+/// be loaded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoadError {
+    module: &'static str,
+    reason: LoadErrorReason,
+}
+
+impl LoadError {
+    /// Name of the split module that failed to load.
+    pub fn module(&self) -> &'static str {
+        self.module
+    }
+
+    pub fn reason(&self) -> LoadErrorReason {
+        self.reason
+    }
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self.reason {
+            LoadErrorReason::Fetch => "fetch failed".to_string(),
+            LoadErrorReason::Instantiate => "instantiation failed".to_string(),
+            LoadErrorReason::Dependency => "a dependency failed to load".to_string(),
+            LoadErrorReason::Integrity => "integrity check failed".to_string(),
+            LoadErrorReason::Unknown(status) => format!("unknown status {status}"),
+        };
+        write!(f, "failed to load split module {}: {reason}", self.module)
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// Governs whether a split module is loaded again after a failed load.
+///
+/// The two layers of retries combine: each load is a single call into the JS
+/// loader, which fetches the module up to `attempts` times with exponential
+/// backoff before reporting a failure (see `setRetryPolicy` in
+/// `__wasm_split.js` and `--retry-attempts`). A failed load is only attempted
+/// again when the module is next needed, so a module is fetched at most
+/// `max_attempts * attempts` times. Like the fetches of the JS loader, each
+/// load after the first is delayed by `backoff`, doubled for every earlier
+/// failed load.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of times each module is loaded, or `None` to try again
+    /// every time the module is needed.
+    pub max_attempts: Option<u32>,
+    /// Delay before the second load of a module.
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: Some(3),
+            backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+thread_local! {
+    static RETRY_POLICY: Cell<RetryPolicy> = Cell::new(RetryPolicy::default());
+
+    // Loaders shared by all split functions in the same module.
+    static LOADERS: RefCell<HashMap<&'static str, Rc<SplitLoader>>> = RefCell::new(HashMap::new());
+}
+
+/// Sets the retry policy used by all split loaders on the current thread.
+pub fn set_retry_policy(policy: RetryPolicy) {
+    RETRY_POLICY.with(|cell| cell.set(policy));
+}
 
 pub struct LazySplitLoader {
-    lazy: Pin<Rc<Lazy>>,
+    loader: Rc<SplitLoader>,
 }
 
 impl LazySplitLoader {
-    pub unsafe fn new(load: LoadFn) -> Self {
+    /// # Safety
+    ///
+    /// `load` must invoke the callback it is given exactly once per call,
+    /// with the data pointer it is given.
+    pub unsafe fn new(load: LoadFn, module: &'static str) -> Self {
         Self {
-            lazy: Rc::pin(Lazy::new(SplitLoaderFuture::new(SplitLoader::new(load)))),
+            loader: SplitLoader::get(load, module),
         }
     }
 }
 
-pub async fn ensure_loaded(loader: &'static std::thread::LocalKey<LazySplitLoader>) -> Option<()> {
-    *loader.with(|inner| inner.lazy.clone()).as_ref().await
+/// Loading status of a split module.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadStatus {
+    NotLoaded,
+    Loading,
+    Loaded,
+    /// The most recent attempt to load the module failed.
+    Failed(LoadError),
+}
+
+/// Returns the loading status of `module`.
+///
+/// Only loads requested through this crate are tracked: a module loaded
+/// directly from JavaScript is reported as `NotLoaded` until one of its
+/// functions is called.
+pub fn status(module: &str) -> LoadStatus {
+    LOADERS.with(|loaders| {
+        let Some(loader) = loaders.borrow().get(module).cloned() else {
+            return LoadStatus::NotLoaded;
+        };
+        match loader.state.get() {
+            SplitLoaderState::Deferred => LoadStatus::NotLoaded,
+            SplitLoaderState::Pending => LoadStatus::Loading,
+            SplitLoaderState::Completed => LoadStatus::Loaded,
+            SplitLoaderState::Failed(error) => LoadStatus::Failed(error),
+        }
+    })
+}
+
+#[wasm_split_macros::loader_import]
+extern "C" {
+    fn __wasm_split_module_count() -> u32;
+    fn __wasm_split_module_name(index: u32, buf: *mut u8, len: u32) -> u32;
+    // Invokes the callback with `LOAD_SUCCESS` after `ms` milliseconds.
+    fn __wasm_split_set_timeout(ms: u32, callback: LoadCallbackFn, data: *const c_void);
+}
+
+/// Returns the names of all split modules in the program.
+pub fn modules() -> Vec<String> {
+    let count = unsafe { __wasm_split_module_count() };
+    (0..count)
+        .map(|index| {
+            let mut buf = vec![0u8; 64];
+            loop {
+                let len =
+                    unsafe { __wasm_split_module_name(index, buf.as_mut_ptr(), buf.len() as u32) }
+                        as usize;
+                if len <= buf.len() {
+                    buf.truncate(len);
+                    break;
+                }
+                buf.resize(len, 0);
+            }
+            String::from_utf8(buf).unwrap()
+        })
+        .collect()
+}
+
+/// Starts loading `module`, if it is not already loaded or being loaded.
+///
+/// This is normally invoked through the `preload!` macro.
+///
+/// # Safety
+///
+/// Same requirements as `LazySplitLoader::new`.
+pub unsafe fn preload(load: LoadFn, module: &'static str) {
+    let loader = SplitLoader::get(load, module);
+    match loader.state.get() {
+        SplitLoaderState::Deferred => loader.start(),
+        SplitLoaderState::Failed(_) if loader.can_retry() => loader.start(),
+        _ => {}
+    }
+}
+
+pub async fn ensure_loaded(
+    loader: &'static std::thread::LocalKey<LazySplitLoader>,
+) -> Result<(), LoadError> {
+    SplitLoaderFuture::new(loader.with(|inner| inner.loader.clone())).await
 }
 
 #[derive(Clone, Copy, Debug)]
 enum SplitLoaderState {
-    Deferred(LoadFn),
+    Deferred,
     Pending,
-    Completed(Option<()>),
+    Completed,
+    // The most recent attempt failed. Depending on the retry policy, the next
+    // request for the module starts another attempt.
+    Failed(LoadError),
 }
 
 struct SplitLoader {
+    load: LoadFn,
+    module: &'static str,
     state: Cell<SplitLoaderState>,
-    waker: Cell<Option<Waker>>,
+    attempts: Cell<u32>,
+    wakers: RefCell<Vec<Waker>>,
 }
 
 impl SplitLoader {
-    fn new(load: LoadFn) -> Rc<Self> {
-        Rc::new(SplitLoader {
-            state: Cell::new(SplitLoaderState::Deferred(load)),
-            waker: Cell::new(None),
+    fn get(load: LoadFn, module: &'static str) -> Rc<Self> {
+        LOADERS.with(|loaders| {
+            loaders
+                .borrow_mut()
+                .entry(module)
+                .or_insert_with(|| {
+                    Rc::new(SplitLoader {
+                        load,
+                        module,
+                        state: Cell::new(SplitLoaderState::Deferred),
+                        attempts: Cell::new(0),
+                        wakers: RefCell::new(Vec::new()),
+                    })
+                })
+                .clone()
         })
     }
 
-    fn complete(&self, value: bool) {
-        self.state.set(SplitLoaderState::Completed(if value {
-            Some(())
+    fn can_retry(&self) -> bool {
+        RETRY_POLICY
+            .with(|policy| policy.get())
+            .max_attempts
+            .is_none_or(|max_attempts| self.attempts.get() < max_attempts)
+    }
+
+    // Delay before the next load, matching `readWithRetry` in the JS loader.
+    fn backoff(&self) -> Duration {
+        match self.attempts.get() {
+            0 => Duration::ZERO,
+            failed => RETRY_POLICY
+                .with(|policy| policy.get())
+                .backoff
+                .saturating_mul(2u32.saturating_pow(failed - 1)),
+        }
+    }
+
+    fn start(self: &Rc<Self>) {
+        let backoff = self.backoff();
+        self.state.set(SplitLoaderState::Pending);
+        self.attempts.set(self.attempts.get() + 1);
+        let data = Rc::<SplitLoader>::into_raw(self.clone()) as *const c_void;
+        if backoff.is_zero() {
+            unsafe { (self.load)(load_callback, data) };
         } else {
-            None
-        }));
-        match self.waker.take() {
-            Some(waker) => {
-                waker.wake();
-            }
-            _ => {}
+            let ms = u32::try_from(backoff.as_millis()).unwrap_or(u32::MAX);
+            unsafe { __wasm_split_set_timeout(ms, backoff_callback, data) };
+        }
+    }
+
+    fn register_waker(&self, waker: &Waker) {
+        let mut wakers = self.wakers.borrow_mut();
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+
+    fn complete(&self, status: u32) {
+        self.state.set(match LoadErrorReason::from_status(status) {
+            Ok(()) => SplitLoaderState::Completed,
+            Err(reason) => SplitLoaderState::Failed(LoadError {
+                module: self.module,
+                reason,
+            }),
+        });
+        for waker in self.wakers.take() {
+            waker.wake();
         }
     }
 }
 
 struct SplitLoaderFuture {
     loader: Rc<SplitLoader>,
+    // Whether this future has waited on an attempt, in which case a failure
+    // is reported rather than retried.
+    waited: bool,
 }
 
 impl SplitLoaderFuture {
     fn new(loader: Rc<SplitLoader>) -> Self {
-        SplitLoaderFuture { loader }
+        SplitLoaderFuture {
+            loader,
+            waited: false,
+        }
     }
 }
 
 impl Future for SplitLoaderFuture {
-    type Output = Option<()>;
+    type Output = Result<(), LoadError>;
 
-    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<()>> {
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), LoadError>> {
         match self.loader.state.get() {
-            SplitLoaderState::Deferred(load) => {
-                self.loader.state.set(SplitLoaderState::Pending);
-                self.loader.waker.set(Some(cx.waker().clone()));
-                unsafe {
-                    load(
-                        load_callback,
-                        Rc::<SplitLoader>::into_raw(self.loader.clone()) as *const c_void,
-                    )
-                };
-                Poll::Pending
+            SplitLoaderState::Completed => return Poll::Ready(Ok(())),
+            SplitLoaderState::Failed(error) if self.waited || !self.loader.can_retry() => {
+                return Poll::Ready(Err(error))
+            }
+            SplitLoaderState::Deferred | SplitLoaderState::Failed(_) => {
+                self.loader.register_waker(cx.waker());
+                self.loader.start();
+                // The load may have completed synchronously.
+                match self.loader.state.get() {
+                    SplitLoaderState::Completed => return Poll::Ready(Ok(())),
+                    SplitLoaderState::Failed(error) => return Poll::Ready(Err(error)),
+                    _ => {}
+                }
             }
             SplitLoaderState::Pending => {
-                self.loader.waker.set(Some(cx.waker().clone()));
-                Poll::Pending
+                self.loader.register_waker(cx.waker());
             }
-            SplitLoaderState::Completed(value) => Poll::Ready(value),
         }
+        self.waited = true;
+        Poll::Pending
     }
 }
 
-unsafe extern "C" fn load_callback(loader: *const c_void, success: bool) {
-    unsafe { Rc::from_raw(loader as *const SplitLoader) }.complete(success);
+unsafe extern "C" fn load_callback(loader: *const c_void, status: u32) {
+    unsafe { Rc::from_raw(loader as *const SplitLoader) }.complete(status);
+}
+
+// Starts a delayed load, passing on the reference held by the timer.
+unsafe extern "C" fn backoff_callback(loader: *const c_void, _status: u32) {
+    unsafe { ((*(loader as *const SplitLoader)).load)(load_callback, loader) };
 }
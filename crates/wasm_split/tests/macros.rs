@@ -0,0 +1,178 @@
+//! The `wasm_split` attribute must expand to code that type checks for each
+//! supported kind of function.
+//!
+//! Split functions call imports that only the generated loader provides, so
+//! they are not called here. Their signatures are checked by functions that
+//! are type checked but never run, which keeps the imports out of the test
+//! binary.
+
+use std::{
+    ffi::c_void,
+    fmt::Display,
+    io::{Cursor, Read},
+};
+
+use wasm_split::{wasm_split, LoadCallbackFn, LoadError, LoadStatus};
+
+#[wasm_split(plain)]
+fn add(a: u32, b: u32) -> u32 {
+    a + b
+}
+
+#[wasm_split(plain)]
+async fn greet(name: String) -> String {
+    format!("hello {name}")
+}
+
+#[wasm_split(fallible_module, fallible)]
+fn checked_div(a: u32, b: u32) -> Option<u32> {
+    a.checked_div(b)
+}
+
+#[wasm_split(generic, instantiate(u32, String))]
+fn describe<T: Display>(value: T) -> String {
+    value.to_string()
+}
+
+#[wasm_split(generic, instantiate((u8, u16)))]
+fn pair<A: Copy, B: Copy>(a: A, b: B) -> (B, A) {
+    (b, a)
+}
+
+#[wasm_split(generic, instantiate(Vec<u8>))]
+fn length(value: impl AsRef<[u8]>) -> usize {
+    value.as_ref().len()
+}
+
+// Borrowed types can be instantiated.
+#[wasm_split(generic, instantiate(&[u8], Cursor<&[u8]>))]
+fn read_all<R: Read>(mut reader: R) -> Vec<u8> {
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf).unwrap();
+    buf
+}
+
+trait Shape {
+    type Size;
+}
+
+impl Shape for u8 {
+    type Size = u16;
+}
+
+// Associated types of type parameters, as plain and qualified paths.
+#[wasm_split(generic, instantiate(u8))]
+fn sizes<T: Shape>(a: T::Size, b: <T as Shape>::Size) -> Vec<<T as Shape>::Size> {
+    vec![a, b]
+}
+
+#[wasm_split(plain, import_module = "./other_loader.js")]
+fn from_other_loader() {}
+
+struct Counter {
+    count: u32,
+}
+
+#[wasm_split]
+impl Counter {
+    #[wasm_split(methods)]
+    fn get(&self) -> u32 {
+        self.count
+    }
+
+    #[wasm_split(methods, fallible)]
+    fn increment(&mut self, by: u32) -> u32 {
+        self.count += by;
+        self.count
+    }
+
+    #[wasm_split(methods)]
+    fn new_counter(count: u32) -> Self {
+        Self { count }
+    }
+
+    #[wasm_split(methods, instantiate(u8, u16))]
+    fn scaled<T: Into<u32>>(&self, factor: T) -> u32 {
+        self.count * factor.into()
+    }
+
+    fn unsplit(&self) -> u32 {
+        self.count
+    }
+}
+
+trait Render {
+    async fn render(&self, prefix: String) -> String;
+}
+
+#[wasm_split]
+impl Render for Counter {
+    #[wasm_split(methods)]
+    async fn render(&self, prefix: String) -> String {
+        format!("{prefix}{}", self.count)
+    }
+}
+
+/// Checks the signatures of the generated functions. Never called.
+#[allow(dead_code)]
+async fn check_signatures(mut counter: Counter) {
+    let _: u32 = add(1, 2).await;
+    let _: Option<u32> = try_call_add(1, 2);
+    let _: String = greet("a".to_string()).await;
+    let _: Option<String> = try_call_greet("a".to_string());
+
+    let _: Result<Option<u32>, LoadError> = checked_div(4, 2).await;
+    let _: Option<Option<u32>> = try_call_checked_div(4, 2);
+
+    let _: String = describe(1u32).await;
+    let _: String = describe("a".to_string()).await;
+    let _: Option<String> = try_call_describe(1u32);
+    let _: (u16, u8) = pair(1u8, 2u16).await;
+    let _: Option<(u16, u8)> = try_call_pair(1u8, 2u16);
+    let _: usize = length(vec![1u8]).await;
+    let _: Option<usize> = try_call_length(vec![1u8]);
+    let bytes = [1u8];
+    let _: Vec<u8> = read_all(&bytes[..]).await;
+    let _: Vec<u8> = read_all(Cursor::new(&bytes[..])).await;
+    let _: Option<Vec<u8>> = try_call_read_all(&bytes[..]);
+    let _: Vec<u16> = sizes::<u8>(1, 2).await;
+
+    let _: () = from_other_loader().await;
+
+    let _: u32 = counter.get().await;
+    let _: Option<u32> = counter.try_call_get();
+    let _: Result<u32, LoadError> = counter.increment(1).await;
+    let _: Option<u32> = counter.try_call_increment(1);
+    let _: Counter = Counter::new_counter(1).await;
+    let _: Option<Counter> = Counter::try_call_new_counter(1);
+    let _: u32 = counter.scaled(2u8).await;
+    let _: Option<u32> = counter.try_call_scaled(2u16);
+    let _: u32 = counter.unsplit();
+    let _: String = counter.render("count: ".to_string()).await;
+}
+
+const PRELOAD_MODULE: &str = "preloaded";
+
+/// Load function imported by `preload!(preloaded)`, which the generated
+/// loader would otherwise provide.
+#[no_mangle]
+unsafe extern "C" fn __wasm_split_load_preloaded(callback: LoadCallbackFn, data: *const c_void) {
+    unsafe { callback(data, wasm_split::LOAD_SUCCESS) };
+}
+
+/// Timer imported by the split loaders to delay loading a module again.
+#[no_mangle]
+unsafe extern "C" fn __wasm_split_set_timeout(
+    _ms: u32,
+    callback: LoadCallbackFn,
+    data: *const c_void,
+) {
+    unsafe { callback(data, wasm_split::LOAD_SUCCESS) };
+}
+
+#[test]
+fn preload_loads_module() {
+    assert_eq!(wasm_split::status(PRELOAD_MODULE), LoadStatus::NotLoaded);
+    wasm_split::preload!(preloaded);
+    assert_eq!(wasm_split::status(PRELOAD_MODULE), LoadStatus::Loaded);
+}
@@ -0,0 +1,100 @@
+//! Failed loads must be retried according to the `RetryPolicy`.
+//!
+//! The load function and the timer, which the generated loader would
+//! otherwise provide, complete synchronously, so the futures are ready after
+//! a single poll.
+
+use std::{
+    cell::{Cell, RefCell},
+    ffi::c_void,
+    future::Future,
+    pin::pin,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use wasm_split::{
+    LazySplitLoader, LoadCallbackFn, LoadError, LoadErrorReason, LoadStatus, RetryPolicy,
+};
+
+const MODULE: &str = "flaky";
+
+thread_local! {
+    // Number of loads that fail before one succeeds.
+    static FAILURES: Cell<u32> = const { Cell::new(0) };
+    static LOADS: Cell<u32> = const { Cell::new(0) };
+    // Delays requested from the timer, in milliseconds.
+    static DELAYS: RefCell<Vec<u32>> = const { RefCell::new(Vec::new()) };
+
+    static LOADER: LazySplitLoader = unsafe { LazySplitLoader::new(load_flaky, MODULE) };
+}
+
+unsafe extern "C" fn load_flaky(callback: LoadCallbackFn, data: *const c_void) {
+    LOADS.set(LOADS.get() + 1);
+    let status = if FAILURES.get() > 0 {
+        FAILURES.set(FAILURES.get() - 1);
+        wasm_split::LOAD_FETCH_FAILED
+    } else {
+        wasm_split::LOAD_SUCCESS
+    };
+    unsafe { callback(data, status) };
+}
+
+/// Timer imported by the split loaders, which fires immediately.
+#[no_mangle]
+unsafe extern "C" fn __wasm_split_set_timeout(
+    ms: u32,
+    callback: LoadCallbackFn,
+    data: *const c_void,
+) {
+    DELAYS.with_borrow_mut(|delays| delays.push(ms));
+    unsafe { callback(data, wasm_split::LOAD_SUCCESS) };
+}
+
+fn ensure_loaded() -> Result<(), LoadError> {
+    let future = pin!(wasm_split::ensure_loaded(&LOADER));
+    match future.poll(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(result) => result,
+        Poll::Pending => panic!("Load did not complete synchronously"),
+    }
+}
+
+fn set_retry_policy(max_attempts: u32) {
+    wasm_split::set_retry_policy(RetryPolicy {
+        max_attempts: Some(max_attempts),
+        backoff: Duration::from_millis(100),
+    });
+}
+
+#[test]
+fn failed_load_is_retried_after_backoff() {
+    set_retry_policy(3);
+    FAILURES.set(1);
+
+    let error = ensure_loaded().unwrap_err();
+    assert_eq!(error.reason(), LoadErrorReason::Fetch);
+    assert_eq!(wasm_split::status(MODULE), LoadStatus::Failed(error));
+    assert_eq!(DELAYS.take(), []);
+
+    ensure_loaded().unwrap();
+    assert_eq!(wasm_split::status(MODULE), LoadStatus::Loaded);
+    assert_eq!(DELAYS.take(), [100]);
+    assert_eq!(LOADS.get(), 2);
+}
+
+#[test]
+fn retries_stop_after_max_attempts() {
+    set_retry_policy(3);
+    FAILURES.set(u32::MAX);
+
+    for _ in 0..3 {
+        ensure_loaded().unwrap_err();
+    }
+    // The backoff doubles for each failed load.
+    assert_eq!(DELAYS.take(), [100, 200]);
+    assert_eq!(LOADS.get(), 3);
+
+    let error = ensure_loaded().unwrap_err();
+    assert_eq!(wasm_split::status(MODULE), LoadStatus::Failed(error));
+    assert_eq!(LOADS.get(), 3);
+}
@@ -1,137 +1,271 @@
-use std::{collections::HashMap, path::Path};
+use std::path::Path;
 
-use anyhow::Result;
-use clap::Parser;
-use split_point::SplitModuleIdentifier;
+use anyhow::{Context, Result};
+use clap::{Args, Parser, Subcommand};
+use wasm_split_lib::{GraphFormat, GraphLevel, LoaderTarget};
 
+/// Splits a WebAssembly module at the split points defined by the
+/// `wasm_split` attribute.
 #[derive(Debug, Parser)]
-#[command(name = "wasm-split")]
+#[command(
+    name = "wasm-split",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    split: SplitArgs,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Explain why symbols are included, by printing dependency paths from
+    /// exports, the start function, imports or split points to each symbol.
+    Why(WhyArgs),
+    /// Split the module, and record which functions are called.
+    ///
+    /// The loader exports `profileCounters()`, which returns the number of
+    /// calls of each function, and `profileBitmap()`, which returns the
+    /// bitmap of called functions. `profile_symbols.txt` in the output
+    /// directory names the function of each counter and bit.
+    Instrument(SplitArgs),
+    /// Combine bitmaps returned by `profileBitmap()` in instrumented builds
+    /// into a profile for `--profile`.
+    ProfileMerge(ProfileMergeArgs),
+}
+
+#[derive(Debug, Args)]
+struct SplitArgs {
     /// Input .wasm file.
-    input: Box<Path>,
+    #[arg(required = true)]
+    input: Option<Box<Path>>,
 
     /// Output directory.
-    output: Box<Path>,
+    #[arg(required = true)]
+    output: Option<Box<Path>>,
 
     /// Print verbose split information.
     #[arg(short, long)]
     verbose: bool,
-}
 
-mod dep_graph;
-mod emit;
-mod read;
-mod split_point;
+    /// Write a JSON report describing the contents of each output module.
+    #[arg(long)]
+    report: Option<Box<Path>>,
 
-fn main() -> Result<()> {
-    let args = Cli::parse();
-    let input_wasm = std::fs::read(&args.input)?;
-    let module = crate::read::InputModule::parse(&input_wasm)?;
-    let dep_graph = dep_graph::get_dependencies(&module)?;
-    let split_points = split_point::get_split_points(&module)?;
-    let split_program_info =
-        split_point::compute_split_modules(&module, &dep_graph, &split_points)?;
-
-    if args.verbose {
-        for (name, split_deps) in split_program_info.output_modules.iter() {
-            split_deps.print(format!("{:?}", name).as_str(), &module);
-        }
-    }
+    /// Write a visualization of the dependency graph, colored by output
+    /// module, to the output directory.
+    #[arg(long, value_name = "FORMAT")]
+    emit_graph: Option<GraphFormat>,
+
+    /// Level of detail of the graph written by `--emit-graph`.
+    #[arg(long, value_name = "LEVEL", default_value = "modules")]
+    graph_level: GraphLevel,
 
-    crate::emit::emit_modules(
-        &module,
-        &split_program_info,
-        &|output_module_index: usize, data: &[u8]| -> Result<()> {
-            let identifier = &split_program_info.output_modules[output_module_index].0;
-            let output_filename = identifier.name() + ".wasm";
-            let output_path = args.output.join(output_filename);
-            std::fs::create_dir_all(&args.output)?;
-            std::fs::write(output_path, data)?;
-            Ok(())
+    /// Merge chunks of code shared between split modules that are smaller
+    /// than this many bytes into a larger chunk or into the main module.
+    #[arg(long, default_value_t = 0, value_name = "BYTES")]
+    min_chunk_size: usize,
+
+    /// TOML file with manual placement overrides: `force_main` and `exclude`
+    /// lists of symbol patterns, and `[[pin]]` tables with `symbols` and
+    /// `module` keys.
+    #[arg(long)]
+    config: Option<Box<Path>>,
+
+    /// Profile listing the functions called during typical sessions, one per
+    /// line, as written by `profile-merge`. Called split functions are moved
+    /// to main, and main functions that were not called are moved to a
+    /// separately loaded `main_cold` module. Calls into `main_cold` before it
+    /// has loaded fetch it synchronously, which blocks the thread and is not
+    /// supported in Deno or service workers.
+    #[arg(long)]
+    profile: Option<Box<Path>>,
+
+    /// Name split modules `<name>.<hash>.wasm` after their contents, and write
+    /// `manifest.json` with the file and size of each module, and the
+    /// integrity digest of each module other than main. `main.wasm` keeps its
+    /// name for `wasm-bindgen`, which rewrites it.
+    #[arg(long)]
+    hash_file_names: bool,
+
+    /// Environment of the generated loader: `web` (ES module), `node`
+    /// (CommonJS), `worker` (for `importScripts`), `no-modules` (classic
+    /// script), or `custom=<TEMPLATE>`, a file with `{{RUNTIME}}`,
+    /// `{{EXPORTS}}` and `{{MODULES}}` placeholders, as documented on
+    /// `LoaderTarget::Custom`.
+    #[arg(long, default_value = "web", value_parser = parse_target)]
+    target: LoaderTarget,
+
+    /// Default number of times the generated loader fetches a module before
+    /// reporting a failure. This applies to each load, which the program
+    /// repeats according to `wasm_split::RetryPolicy`.
+    #[arg(long, default_value_t = 3)]
+    retry_attempts: u32,
+
+    /// Default delay in milliseconds before the first retry of a failed fetch.
+    /// The delay doubles after each failed attempt.
+    #[arg(long, default_value_t = 500)]
+    retry_backoff_ms: u32,
+
+    /// Split this output of `wasm-bindgen --no-demangle --keep-lld-exports`
+    /// instead of the input, which must be the module that was passed to
+    /// `wasm-bindgen`. The output `main.wasm` replaces its `_bg.wasm` file.
+    /// The JavaScript bindings are not split, so they keep the glue for
+    /// imports and exports used only by split modules.
+    #[arg(long, value_name = "FILE")]
+    bindgen_output: Option<Box<Path>>,
+}
+
+/// Parses `--target`, reading the template of a custom target.
+fn parse_target(value: &str) -> Result<LoaderTarget, String> {
+    match value {
+        "web" => Ok(LoaderTarget::Web),
+        "node" => Ok(LoaderTarget::Node),
+        "worker" => Ok(LoaderTarget::Worker),
+        "no-modules" => Ok(LoaderTarget::NoModules),
+        _ => match value.strip_prefix("custom=") {
+            Some(path) => std::fs::read_to_string(path)
+                .map(LoaderTarget::Custom)
+                .map_err(|error| format!("failed to read {path}: {error}")),
+            None => Err("expected web, node, worker, no-modules or custom=<TEMPLATE>".to_string()),
         },
-    )?;
-
-    let mut javascript = String::new();
-    javascript.push_str(
-        r#"import { initSync } from "./main.js";
-function makeLoad(url, deps) {
-  let alreadyLoaded = false;
-  return async(callbackIndex, callbackData) => {
-    if (alreadyLoaded) return;
-    for (let dep of deps) {
-      await dep();
     }
-    let mainExports = undefined;
-      try {
-        const response = await fetch(url);
-        mainExports = initSync(undefined, undefined);
-        const imports = {
-          env: {
-            memory: mainExports.memory,
-          },
-          __wasm_split: {
-            __indirect_function_table: mainExports.__indirect_function_table,
-            __stack_pointer: mainExports.__stack_pointer,
-            __tls_base: mainExports.__tls_base,
-            memory: mainExports.memory,
-          },
-        };
-        const module = await WebAssembly.instantiateStreaming(response, imports);
-        alreadyLoaded = true;
-        if (callbackIndex === undefined) return;
-        mainExports.__indirect_function_table.get(callbackIndex)(
-          callbackData,
-          true,
-        );
-      } catch (e) {
-        if (callbackIndex === undefined) throw e;
-        console.error("Failed to load " + url.href, e);
-        if (mainExports === undefined) {
-          mainExports = initSync(undefined, undefined);
-        }
-        mainExports.__indirect_function_table.get(callbackIndex)(
-          callbackData,
-          false,
-        );
-      }
-  };
 }
-"#,
-    );
-    let mut split_deps = HashMap::<String, Vec<String>>::new();
-    for (name, _) in split_program_info.output_modules.iter() {
-        let SplitModuleIdentifier::Chunk(splits) = name else {
-            continue;
-        };
-        for split in splits {
-            split_deps
-                .entry(split.clone())
-                .or_default()
-                .push(name.name());
+
+#[derive(Debug, Args)]
+struct WhyArgs {
+    /// Input .wasm file.
+    input: Box<Path>,
+
+    /// Regular expression matching the names of the symbols to explain.
+    symbol: String,
+
+    /// Maximum number of paths to print for each symbol, shortest first. Use
+    /// 0 to print every path, which may take a long time.
+    #[arg(long, default_value_t = 5)]
+    max_paths: usize,
+}
+
+#[derive(Debug, Args)]
+struct ProfileMergeArgs {
+    /// Symbol map written by `instrument` (`profile_symbols.txt`).
+    #[arg(long)]
+    symbols: Box<Path>,
+
+    /// Output profile.
+    #[arg(short, long)]
+    output: Box<Path>,
+
+    /// Bitmap files, each containing the bytes returned by `profileBitmap()`
+    /// in one session.
+    #[arg(required = true)]
+    bitmaps: Vec<Box<Path>>,
+}
+
+fn split(args: SplitArgs, instrument: bool) -> Result<()> {
+    let (Some(input), Some(output_dir)) = (args.input, args.output) else {
+        unreachable!("required by clap");
+    };
+    let input_wasm = std::fs::read(input)?;
+    let config = match &args.config {
+        Some(path) => {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            wasm_split_lib::SplitConfig::parse(&contents)
+                .with_context(|| format!("Invalid config {}", path.display()))?
         }
-        javascript.push_str(format!(
-            "const __wasm_split_load_{name} = makeLoad(new URL(\"./{name}.wasm\", import.meta.url), []);\n",
-            name = name.name(),
-        ).as_str())
+        None => Default::default(),
+    };
+    let profile = match &args.profile {
+        Some(path) => Some(wasm_split_lib::Profile::parse(
+            &std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?,
+        )),
+        None => None,
+    };
+    let splitter = wasm_split_lib::Splitter::new()
+        .verbose(args.verbose)
+        .report(args.report.is_some())
+        .graph(args.emit_graph)
+        .graph_level(args.graph_level)
+        .min_chunk_size(args.min_chunk_size)
+        .config(config)
+        .profile(profile)
+        .instrument(instrument)
+        .hash_file_names(args.hash_file_names)
+        .target(args.target)
+        .retry_attempts(args.retry_attempts)
+        .retry_backoff_ms(args.retry_backoff_ms);
+    let output = match &args.bindgen_output {
+        Some(path) => splitter.split_bindgen_output(
+            &input_wasm,
+            &std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?,
+        )?,
+        None => splitter.split(&input_wasm)?,
+    };
+    if let Some(verbose_info) = &output.verbose_info {
+        print!("{verbose_info}");
+    }
+    for name in output.orphan_split_exports.iter() {
+        eprintln!("warning: No corresponding import for split export {name:?}, omitting it");
+    }
+    output.write_to_dir(&output_dir)?;
+    if let (Some(path), Some(report)) = (&args.report, &output.report) {
+        std::fs::write(path, report.to_json())?;
     }
-    for (identifier, _) in split_program_info.output_modules.iter().rev() {
-        if matches!(identifier, SplitModuleIdentifier::Chunk(_)) {
-            continue;
+    Ok(())
+}
+
+fn why(args: WhyArgs) -> Result<()> {
+    let input_wasm = std::fs::read(&args.input)?;
+    let max_paths = match args.max_paths {
+        0 => usize::MAX,
+        n => n,
+    };
+    let results = wasm_split_lib::why(&input_wasm, &args.symbol, max_paths)?;
+    if results.is_empty() {
+        println!("No symbols match {:?}", args.symbol);
+    }
+    for result in results {
+        println!("{}", result.symbol);
+        if result.paths.is_empty() {
+            println!("  unreachable");
+        }
+        for path in result.paths {
+            println!("  from {}:", path.root);
+            for symbol in path.symbols {
+                println!("    {symbol}");
+            }
         }
-        let name = identifier.name();
-        javascript.push_str(format!(
-            "export const __wasm_split_load_{name} = makeLoad(new URL(\"./{name}.wasm\", import.meta.url), [{deps}]);\n",
-            name = name,
-            deps = split_deps
-            .remove(&name)
-            .unwrap_or_default()
-            .iter()
-            .map(|x| format!("__wasm_split_load_{x}"))
-            .collect::<Vec<_>>()
-            .join(", "),
-        ).as_str())
     }
+    Ok(())
+}
 
-    std::fs::write(args.output.join("__wasm_split.js"), javascript)?;
+fn profile_merge(args: ProfileMergeArgs) -> Result<()> {
+    let symbol_map = std::fs::read_to_string(&args.symbols)
+        .with_context(|| format!("Failed to read {}", args.symbols.display()))?;
+    let bitmaps = args
+        .bitmaps
+        .iter()
+        .map(|path| {
+            std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let profile =
+        wasm_split_lib::Profile::from_bitmaps(&symbol_map, bitmaps.iter().map(Vec::as_slice))?;
+    std::fs::write(&args.output, profile.to_string())?;
     Ok(())
 }
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Command::Why(args)) => why(args),
+        Some(Command::Instrument(args)) => split(args, true),
+        Some(Command::ProfileMerge(args)) => profile_merge(args),
+        None => split(cli.split, false),
+    }
+}
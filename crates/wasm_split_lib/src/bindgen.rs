@@ -0,0 +1,662 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use anyhow::{anyhow, bail, Context, Result};
+use wasm_encoder::Encode;
+use wasmparser::{
+    BinaryReader, DefinedDataSymbol, Operator, Payload, RelocAddendKind, RelocationEntry,
+    RelocationType, SymbolInfo,
+};
+
+use crate::{
+    emit::{get_active_segment_offset, is_indirect_function_reloc},
+    read::{DataSegmentId, InputFuncId, InputModule},
+};
+
+/// Returns a relocatable module equivalent to `bindgen_output`, the result of
+/// running `wasm-bindgen` on `original`, so that it can be split in place of
+/// `original`.
+///
+/// `wasm-bindgen` removes the relocations, so they are derived from the
+/// operators of `bindgen_output` where possible, and otherwise copied from
+/// the function of `original` with the same name. Functions added by
+/// `wasm-bindgen` are assumed to only refer to memory through the functions
+/// they call. Each copied relocation is checked against the value in
+/// `bindgen_output`. Relocations cannot be copied from a function whose name
+/// is not unique in both modules, which is an error unless it has none.
+pub(crate) fn relocate_bindgen_output(original: &[u8], bindgen_output: &[u8]) -> Result<Vec<u8>> {
+    let original = InputModule::parse(original).context("Failed to parse original module")?;
+    let output =
+        InputModule::parse(bindgen_output).context("Failed to parse wasm-bindgen output")?;
+    if original.symbols.is_empty() {
+        bail!("The original module has no symbol table; link it with --emit-relocs");
+    }
+    if output.names.functions.is_empty() {
+        bail!("The wasm-bindgen output has no function names");
+    }
+    BindgenRelocator::new(&original, &output)?.relocate()
+}
+
+struct BindgenRelocator<'a> {
+    original: &'a InputModule<'a>,
+    output: &'a InputModule<'a>,
+    // Functions of `output` by function of `original`, for names that are
+    // unique in both and name either two imports or two defined functions.
+    output_funcs: HashMap<InputFuncId, InputFuncId>,
+    // Symbols of the generated symbol table by symbol of `original`. The
+    // generated table has a symbol for each function of `output`, with the
+    // same index, followed by the data symbols of `original`.
+    symbols: HashMap<usize, usize>,
+    // Functions of `output` by indirect function table index.
+    table_entries: HashMap<u64, InputFuncId>,
+}
+
+/// Relocation of `output`, relative to the start of a section.
+struct OutputRelocation {
+    ty: RelocationType,
+    offset: usize,
+    symbol: usize,
+    addend: i64,
+}
+
+fn unique_function_names<'a>(module: &InputModule<'a>) -> HashMap<&'a str, Option<InputFuncId>> {
+    let mut names = HashMap::new();
+    for (&func_id, &name) in module.names.functions.iter() {
+        names
+            .entry(name)
+            .and_modify(|func| *func = None)
+            .or_insert(Some(func_id));
+    }
+    names
+}
+
+/// Whether a relocation of `original` is copied to `output`. Function indices
+/// are instead derived from the operators of `output`, and the remaining
+/// types only refer to entities that are never moved out of main.
+fn is_copied_reloc(ty: RelocationType) -> bool {
+    use RelocationType::*;
+    is_indirect_function_reloc(ty)
+        || matches!(
+            ty,
+            MemoryAddrLeb
+                | MemoryAddrSleb
+                | MemoryAddrI32
+                | MemoryAddrRelSleb
+                | MemoryAddrTlsSleb
+                | MemoryAddrLocrelI32
+                | MemoryAddrLeb64
+                | MemoryAddrSleb64
+                | MemoryAddrI64
+                | MemoryAddrRelSleb64
+                | MemoryAddrTlsSleb64
+        )
+}
+
+/// Returns the operators of a function body with their offsets, followed by
+/// the end offset of the body.
+fn read_operators<'a>(
+    body: &wasmparser::FunctionBody<'a>,
+) -> Result<(Vec<(Operator<'a>, usize)>, usize)> {
+    let mut reader = body.get_operators_reader()?;
+    let mut operators = Vec::new();
+    while !reader.eof() {
+        operators.push(reader.read_with_offset()?);
+    }
+    Ok((operators, body.range().end))
+}
+
+/// Returns the range of the LEB128 immediate at the end of the operator
+/// occupying `range` of `data`. This is the index of `call` and `ref.func`,
+/// the value of `i32.const` and `i64.const`, and the offset of loads and
+/// stores, which are the operands that relocations refer to.
+fn trailing_leb_range(data: &[u8], range: Range<usize>) -> Range<usize> {
+    let mut start = range.end - 1;
+    while start > range.start + 1 && data[start - 1] & 0x80 != 0 {
+        start -= 1;
+    }
+    start..range.end
+}
+
+fn read_leb(data: &[u8], signed: bool) -> Result<i64> {
+    let mut reader = BinaryReader::new(data);
+    Ok(if signed {
+        reader.read_var_i64()?
+    } else {
+        reader.read_var_u64()? as i64
+    })
+}
+
+/// Encodes `value` as a LEB128 of exactly `width` bytes, as the linker does
+/// for relocated operands.
+fn encode_padded_leb(mut value: i64, width: usize, output: &mut Vec<u8>) {
+    for i in 0..width {
+        let continuation = if i + 1 < width { 0x80 } else { 0 };
+        output.push((value as u8) & 0x7f | continuation);
+        value >>= 7;
+    }
+}
+
+fn read_fixed(data: &[u8]) -> u64 {
+    let mut bytes = [0; 8];
+    bytes[..data.len()].copy_from_slice(data);
+    u64::from_le_bytes(bytes)
+}
+
+fn encode_relocations(section_index: usize, relocations: &[OutputRelocation]) -> Vec<u8> {
+    let mut data = Vec::new();
+    (section_index as u32).encode(&mut data);
+    (relocations.len() as u32).encode(&mut data);
+    for relocation in relocations {
+        data.push(relocation.ty as u8);
+        (relocation.offset as u32).encode(&mut data);
+        (relocation.symbol as u32).encode(&mut data);
+        match relocation.ty.addend_kind() {
+            RelocAddendKind::None => {}
+            RelocAddendKind::Addend32 => (relocation.addend as i32).encode(&mut data),
+            RelocAddendKind::Addend64 => relocation.addend.encode(&mut data),
+        }
+    }
+    data
+}
+
+impl<'a> BindgenRelocator<'a> {
+    fn new(original: &'a InputModule<'a>, output: &'a InputModule<'a>) -> Result<Self> {
+        let output_names = unique_function_names(output);
+        let output_funcs: HashMap<_, _> = unique_function_names(original)
+            .into_iter()
+            .filter_map(|(name, func_id)| Some((func_id?, (*output_names.get(name)?)?)))
+            .filter(|&(original_func, output_func)| {
+                (original_func < original.imported_funcs.len())
+                    == (output_func < output.imported_funcs.len())
+            })
+            .collect();
+
+        let mut symbols = HashMap::new();
+        let mut num_data_symbols = 0;
+        let num_output_funcs = output.imported_funcs.len() + output.defined_funcs.len();
+        for (symbol_index, symbol) in original.symbols.iter().enumerate() {
+            match symbol {
+                SymbolInfo::Func { index, .. } => {
+                    if let Some(&func_id) = output_funcs.get(&(*index as InputFuncId)) {
+                        symbols.insert(symbol_index, func_id);
+                    }
+                }
+                SymbolInfo::Data { .. } => {
+                    symbols.insert(symbol_index, num_output_funcs + num_data_symbols);
+                    num_data_symbols += 1;
+                }
+                _ => {}
+            }
+        }
+
+        let mut table_entries = HashMap::new();
+        for element in output.elements.iter() {
+            let wasmparser::ElementKind::Active {
+                table_index: None | Some(0),
+                offset_expr,
+            } = &element.kind
+            else {
+                continue;
+            };
+            let wasmparser::Operator::I32Const { value: offset } =
+                offset_expr.get_operators_reader().read()?
+            else {
+                continue;
+            };
+            let funcs: Vec<u32> = match &element.items {
+                wasmparser::ElementItems::Functions(funcs) => {
+                    funcs.clone().into_iter().collect::<Result<_, _>>()?
+                }
+                wasmparser::ElementItems::Expressions(_, exprs) => exprs
+                    .clone()
+                    .into_iter()
+                    .filter_map(
+                        |expr| match expr.map(|expr| expr.get_operators_reader().read()) {
+                            Ok(Ok(Operator::RefFunc { function_index })) => {
+                                Some(Ok(function_index))
+                            }
+                            Ok(Ok(_)) => None,
+                            Ok(Err(e)) | Err(e) => Some(Err(e)),
+                        },
+                    )
+                    .collect::<Result<_, _>>()?,
+            };
+            for (i, func) in funcs.into_iter().enumerate() {
+                table_entries.insert(offset as u64 + i as u64, func as InputFuncId);
+            }
+        }
+
+        Ok(Self {
+            original,
+            output,
+            output_funcs,
+            symbols,
+            table_entries,
+        })
+    }
+
+    fn function_name(module: &InputModule, func_id: InputFuncId) -> String {
+        module
+            .names
+            .functions
+            .get(&func_id)
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| format!("func[{func_id}]"))
+    }
+
+    /// Returns the symbol of the generated symbol table corresponding to the
+    /// target of `relocation`, a relocation of `original`.
+    fn map_symbol(&self, relocation: &RelocationEntry) -> Result<usize> {
+        self.symbols
+            .get(&(relocation.index as usize))
+            .copied()
+            .ok_or_else(|| {
+                let symbol = &self.original.symbols[relocation.index as usize];
+                match symbol {
+                    SymbolInfo::Func { index, .. } => anyhow!(
+                        "Relocation {relocation:?} refers to {}, which is not in the wasm-bindgen output",
+                        Self::function_name(self.original, *index as InputFuncId)
+                    ),
+                    _ => anyhow!("Relocation {relocation:?} refers to unsupported symbol {symbol:?}"),
+                }
+            })
+    }
+
+    /// Checks that the value of a table index relocation of `output` refers to
+    /// the function of `symbol`.
+    fn check_table_index(&self, value: u64, symbol: usize) -> Result<()> {
+        if self.table_entries.get(&value) != Some(&symbol) {
+            bail!(
+                "Table index {value} in the wasm-bindgen output does not refer to {}",
+                Self::function_name(self.output, symbol)
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns the segment and offset within it of the data at `offset`
+    /// within `segment` of `original`.
+    fn map_data_location(
+        &self,
+        segment: DataSegmentId,
+        offset: usize,
+        size: usize,
+    ) -> Result<(DataSegmentId, usize)> {
+        let original_segment = &self.original.data_segments[segment];
+        let Some((memory, base, _)) = get_active_segment_offset(original_segment)? else {
+            // Passive segments are only identified by their index.
+            let output_segment = self.output.data_segments.get(segment);
+            if output_segment.map(|segment| segment.data) != Some(original_segment.data) {
+                bail!("Data segment {segment} differs in the wasm-bindgen output");
+            }
+            return Ok((segment, offset));
+        };
+        let address = base + offset as i64;
+        for (output_index, output_segment) in self.output.data_segments.iter().enumerate() {
+            let Some((output_memory, output_base, _)) = get_active_segment_offset(output_segment)?
+            else {
+                continue;
+            };
+            if output_memory == memory
+                && output_base <= address
+                && address + size as i64 <= output_base + output_segment.data.len() as i64
+            {
+                return Ok((output_index, (address - output_base) as usize));
+            }
+        }
+        bail!("No data at address {address} in the wasm-bindgen output")
+    }
+
+    fn relocate(&self) -> Result<Vec<u8>> {
+        let (code, code_relocations) = self.relocate_code()?;
+        let data_relocations = self.relocate_data()?;
+        let linking = self.linking_section()?;
+
+        let mut module = wasm_encoder::Module::new();
+        for payload in wasmparser::Parser::new(0).parse_all(self.output.raw) {
+            let payload = payload?;
+            if let Payload::CodeSectionStart { .. } = payload {
+                module.section(&wasm_encoder::RawSection {
+                    id: wasm_encoder::SectionId::Code as u8,
+                    data: &code,
+                });
+                continue;
+            }
+            if let Some((id, range)) = payload.as_section() {
+                module.section(&wasm_encoder::RawSection {
+                    id,
+                    data: &self.output.raw[range],
+                });
+            }
+        }
+        // Appending the custom sections preserves the indices of the other
+        // sections.
+        module.section(&linking);
+        module.section(&wasm_encoder::CustomSection {
+            name: "reloc.CODE".into(),
+            data: encode_relocations(self.output.code_section_index, &code_relocations).into(),
+        });
+        if !data_relocations.is_empty() {
+            module.section(&wasm_encoder::CustomSection {
+                name: "reloc.DATA".into(),
+                data: encode_relocations(self.output.data_section_index, &data_relocations).into(),
+            });
+        }
+        Ok(module.finish())
+    }
+
+    fn linking_section(&self) -> Result<wasm_encoder::LinkingSection> {
+        let mut symbol_table = wasm_encoder::SymbolTable::new();
+        for func_id in 0..self.output.imported_funcs.len() {
+            symbol_table.function(
+                wasm_encoder::SymbolTable::WASM_SYM_UNDEFINED,
+                func_id as u32,
+                None,
+            );
+        }
+        for defined_index in 0..self.output.defined_funcs.len() {
+            let func_id = self.output.imported_funcs.len() + defined_index;
+            symbol_table.function(
+                0,
+                func_id as u32,
+                Some(&Self::function_name(self.output, func_id)),
+            );
+        }
+        for symbol in self.original.symbols.iter() {
+            let SymbolInfo::Data {
+                flags,
+                name,
+                symbol,
+            } = symbol
+            else {
+                continue;
+            };
+            let (flags, definition) = match symbol {
+                Some(DefinedDataSymbol {
+                    index,
+                    offset,
+                    size,
+                }) if *size > 0 => {
+                    let (index, offset) = self
+                        .map_data_location(*index as usize, *offset as usize, *size as usize)
+                        .with_context(|| format!("Failed to map data symbol {name}"))?;
+                    let definition = wasm_encoder::DataSymbolDefinition {
+                        index: index as u32,
+                        offset: offset as u32,
+                        size: *size,
+                    };
+                    (flags.bits(), Some(definition))
+                }
+                // Zero-size symbols, such as `__heap_base`, may be outside of
+                // any data segment, and cannot be the target of a relocation.
+                _ => (
+                    flags.bits() | wasm_encoder::SymbolTable::WASM_SYM_UNDEFINED,
+                    None,
+                ),
+            };
+            symbol_table.data(flags, name, definition);
+        }
+        let mut linking = wasm_encoder::LinkingSection::new();
+        linking.symbol_table(&symbol_table);
+        Ok(linking)
+    }
+
+    /// Returns the code section of `output` with padded relocated operands,
+    /// and the relocations.
+    fn relocate_code(&self) -> Result<(Vec<u8>, Vec<OutputRelocation>)> {
+        let mut original_funcs = HashMap::new();
+        for (&original_func, &output_func) in self.output_funcs.iter() {
+            original_funcs.insert(output_func, original_func);
+        }
+        let mut original_relocations: Vec<RelocationEntry> = self
+            .original
+            .relocs
+            .get(&self.original.code_section_index)
+            .cloned()
+            .unwrap_or_default();
+        original_relocations.retain(|relocation| is_copied_reloc(relocation.ty));
+        original_relocations.sort_by_key(|relocation| relocation.offset);
+
+        self.check_unmatched_functions(&original_relocations)?;
+
+        let mut code = Vec::new();
+        let mut relocations = Vec::new();
+        (self.output.defined_funcs.len() as u32).encode(&mut code);
+        for (defined_index, func) in self.output.defined_funcs.iter().enumerate() {
+            let func_id = self.output.imported_funcs.len() + defined_index;
+            let copied = match original_funcs.get(&func_id) {
+                Some(&original_func) => {
+                    self.copied_relocations(original_func, func_id, &original_relocations)?
+                }
+                None => HashMap::new(),
+            };
+            let (body, body_relocations) =
+                self.relocate_body(func, &copied).with_context(|| {
+                    format!(
+                        "Failed to relocate {}",
+                        Self::function_name(self.output, func_id)
+                    )
+                })?;
+            (body.len() as u32).encode(&mut code);
+            relocations.extend(body_relocations.into_iter().map(|mut relocation| {
+                relocation.offset += code.len();
+                relocation
+            }));
+            code.extend_from_slice(&body);
+        }
+        Ok((code, relocations))
+    }
+
+    /// Returns the relocations within the body of `original_func`, given the
+    /// copied relocations of the code section ordered by offset.
+    fn function_relocations<'r>(
+        &self,
+        original_func: InputFuncId,
+        original_relocations: &'r [RelocationEntry],
+    ) -> &'r [RelocationEntry] {
+        let body =
+            &self.original.defined_funcs[original_func - self.original.imported_funcs.len()].body;
+        let code_offset = self.original.code_section_offset;
+        let range = body.range();
+        let start = original_relocations
+            .partition_point(|relocation| code_offset + (relocation.offset as usize) < range.start);
+        let end = original_relocations
+            .partition_point(|relocation| code_offset + (relocation.offset as usize) < range.end);
+        &original_relocations[start..end]
+    }
+
+    /// Fails if a function of `original` that is still in `output` has
+    /// relocations that would be lost because its name is not unique, which
+    /// would leave the data and functions it refers to untracked.
+    fn check_unmatched_functions(&self, original_relocations: &[RelocationEntry]) -> Result<()> {
+        let output_names = unique_function_names(self.output);
+        let num_imported_funcs = self.original.imported_funcs.len();
+        for original_func in
+            num_imported_funcs..num_imported_funcs + self.original.defined_funcs.len()
+        {
+            if self.output_funcs.contains_key(&original_func) {
+                continue;
+            }
+            let Some(name) = self.original.names.functions.get(&original_func) else {
+                continue;
+            };
+            if output_names.contains_key(name)
+                && !self
+                    .function_relocations(original_func, original_relocations)
+                    .is_empty()
+            {
+                bail!(
+                    "{name} names more than one function in the original module or the \
+                     wasm-bindgen output, so its relocations cannot be mapped"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the relocations of `original_func` that are copied to
+    /// `output_func`, by operator index.
+    fn copied_relocations(
+        &self,
+        original_func: InputFuncId,
+        output_func: InputFuncId,
+        original_relocations: &[RelocationEntry],
+    ) -> Result<HashMap<usize, RelocationEntry>> {
+        let function_relocations = self.function_relocations(original_func, original_relocations);
+        if function_relocations.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let body =
+            &self.original.defined_funcs[original_func - self.original.imported_funcs.len()].body;
+        let code_offset = self.original.code_section_offset;
+
+        let (operators, body_end) = read_operators(body)?;
+        let output_body =
+            &self.output.defined_funcs[output_func - self.output.imported_funcs.len()].body;
+        let (output_operators, _) = read_operators(output_body)?;
+        if operators.len() != output_operators.len()
+            || operators
+                .iter()
+                .zip(output_operators.iter())
+                .any(|((a, _), (b, _))| std::mem::discriminant(a) != std::mem::discriminant(b))
+        {
+            bail!(
+                "wasm-bindgen modified {}, so its relocations cannot be mapped",
+                Self::function_name(self.original, original_func)
+            );
+        }
+
+        let mut copied = HashMap::new();
+        for relocation in function_relocations.iter() {
+            let relocation_range = relocation.relocation_range();
+            let relocation_start = code_offset + relocation_range.start;
+            let index = operators.partition_point(|(_, offset)| *offset <= relocation_start) - 1;
+            let operator_end = operators
+                .get(index + 1)
+                .map_or(body_end, |(_, offset)| *offset);
+            let operand = trailing_leb_range(self.original.raw, operators[index].1..operator_end);
+            if operand != (relocation_start..code_offset + relocation_range.end) {
+                bail!("Unsupported relocation {relocation:?}");
+            }
+            copied.insert(index, *relocation);
+        }
+        Ok(copied)
+    }
+
+    fn relocate_body(
+        &self,
+        func: &crate::read::DefinedFunc,
+        copied: &HashMap<usize, RelocationEntry>,
+    ) -> Result<(Vec<u8>, Vec<OutputRelocation>)> {
+        let raw = self.output.raw;
+        let (operators, body_end) = read_operators(&func.body)?;
+        let body_start = func.body.range().start;
+        let mut body = Vec::new();
+        let mut relocations = Vec::new();
+        let mut position = body_start;
+        for (index, (operator, offset)) in operators.iter().enumerate() {
+            let operator_end = operators
+                .get(index + 1)
+                .map_or(body_end, |(_, offset)| *offset);
+            let (ty, symbol, addend) = match *operator {
+                Operator::Call { function_index }
+                | Operator::ReturnCall { function_index }
+                | Operator::RefFunc { function_index } => {
+                    (RelocationType::FunctionIndexLeb, function_index as usize, 0)
+                }
+                _ => match copied.get(&index) {
+                    Some(relocation) => (
+                        relocation.ty,
+                        self.map_symbol(relocation)?,
+                        relocation.addend,
+                    ),
+                    None => continue,
+                },
+            };
+            let operand = trailing_leb_range(raw, *offset..operator_end);
+            let signed = matches!(
+                operator,
+                Operator::I32Const { .. } | Operator::I64Const { .. }
+            );
+            let value = read_leb(&raw[operand.clone()], signed)?;
+            if let Some(relocation) = copied.get(&index) {
+                if is_indirect_function_reloc(ty) {
+                    self.check_table_index(value as u64, symbol)?;
+                } else {
+                    let original_range = relocation.relocation_range();
+                    let original_offset = self.original.code_section_offset;
+                    let original_value = read_leb(
+                        &self.original.raw[original_offset + original_range.start
+                            ..original_offset + original_range.end],
+                        signed,
+                    )?;
+                    if value != original_value {
+                        bail!(
+                            "Address {value} in the wasm-bindgen output does not match \
+                             {original_value} for relocation {relocation:?}"
+                        );
+                    }
+                }
+            }
+            body.extend_from_slice(&raw[position..operand.start]);
+            relocations.push(OutputRelocation {
+                ty,
+                offset: body.len(),
+                symbol,
+                addend,
+            });
+            encode_padded_leb(value, ty.extent(), &mut body);
+            position = operand.end;
+        }
+        body.extend_from_slice(&raw[position..body_end]);
+        Ok((body, relocations))
+    }
+
+    /// Returns the relocations of the data section of `output`.
+    fn relocate_data(&self) -> Result<Vec<OutputRelocation>> {
+        let Some(original_relocations) =
+            self.original.relocs.get(&self.original.data_section_index)
+        else {
+            return Ok(Vec::new());
+        };
+        let data_start = |segment: &wasmparser::Data| segment.range.end - segment.data.len();
+        let mut relocations = Vec::new();
+        for relocation in original_relocations.iter() {
+            if !is_copied_reloc(relocation.ty) {
+                continue;
+            }
+            let range = relocation.relocation_range();
+            let start = self.original.data_section_offset + range.start;
+            let segment = self
+                .original
+                .data_segments
+                .partition_point(|segment| segment.range.end <= start);
+            let original_segment = self
+                .original
+                .data_segments
+                .get(segment)
+                .filter(|segment| data_start(segment) <= start)
+                .ok_or_else(|| anyhow!("Invalid relocation {relocation:?}"))?;
+            let (output_segment, offset) =
+                self.map_data_location(segment, start - data_start(original_segment), range.len())?;
+            let output_start = data_start(&self.output.data_segments[output_segment]) + offset;
+            let value = read_fixed(&self.output.raw[output_start..output_start + range.len()]);
+            let symbol = self.map_symbol(relocation)?;
+            if is_indirect_function_reloc(relocation.ty) {
+                self.check_table_index(value, symbol)?;
+            } else if value != read_fixed(&self.original.raw[start..start + range.len()]) {
+                bail!(
+                    "Address {value} in the wasm-bindgen output does not match relocation \
+                     {relocation:?}"
+                );
+            }
+            relocations.push(OutputRelocation {
+                ty: relocation.ty,
+                offset: output_start - self.output.data_section_offset,
+                symbol,
+                addend: relocation.addend,
+            });
+        }
+        Ok(relocations)
+    }
+}
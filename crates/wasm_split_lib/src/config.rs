@@ -0,0 +1,93 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+
+/// Manual overrides for the placement of symbols in output modules.
+///
+/// Symbols are matched by name (as shown in the split report) using regular
+/// expressions. The TOML format is:
+///
+/// ```toml
+/// # Symbols that are placed in the main module, along with everything they
+/// # depend on.
+/// force_main = ["^_ZN4core3fmt"]
+///
+/// # Symbols that are kept in the main module. Their dependencies are placed
+/// # as usual.
+/// exclude = ["panic"]
+///
+/// # Symbols that are placed in a specific output module: "main", a split
+/// # module, or a chunk (e.g. "a_b" for the chunk shared by "a" and "b").
+/// [[pin]]
+/// symbols = "decompress"
+/// module = "brotli_gzip"
+/// ```
+///
+/// Overrides that cannot be satisfied, e.g. pinning a symbol used by the main
+/// module to a split module, or patterns that match no symbols, are errors.
+#[derive(Clone, Debug, Default)]
+pub struct SplitConfig {
+    pub force_main: Vec<Regex>,
+    pub exclude: Vec<Regex>,
+    pub pin: Vec<Pin>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Pin {
+    pub symbols: Regex,
+    /// Name of the output module.
+    pub module: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    #[serde(default)]
+    force_main: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    #[serde(default)]
+    pin: Vec<RawPin>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawPin {
+    symbols: String,
+    module: String,
+}
+
+fn parse_regex(pattern: &str) -> Result<Regex> {
+    Regex::new(pattern).with_context(|| format!("Invalid symbol pattern {pattern:?}"))
+}
+
+impl SplitConfig {
+    /// Parses a configuration in TOML format.
+    pub fn parse(toml: &str) -> Result<Self> {
+        let raw: RawConfig = toml::from_str(toml)?;
+        let parse_all = |patterns: &[String]| -> Result<Vec<Regex>> {
+            patterns
+                .iter()
+                .map(|pattern| parse_regex(pattern))
+                .collect()
+        };
+        Ok(Self {
+            force_main: parse_all(&raw.force_main)?,
+            exclude: parse_all(&raw.exclude)?,
+            pin: raw
+                .pin
+                .into_iter()
+                .map(|pin| {
+                    Ok(Pin {
+                        symbols: parse_regex(&pin.symbols)?,
+                        module: pin.module,
+                    })
+                })
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.force_main.is_empty() && self.exclude.is_empty() && self.pin.is_empty()
+    }
+}
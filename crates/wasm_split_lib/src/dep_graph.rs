@@ -5,9 +5,14 @@ use std::{
     ops::Range,
 };
 
-use anyhow::{anyhow, bail, Context};
+use anyhow::{bail, Context};
 
-use crate::read::{InputFuncId, InputModule, SymbolIndex};
+use wasmparser::{RelocationEntry, RelocationType};
+
+use crate::{
+    emit::is_indirect_function_reloc,
+    read::{InputFuncId, InputModule, SymbolIndex},
+};
 
 #[derive(Debug, PartialEq, Eq, Hash, Copy, PartialOrd, Ord, Clone)]
 pub enum DepNode {
@@ -33,10 +38,69 @@ impl<'a> SymbolTable for InputModule<'a> {
     }
 }
 
+/// Returns every function and data symbol of the module.
+pub fn all_symbols<'a>(module: &'a InputModule) -> impl Iterator<Item = DepNode> + 'a {
+    let num_funcs = module.imported_funcs.len() + module.defined_funcs.len();
+    let data_symbols = module
+        .symbols
+        .iter()
+        .enumerate()
+        .filter(|(_, symbol)| matches!(symbol, wasmparser::SymbolInfo::Data { .. }))
+        .map(|(index, _)| DepNode::DataSymbol(index));
+    (0..num_funcs).map(DepNode::Function).chain(data_symbols)
+}
+
+pub fn symbol_name(module: &InputModule, node: &DepNode) -> String {
+    match *node {
+        DepNode::Function(index) => module
+            .names
+            .functions
+            .get(&index)
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| format!("func[{index}]")),
+        DepNode::DataSymbol(index) => match module.symbols[index] {
+            wasmparser::SymbolInfo::Data { name, .. } => name.to_string(),
+            symbol => format!("{symbol:?}"),
+        },
+    }
+}
+
+pub fn symbol_size(module: &InputModule, node: &DepNode) -> usize {
+    match *node {
+        DepNode::Function(index) => index
+            .checked_sub(module.imported_funcs.len())
+            .map(|defined_index| module.defined_funcs[defined_index].body.range().len())
+            .unwrap_or_default(),
+        DepNode::DataSymbol(index) => match module.symbols[index] {
+            wasmparser::SymbolInfo::Data {
+                symbol: Some(symbol),
+                ..
+            } => symbol.size as usize,
+            _ => 0,
+        },
+    }
+}
+
 pub fn get_dependencies(module: &InputModule) -> anyhow::Result<DepGraph> {
+    get_dependencies_matching(module, |_| true)
+}
+
+/// Returns only the dependencies due to taking the address of a function,
+/// i.e. references to the indirect function table.
+pub fn get_indirect_dependencies(module: &InputModule) -> anyhow::Result<DepGraph> {
+    get_dependencies_matching(module, is_indirect_function_reloc)
+}
+
+fn get_dependencies_matching(
+    module: &InputModule,
+    filter: impl Fn(RelocationType) -> bool,
+) -> anyhow::Result<DepGraph> {
     let mut deps = DepGraph::new();
-    let mut add_dep = |a: DepNode, b: u32| {
-        if let Some(target) = module.get_symbol_dep_node(b as usize) {
+    let mut add_dep = |a: DepNode, entry: &RelocationEntry| {
+        if !filter(entry.ty) {
+            return;
+        }
+        if let Some(target) = module.get_symbol_dep_node(entry.index as usize) {
             deps.entry(a).or_default().insert(target);
         };
     };
@@ -51,7 +115,7 @@ pub fn get_dependencies(module: &InputModule) -> anyhow::Result<DepGraph> {
                 shift_range(entry.relocation_range(), module.code_section_offset),
             )
             .with_context(|| format!("Invalid relocation entry {entry:?}"))?;
-            add_dep(DepNode::Function(func_index), entry.index);
+            add_dep(DepNode::Function(func_index), entry);
         }
     }
 
@@ -62,7 +126,7 @@ pub fn get_dependencies(module: &InputModule) -> anyhow::Result<DepGraph> {
                 shift_range(entry.relocation_range(), module.data_section_offset),
             )
             .with_context(|| format!("Invalid relocation entry {entry:?}"))?;
-            add_dep(DepNode::DataSymbol(symbol_index), entry.index);
+            add_dep(DepNode::DataSymbol(symbol_index), entry);
         }
     }
     Ok(deps)
@@ -6,22 +6,27 @@ use std::{
 
 use crate::{
     dep_graph::DepNode,
-    read::{ImportId, InputFuncId, InputModule, SymbolIndex},
-    split_point::{OutputModuleInfo, SplitProgramInfo},
+    profile::Instrumentation,
+    read::{DataSegmentId, ExportId, InputFuncId, InputModule, SymbolIndex},
+    split_point::{OutputModuleInfo, SplitModuleIdentifier, SplitProgramInfo},
 };
 use anyhow::{anyhow, bail, Context, Result};
 use wasmparser::{DataKind, RelocationEntry, RelocationType, SymbolInfo};
 
-fn is_indirect_function_reloc(ty: RelocationType) -> bool {
+pub fn is_indirect_function_reloc(ty: RelocationType) -> bool {
     use RelocationType::*;
-    match ty {
-        TableIndexSleb | TableIndexI32 | TableIndexRelSleb | TableIndexSleb64 | TableIndexI64
-        | TableIndexRelSleb64 => true,
-        _ => false,
-    }
+    matches!(
+        ty,
+        TableIndexSleb
+            | TableIndexI32
+            | TableIndexRelSleb
+            | TableIndexSleb64
+            | TableIndexI64
+            | TableIndexRelSleb64
+    )
 }
 
-fn get_indirect_functions(module: &InputModule) -> Result<HashSet<InputFuncId>> {
+pub(crate) fn get_indirect_functions(module: &InputModule) -> Result<HashSet<InputFuncId>> {
     let mut funcs = HashSet::new();
 
     for relocs in [module.code_section_index, module.data_section_index]
@@ -43,18 +48,32 @@ fn get_indirect_functions(module: &InputModule) -> Result<HashSet<InputFuncId>>
 }
 
 #[derive(Debug)]
-struct EmitState {
+struct EmitState<'a> {
     indirect_functions: IndirectFunctionEmitInfo,
     // All relocations, ordered by offset, which are relative to the start of
     // the file rather than the start of the section.
     all_relocations: Vec<RelocationEntry>,
 
-    // Imports (corresponding to split points) to exclude from all modules.
-    split_point_imports: HashSet<ImportId>,
+    data_segments: DataSegmentEmitInfo,
+
+    // Functions in the `SplitModuleIdentifier::Cold` module.
+    cold_functions: HashSet<InputFuncId>,
+
+    instrumentation: Option<&'a Instrumentation>,
+
+    // Module from which main imports the cold module loader.
+    loader_module: &'a str,
+
+    // Split exports that main does not export.
+    orphan_exports: &'a [ExportId],
 }
 
-impl EmitState {
-    fn new(module: &InputModule, program_info: &SplitProgramInfo) -> Result<Self> {
+impl<'a> EmitState<'a> {
+    fn new(
+        module: &InputModule,
+        program_info: &'a SplitProgramInfo,
+        instrumentation: Option<&'a Instrumentation>,
+    ) -> Result<Self> {
         let indirect_functions = IndirectFunctionEmitInfo::new(module, program_info)?;
         let mut all_relocations = Vec::<RelocationEntry>::new();
         for (section_index, section_offset) in [
@@ -65,7 +84,7 @@ impl EmitState {
                 continue;
             };
             for reloc in section_relocs {
-                let mut reloc = reloc.clone();
+                let mut reloc = *reloc;
                 reloc.offset =
                     reloc
                         .offset
@@ -79,16 +98,30 @@ impl EmitState {
             }
         }
         all_relocations.sort_by_key(|reloc| reloc.offset);
-        let mut split_point_imports = HashSet::<InputFuncId>::new();
-        for (_, output_module) in program_info.output_modules.iter() {
-            for split_point in output_module.split_points.iter() {
-                split_point_imports.insert(split_point.import);
-            }
-        }
+        let data_segments = DataSegmentEmitInfo::new(module, program_info)?;
+        let cold_functions = program_info
+            .output_module_identifiers
+            .get(&SplitModuleIdentifier::Cold)
+            .map(|&cold_index| {
+                program_info.output_modules[cold_index]
+                    .1
+                    .included_symbols
+                    .iter()
+                    .filter_map(|symbol| match symbol {
+                        DepNode::Function(func_id) => Some(*func_id),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
         Ok(EmitState {
             indirect_functions,
             all_relocations,
-            split_point_imports,
+            data_segments,
+            cold_functions,
+            instrumentation,
+            loader_module: &program_info.loader_module,
+            orphan_exports: &program_info.orphan_exports,
         })
     }
 
@@ -96,35 +129,15 @@ impl EmitState {
         let start = self
             .all_relocations
             .binary_search_by_key(&range.start, |reloc| reloc.offset as usize)
-            .map_or_else(identity, identity);
+            .unwrap_or_else(identity);
         let end = self
             .all_relocations
             .binary_search_by_key(&range.end, |reloc| reloc.offset as usize)
-            .map_or_else(identity, identity);
+            .unwrap_or_else(identity);
         &self.all_relocations[start..end]
     }
 }
 
-fn emit_main_module(
-    module_info: &InputModule,
-    program_info: &SplitProgramInfo,
-    emit_state: &EmitState,
-) -> anyhow::Result<()> {
-    // // Currently it is not possible to clone an existing `walrus::Module`.
-    // // Therefore, we simply reparse the wasm.
-    // let mut new_module = walrus::Module::from_buffer(module_info.wasm)?;
-
-    // // Modify indirect function table.
-    // let table = new_module
-    //     .tables
-    //     .get_mut(emit_state.indirect_function_table_id);
-    // if let Some(mut max) = table.maximum {
-    //     max = max.max(emit_state.max_indirect_function_index);
-    // };
-
-    Ok(())
-}
-
 #[derive(Debug, Default)]
 struct IndirectFunctionEmitInfo {
     table_entries: Vec<InputFuncId>,
@@ -190,43 +203,183 @@ impl IndirectFunctionEmitInfo {
     }
 }
 
-fn encode_leb128_u32_5byte(mut value: u32, buf: &mut [u8; 5]) {
-    for i in 0..5 {
-        buf[i] = (value as u8) & 0x7f;
-        value >>= 7;
+/// Portion of an input data segment to include in an output module.
+#[derive(Debug, Clone)]
+struct OutputDataSegment {
+    input_segment: DataSegmentId,
+    // Range relative to the start of the WebAssembly file.
+    range: Range<usize>,
+}
+
+#[derive(Debug, Default)]
+struct DataSegmentEmitInfo {
+    // Data segments for each output module. For the main module, the first
+    // `data_segments.len()` entries correspond one-to-one with the input data
+    // segments (possibly with an empty range) in order to preserve the indices
+    // referenced by `memory.init` and `data.drop`; any additional pieces of
+    // active segments follow.
+    segments_for_output_module: Vec<Vec<OutputDataSegment>>,
+}
+
+/// Returns the memory index, constant offset, and whether the offset is 64-bit
+/// for an active data segment.
+pub fn get_active_segment_offset(segment: &wasmparser::Data) -> Result<Option<(u32, i64, bool)>> {
+    let DataKind::Active {
+        memory_index,
+        offset_expr,
+    } = &segment.kind
+    else {
+        return Ok(None);
+    };
+    let mut reader = offset_expr.get_operators_reader();
+    let (offset, memory64) = match reader.read()? {
+        wasmparser::Operator::I32Const { value } => (value as i64, false),
+        wasmparser::Operator::I64Const { value } => (value, true),
+        _ => return Ok(None),
+    };
+    if !matches!(reader.read()?, wasmparser::Operator::End) {
+        return Ok(None);
     }
-    for i in 0..4 {
-        buf[i] |= 0x80;
+    Ok(Some((*memory_index, offset, memory64)))
+}
+
+impl DataSegmentEmitInfo {
+    fn new(module: &InputModule, program_info: &SplitProgramInfo) -> Result<Self> {
+        // Determine the output module for each group of overlapping data
+        // symbols. A group is only moved out of the main module if all of its
+        // symbols are assigned to the same non-main output module.
+        let mut moved_ranges: Vec<(usize, Range<usize>)> = Vec::new();
+        let mut group: Option<(Option<usize>, Range<usize>)> = None;
+        let mut finish_group = |group: Option<(Option<usize>, Range<usize>)>| {
+            if let Some((Some(output_module_index), range)) = group {
+                if output_module_index != 0 {
+                    moved_ranges.push((output_module_index, range));
+                }
+            }
+        };
+        for data_symbol in module.data_symbols.iter() {
+            let output_module_index = program_info
+                .symbol_output_module
+                .get(&DepNode::DataSymbol(data_symbol.symbol_index))
+                .copied()
+                .unwrap_or(0);
+            match &mut group {
+                Some((group_output_module, range)) if data_symbol.range.start < range.end => {
+                    range.end = range.end.max(data_symbol.range.end);
+                    if *group_output_module != Some(output_module_index) {
+                        *group_output_module = None;
+                    }
+                }
+                _ => {
+                    finish_group(group.take());
+                    group = Some((Some(output_module_index), data_symbol.range.clone()));
+                }
+            }
+        }
+        finish_group(group);
+
+        let mut segments_for_output_module: Vec<Vec<OutputDataSegment>> =
+            vec![Vec::new(); program_info.output_modules.len()];
+        let mut extra_main_segments = Vec::new();
+        let mut moved_ranges = moved_ranges.into_iter().peekable();
+        for (input_segment, segment) in module.data_segments.iter().enumerate() {
+            // Note: `segment.range` includes the segment header.
+            let data_range = (segment.range.end - segment.data.len())..segment.range.end;
+            let mut segment_moved_ranges = Vec::new();
+            while let Some((_, range)) = moved_ranges.peek() {
+                if range.start >= data_range.end {
+                    break;
+                }
+                segment_moved_ranges.push(moved_ranges.next().unwrap());
+            }
+            if segment_moved_ranges.is_empty() || get_active_segment_offset(segment)?.is_none() {
+                // Passive segments, and active segments with a non-constant
+                // offset, are always left in the main module.
+                segments_for_output_module[0].push(OutputDataSegment {
+                    input_segment,
+                    range: data_range,
+                });
+                continue;
+            }
+            let mut main_ranges = Vec::new();
+            let mut start = data_range.start;
+            for (output_module_index, range) in segment_moved_ranges {
+                if range.start > start {
+                    main_ranges.push(start..range.start);
+                }
+                start = range.end;
+                segments_for_output_module[output_module_index].push(OutputDataSegment {
+                    input_segment,
+                    range,
+                });
+            }
+            if data_range.end > start {
+                main_ranges.push(start..data_range.end);
+            }
+            let mut main_ranges = main_ranges.into_iter();
+            segments_for_output_module[0].push(OutputDataSegment {
+                input_segment,
+                range: main_ranges
+                    .next()
+                    .unwrap_or(data_range.start..data_range.start),
+            });
+            extra_main_segments.extend(main_ranges.map(|range| OutputDataSegment {
+                input_segment,
+                range,
+            }));
+        }
+        segments_for_output_module[0].extend(extra_main_segments);
+
+        // Merge adjacent pieces emitted to split modules.
+        for segments in segments_for_output_module.iter_mut().skip(1) {
+            let mut merged: Vec<OutputDataSegment> = Vec::new();
+            for segment in segments.drain(..) {
+                match merged.last_mut() {
+                    Some(last)
+                        if last.input_segment == segment.input_segment
+                            && last.range.end == segment.range.start =>
+                    {
+                        last.range.end = segment.range.end;
+                    }
+                    _ => merged.push(segment),
+                }
+            }
+            *segments = merged;
+        }
+
+        Ok(Self {
+            segments_for_output_module,
+        })
     }
 }
 
-fn encode_leb128_i32_5byte(mut value: i32, buf: &mut [u8; 5]) {
-    for i in 0..5 {
-        buf[i] = (value as u8) & 0x7f;
+fn encode_leb128_u32_5byte(mut value: u32, buf: &mut [u8; 5]) {
+    for byte in buf.iter_mut() {
+        *byte = (value as u8) & 0x7f;
         value >>= 7;
     }
-    for i in 0..4 {
-        buf[i] |= 0x80;
+    for byte in buf[..4].iter_mut() {
+        *byte |= 0x80;
     }
 }
 
-fn encode_leb128_u64_10byte(mut value: u64, buf: &mut [u8; 10]) {
-    for i in 0..10 {
-        buf[i] = (value as u8) & 0x7f;
+fn encode_leb128_i32_5byte(mut value: i32, buf: &mut [u8; 5]) {
+    for byte in buf.iter_mut() {
+        *byte = (value as u8) & 0x7f;
         value >>= 7;
     }
-    for i in 0..9 {
-        buf[i] |= 0x80;
+    for byte in buf[..4].iter_mut() {
+        *byte |= 0x80;
     }
 }
 
 fn encode_leb128_i64_10byte(mut value: i64, buf: &mut [u8; 10]) {
-    for i in 0..10 {
-        buf[i] = (value as u8) & 0x7f;
+    for byte in buf.iter_mut() {
+        *byte = (value as u8) & 0x7f;
         value >>= 7;
     }
-    for i in 0..9 {
-        buf[i] |= 0x80;
+    for byte in buf[..9].iter_mut() {
+        *byte |= 0x80;
     }
 }
 
@@ -238,19 +391,36 @@ fn encode_u64(value: u64, buf: &mut [u8; 8]) {
     *buf = value.to_le_bytes();
 }
 
-fn encode_i32(value: i32, buf: &mut [u8; 4]) {
-    *buf = value.to_le_bytes();
-}
-
-fn encode_i64(value: i64, buf: &mut [u8; 8]) {
-    *buf = value.to_le_bytes();
-}
-
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Copy)]
 enum OutputFunctionKind {
     Import,
+    // Import of the function that synchronously loads the cold module, in the
+    // main module only.
+    ColdLoaderImport,
     Defined,
     IndirectStub,
+    // Accessor for the call counters added by instrumentation, in the main
+    // module only.
+    ProfileCountersAccessor,
+}
+
+impl OutputFunctionKind {
+    /// Whether the function is added to the output, rather than corresponding
+    /// to an input function.
+    fn is_added(self) -> bool {
+        matches!(self, Self::ColdLoaderImport | Self::ProfileCountersAccessor)
+    }
+
+    fn added_function_type(self) -> Option<wasm_encoder::FuncType> {
+        match self {
+            Self::ColdLoaderImport => Some(wasm_encoder::FuncType::new([], [])),
+            Self::ProfileCountersAccessor => Some(wasm_encoder::FuncType::new(
+                [],
+                [wasm_encoder::ValType::I32],
+            )),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -263,8 +433,7 @@ struct ModuleEmitState<'a> {
     input_module: &'a InputModule<'a>,
     output_module_index: usize,
     output_module_info: &'a OutputModuleInfo,
-    emit_state: &'a EmitState,
-    program_info: &'a SplitProgramInfo,
+    emit_state: &'a EmitState<'a>,
     output_module: wasm_encoder::Module,
     output_functions: Vec<OutputFunction>,
     input_function_output_id: HashMap<InputFuncId, usize>,
@@ -274,7 +443,7 @@ struct ModuleEmitState<'a> {
 impl<'a> ModuleEmitState<'a> {
     fn new(
         module: &'a InputModule<'a>,
-        emit_state: &'a EmitState,
+        emit_state: &'a EmitState<'a>,
         output_module_index: usize,
         program_info: &'a crate::split_point::SplitProgramInfo,
     ) -> Self {
@@ -316,11 +485,27 @@ impl<'a> ModuleEmitState<'a> {
             })
             .collect();
 
+        if output_module_index == 0 {
+            if !emit_state.cold_functions.is_empty() {
+                output_functions.push(OutputFunction {
+                    kind: OutputFunctionKind::ColdLoaderImport,
+                    input_func_id: 0,
+                });
+            }
+            if emit_state.instrumentation.is_some() {
+                output_functions.push(OutputFunction {
+                    kind: OutputFunctionKind::ProfileCountersAccessor,
+                    input_func_id: 0,
+                });
+            }
+        }
+
         output_functions.sort();
 
         let mut input_function_output_id: HashMap<_, _> = output_functions
             .iter()
             .enumerate()
+            .filter(|(_, output_func)| !output_func.kind.is_added())
             .map(|(output_func_id, &OutputFunction { input_func_id, .. })| {
                 (input_func_id, output_func_id)
             })
@@ -332,7 +517,6 @@ impl<'a> ModuleEmitState<'a> {
                 if let Some(&output_func_id) =
                     input_function_output_id.get(&split_point.export_func)
                 {
-                    println!("Mapping split point {split_point:?} -> {output_func_id}");
                     input_function_output_id.insert(split_point.import_func, output_func_id);
                 }
             }
@@ -347,7 +531,6 @@ impl<'a> ModuleEmitState<'a> {
             output_module_index,
             output_module_info,
             emit_state,
-            program_info,
             output_module: wasm_encoder::Module::new(),
             output_functions,
             input_function_output_id,
@@ -359,6 +542,50 @@ impl<'a> ModuleEmitState<'a> {
         self.output_module_index == 0
     }
 
+    fn added_func_id(&self, kind: OutputFunctionKind) -> Option<usize> {
+        self.output_functions
+            .iter()
+            .position(|output_func| output_func.kind == kind)
+    }
+
+    fn input_function_types(&self) -> impl Iterator<Item = wasm_encoder::FuncType> + '_ {
+        self.input_module
+            .types
+            .iter()
+            .map(|ty| ty.clone().try_into().unwrap())
+    }
+
+    /// Types of the added functions that are not in the input module, which
+    /// are appended to the type section.
+    fn missing_function_types(&self) -> Vec<wasm_encoder::FuncType> {
+        let mut types = Vec::new();
+        for output_func in self.output_functions.iter() {
+            let Some(ty) = output_func.kind.added_function_type() else {
+                continue;
+            };
+            if !types.contains(&ty) && !self.input_function_types().any(|other| other == ty) {
+                types.push(ty);
+            }
+        }
+        types
+    }
+
+    fn added_function_type_id(&self, kind: OutputFunctionKind) -> u32 {
+        let ty = kind.added_function_type().unwrap();
+        let type_id = self
+            .input_function_types()
+            .position(|other| other == ty)
+            .unwrap_or_else(|| {
+                self.input_module.types.len()
+                    + self
+                        .missing_function_types()
+                        .iter()
+                        .position(|other| *other == ty)
+                        .unwrap()
+            });
+        type_id as u32
+    }
+
     fn get_relocation_input_function_index(&self, relocation: &RelocationEntry) -> Result<usize> {
         let Some(SymbolInfo::Func {
             index: input_func_id,
@@ -446,6 +673,25 @@ impl<'a> ModuleEmitState<'a> {
                     target.try_into().unwrap(),
                 );
             }
+            MemoryAddrLeb | MemoryAddrSleb | MemoryAddrI32 => {
+                // Only the symbols moved by instrumentation need to be
+                // relocated, since all other data keeps its address.
+                let Some(&address) = self.emit_state.instrumentation.and_then(|instrumentation| {
+                    instrumentation
+                        .relocated_symbols
+                        .get(&(relocation.index as SymbolIndex))
+                }) else {
+                    return Ok(());
+                };
+                let value = address.wrapping_add(relocation.addend as u32);
+                match relocation.ty {
+                    MemoryAddrLeb => encode_leb128_u32_5byte(value, target.try_into().unwrap()),
+                    MemoryAddrSleb => {
+                        encode_leb128_i32_5byte(value as i32, target.try_into().unwrap())
+                    }
+                    _ => encode_u32(value, target.try_into().unwrap()),
+                }
+            }
             FunctionOffsetI32 | SectionOffsetI32 | TableIndexRelSleb | FunctionOffsetI64
             | TableIndexRelSleb64 => {
                 bail!("Unsupported relocation type {relocation:?}");
@@ -486,9 +732,10 @@ impl<'a> ModuleEmitState<'a> {
     fn generate_type_section(&mut self) -> Result<()> {
         // Simply copy all types.  Unneeded types may be pruned by `wasm-opt`.
         let mut section = wasm_encoder::TypeSection::new();
-        for input_func_type in self.input_module.types.iter() {
-            let output_func_type: wasm_encoder::FuncType =
-                input_func_type.clone().try_into().unwrap();
+        for output_func_type in self
+            .input_function_types()
+            .chain(self.missing_function_types())
+        {
             section.function(
                 output_func_type.params().iter().cloned(),
                 output_func_type.results().iter().cloned(),
@@ -550,16 +797,29 @@ impl<'a> ModuleEmitState<'a> {
                 continue;
             }
             let import = &self.input_module.imports[import_id];
-            let ty: wasm_encoder::EntityType = import.ty.clone().try_into().unwrap();
+            let ty: wasm_encoder::EntityType = import.ty.try_into().unwrap();
             section.import(import.module, import.name, ty);
         }
 
+        if self
+            .added_func_id(OutputFunctionKind::ColdLoaderImport)
+            .is_some()
+        {
+            section.import(
+                self.emit_state.loader_module,
+                "__wasm_split_load_main_cold_sync",
+                wasm_encoder::EntityType::Function(
+                    self.added_function_type_id(OutputFunctionKind::ColdLoaderImport),
+                ),
+            );
+        }
+
         // Copy all non-function imports from input.
         for import in self.input_module.imports.iter() {
             if let wasmparser::TypeRef::Func(_) = import.ty {
                 continue;
             }
-            let ty: wasm_encoder::EntityType = import.ty.clone().try_into().unwrap();
+            let ty: wasm_encoder::EntityType = import.ty.try_into().unwrap();
             section.import(import.module, import.name, ty);
         }
 
@@ -587,7 +847,7 @@ impl<'a> ModuleEmitState<'a> {
 
             // Import all memories defined by the input module.
             for (memory_index, memory) in self.input_module.memories.iter().enumerate() {
-                let ty: wasm_encoder::MemoryType = memory.clone().try_into().unwrap();
+                let ty: wasm_encoder::MemoryType = (*memory).into();
                 section.import(
                     "__wasm_split",
                     self.get_memory_name(memory_index).as_str(),
@@ -600,12 +860,20 @@ impl<'a> ModuleEmitState<'a> {
 
     fn generate_function_section(&mut self) {
         let mut section = wasm_encoder::FunctionSection::new();
-        for OutputFunction { input_func_id, .. } in self
-            .output_functions
-            .iter()
-            .filter(|OutputFunction { kind, .. }| *kind != OutputFunctionKind::Import)
+        for OutputFunction {
+            kind,
+            input_func_id,
+        } in self.output_functions.iter()
         {
-            section.function(self.input_module.func_type_id(*input_func_id) as u32);
+            match kind {
+                OutputFunctionKind::Import | OutputFunctionKind::ColdLoaderImport => {}
+                OutputFunctionKind::ProfileCountersAccessor => {
+                    section.function(self.added_function_type_id(*kind));
+                }
+                OutputFunctionKind::Defined | OutputFunctionKind::IndirectStub => {
+                    section.function(self.input_module.func_type_id(*input_func_id) as u32);
+                }
+            }
         }
         self.output_module.section(&section);
     }
@@ -624,8 +892,12 @@ impl<'a> ModuleEmitState<'a> {
             return;
         }
         let mut section = wasm_encoder::MemorySection::new();
-        for memory in self.input_module.memories.iter() {
-            section.memory(memory.clone().try_into().unwrap());
+        for (memory_index, memory) in self.input_module.memories.iter().enumerate() {
+            let mut memory: wasm_encoder::MemoryType = (*memory).into();
+            if let (0, Some(instrumentation)) = (memory_index, self.emit_state.instrumentation) {
+                memory.minimum += instrumentation.extra_pages;
+            }
+            section.memory(memory);
         }
         self.output_module.section(&section);
     }
@@ -635,11 +907,16 @@ impl<'a> ModuleEmitState<'a> {
             return;
         }
         let mut section = wasm_encoder::GlobalSection::new();
-        for global in self.input_module.globals.iter() {
-            section.global(
-                global.ty.clone().try_into().unwrap(),
-                &global.init_expr.clone().try_into().unwrap(),
-            );
+        for (global_id, global) in self.input_module.globals.iter().enumerate() {
+            let relocated = self
+                .emit_state
+                .instrumentation
+                .and_then(|instrumentation| instrumentation.relocated_globals.get(&global_id));
+            let init_expr = match relocated {
+                Some(&address) => wasm_encoder::ConstExpr::i32_const(address as i32),
+                None => global.init_expr.try_into().unwrap(),
+            };
+            section.global(global.ty.try_into().unwrap(), &init_expr);
         }
         self.output_module.section(&section);
     }
@@ -650,7 +927,10 @@ impl<'a> ModuleEmitState<'a> {
         }
         let mut section = wasm_encoder::ExportSection::new();
         let mut existing_exports = HashSet::<&str>::new();
-        for export in self.input_module.exports.iter() {
+        for (export_id, export) in self.input_module.exports.iter().enumerate() {
+            if self.emit_state.orphan_exports.contains(&export_id) {
+                continue;
+            }
             let mut index = export.index;
             if export.kind == wasmparser::ExternalKind::Func {
                 let Some(&func_id) = self.input_function_output_id.get(&(index as InputFuncId))
@@ -659,10 +939,20 @@ impl<'a> ModuleEmitState<'a> {
                 };
                 index = func_id as u32;
             }
-            section.export(export.name, export.kind.try_into().unwrap(), index);
+            section.export(export.name, export.kind.into(), index);
             existing_exports.insert(export.name);
         }
 
+        if let Some(accessor_func_id) =
+            self.added_func_id(OutputFunctionKind::ProfileCountersAccessor)
+        {
+            section.export(
+                "__wasm_split_profile_counters",
+                wasm_encoder::ExportKind::Func,
+                accessor_func_id as u32,
+            );
+        }
+
         // Export table.
         if !existing_exports.contains("__indirect_function_table") {
             section.export(
@@ -707,7 +997,6 @@ impl<'a> ModuleEmitState<'a> {
     fn generate_element_section(&mut self) -> Result<()> {
         let indirect_range = self.indirect_function_table_range.clone();
         if indirect_range.is_empty() {
-            panic!("No indirect range");
             return Ok(());
         }
         let mut section = wasm_encoder::ElementSection::new();
@@ -738,13 +1027,13 @@ impl<'a> ModuleEmitState<'a> {
         Ok(())
     }
 
+    fn output_data_segments(&self) -> &'a [OutputDataSegment] {
+        &self.emit_state.data_segments.segments_for_output_module[self.output_module_index]
+    }
+
     fn generate_data_count_section(&mut self) {
         let section = wasm_encoder::DataCountSection {
-            count: if self.is_main() {
-                self.input_module.data_segments.len() as u32
-            } else {
-                0
-            },
+            count: self.output_data_segments().len() as u32,
         };
         self.output_module.section(&section);
     }
@@ -753,9 +1042,21 @@ impl<'a> ModuleEmitState<'a> {
         &self,
         indirect_index: usize,
         type_id: usize,
+        cold_loader_func_id: Option<usize>,
     ) -> wasm_encoder::Function {
         let func_type = &self.input_module.types[type_id];
         let mut func = wasm_encoder::Function::new([]);
+        if let Some(cold_loader_func_id) = cold_loader_func_id {
+            // Load the cold module if its table entry is not filled in yet.
+            func.instruction(&wasm_encoder::Instruction::I32Const(indirect_index as i32));
+            func.instruction(&wasm_encoder::Instruction::TableGet(0));
+            func.instruction(&wasm_encoder::Instruction::RefIsNull);
+            func.instruction(&wasm_encoder::Instruction::If(
+                wasm_encoder::BlockType::Empty,
+            ));
+            func.instruction(&wasm_encoder::Instruction::Call(cold_loader_func_id as u32));
+            func.instruction(&wasm_encoder::Instruction::End);
+        }
         for (param_i, _param_type) in func_type.params().iter().enumerate() {
             func.instruction(&wasm_encoder::Instruction::LocalGet(param_i as u32));
         }
@@ -772,11 +1073,25 @@ impl<'a> ModuleEmitState<'a> {
         let mut section = wasm_encoder::CodeSection::new();
         for output_func in self.output_functions.iter() {
             match output_func.kind {
-                OutputFunctionKind::Import => {}
+                OutputFunctionKind::Import | OutputFunctionKind::ColdLoaderImport => {}
+                OutputFunctionKind::ProfileCountersAccessor => {
+                    let instrumentation = self.emit_state.instrumentation.unwrap();
+                    let mut function = wasm_encoder::Function::new([]);
+                    function.instruction(&wasm_encoder::Instruction::I32Const(
+                        instrumentation.counters_address as i32,
+                    ));
+                    function.instruction(&wasm_encoder::Instruction::End);
+                    section.function(&function);
+                }
                 OutputFunctionKind::Defined => {
-                    let input_func = &self.input_module.defined_funcs
-                        [output_func.input_func_id - self.input_module.imported_funcs.len()];
-                    section.raw(&self.get_relocated_data(input_func.body.range())?);
+                    let defined_index =
+                        output_func.input_func_id - self.input_module.imported_funcs.len();
+                    let input_func = &self.input_module.defined_funcs[defined_index];
+                    let mut body = self.get_relocated_data(input_func.body.range())?;
+                    if let Some(instrumentation) = self.emit_state.instrumentation {
+                        body = instrumentation.add_prologue(input_func, defined_index, &body)?;
+                    }
+                    section.raw(&body);
                 }
                 OutputFunctionKind::IndirectStub => {
                     let indirect_index = self
@@ -785,9 +1100,16 @@ impl<'a> ModuleEmitState<'a> {
                         .function_table_index
                         .get(&output_func.input_func_id)
                         .unwrap();
+                    let cold_loader_func_id = self
+                        .emit_state
+                        .cold_functions
+                        .contains(&output_func.input_func_id)
+                        .then(|| self.added_func_id(OutputFunctionKind::ColdLoaderImport))
+                        .flatten();
                     let function = self.generate_indirect_stub(
                         *indirect_index,
                         self.input_module.func_type_id(output_func.input_func_id),
+                        cold_loader_func_id,
                     );
                     section.function(&function);
                 }
@@ -798,21 +1120,31 @@ impl<'a> ModuleEmitState<'a> {
     }
 
     fn generate_data_section(&mut self) -> Result<()> {
-        if !self.is_main() {
-            return Ok(());
-        }
         let mut section = wasm_encoder::DataSection::new();
-        for input_segment in self.input_module.data_segments.iter() {
-            // Note: `input_segment.range` includes the segment header.
-            let range_end = input_segment.range.end;
-            let data =
-                self.get_relocated_data((range_end - input_segment.data.len())..range_end)?;
-            match input_segment.kind {
-                DataKind::Passive => section.passive(data),
-                DataKind::Active {
-                    memory_index,
-                    offset_expr,
-                } => section.active(memory_index, &offset_expr.try_into()?, data),
+        for output_segment in self.output_data_segments() {
+            let input_segment = &self.input_module.data_segments[output_segment.input_segment];
+            let data = self.get_relocated_data(output_segment.range.clone())?;
+            match get_active_segment_offset(input_segment)? {
+                None => match input_segment.kind {
+                    DataKind::Passive => section.passive(data),
+                    DataKind::Active {
+                        memory_index,
+                        offset_expr,
+                    } => section.active(memory_index, &offset_expr.try_into()?, data),
+                },
+                Some((memory_index, offset, memory64)) => {
+                    // Preserve the original address of the data.
+                    let offset = offset
+                        + (output_segment.range.start
+                            - (input_segment.range.end - input_segment.data.len()))
+                            as i64;
+                    let offset_expr = if memory64 {
+                        wasm_encoder::ConstExpr::i64_const(offset)
+                    } else {
+                        wasm_encoder::ConstExpr::i32_const(offset as i32)
+                    };
+                    section.active(memory_index, &offset_expr, data)
+                }
             };
         }
         self.output_module.section(&section);
@@ -846,9 +1178,25 @@ impl<'a> ModuleEmitState<'a> {
             let mut name_map = wasm_encoder::NameMap::new();
             let mut locals_map = wasm_encoder::IndirectNameMap::new();
             let mut labels_map = wasm_encoder::IndirectNameMap::new();
-            for (output_func_id, OutputFunction { input_func_id, .. }) in
-                self.output_functions.iter().enumerate()
+            for (
+                output_func_id,
+                OutputFunction {
+                    kind,
+                    input_func_id,
+                },
+            ) in self.output_functions.iter().enumerate()
             {
+                match kind {
+                    OutputFunctionKind::ColdLoaderImport => {
+                        name_map.append(output_func_id as u32, "__wasm_split_load_main_cold_sync");
+                        continue;
+                    }
+                    OutputFunctionKind::ProfileCountersAccessor => {
+                        name_map.append(output_func_id as u32, "__wasm_split_profile_counters");
+                        continue;
+                    }
+                    _ => {}
+                }
                 if let Some(name) = self.input_module.names.functions.get(input_func_id) {
                     name_map.append(output_func_id as u32, name);
                 }
@@ -904,14 +1252,22 @@ impl<'a> ModuleEmitState<'a> {
     }
 }
 
+/// Returns the range of indirect function table entries defined by each
+/// output module.
+pub fn get_table_ranges(
+    module: &InputModule,
+    program_info: &SplitProgramInfo,
+) -> Result<Vec<Range<usize>>> {
+    Ok(IndirectFunctionEmitInfo::new(module, program_info)?.table_range_for_output_module)
+}
+
 pub fn emit_modules(
     module: &InputModule,
     program_info: &SplitProgramInfo,
-    emit_fn: &dyn Fn(usize, &[u8]) -> anyhow::Result<()>,
+    instrumentation: Option<&Instrumentation>,
+    emit_fn: &mut dyn FnMut(usize, &[u8]) -> anyhow::Result<()>,
 ) -> anyhow::Result<()> {
-    // For now we will ignore data symbols because that simplifies things quite a bit.
-
-    let emit_state = EmitState::new(module, program_info)?;
+    let emit_state = EmitState::new(module, program_info, instrumentation)?;
 
     for output_module_index in 0..program_info.output_modules.len() {
         let mut emit_state =
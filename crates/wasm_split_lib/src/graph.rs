@@ -0,0 +1,459 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+};
+
+use serde::Serialize;
+
+use crate::{
+    dep_graph::{symbol_name, symbol_size, DepGraph, DepNode},
+    read::{InputFuncId, InputModule},
+    split_point::SplitProgramInfo,
+};
+
+/// Format of the dependency graph visualization.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum GraphFormat {
+    /// Graphviz source, with one cluster per output module.
+    Dot,
+    /// Self-contained HTML page that renders the graph.
+    Html,
+}
+
+impl GraphFormat {
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            Self::Dot => "graph.dot",
+            Self::Html => "graph.html",
+        }
+    }
+}
+
+/// Level of detail of the dependency graph visualization.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum GraphLevel {
+    /// One node per output module, with edges summarizing the dependencies
+    /// between them.
+    #[default]
+    Modules,
+    /// One node per symbol, grouped by output module.
+    Symbols,
+}
+
+const COLORS: &[&str] = &[
+    "#8dd3c7", "#ffffb3", "#bebada", "#fb8072", "#80b1d3", "#fdb462", "#b3de69", "#fccde5",
+    "#d9d9d9", "#bc80bd", "#ccebc5", "#ffed6f",
+];
+
+#[derive(Serialize)]
+struct GraphModule {
+    name: String,
+    color: &'static str,
+}
+
+#[derive(Serialize)]
+struct GraphNode {
+    name: String,
+    module: usize,
+    size: usize,
+}
+
+#[derive(Serialize)]
+struct GraphEdge {
+    from: usize,
+    to: usize,
+    /// The edge connects symbols in different output modules.
+    cross_module: bool,
+    /// The target is referenced through the indirect function table.
+    indirect: bool,
+    /// The edge is a call through a split point, which is replaced by a stub
+    /// that loads the target module.
+    split: bool,
+    /// Number of symbol dependencies represented by the edge, which is more
+    /// than one only for edges between modules.
+    count: usize,
+}
+
+#[derive(Serialize)]
+struct Graph {
+    modules: Vec<GraphModule>,
+    nodes: Vec<GraphNode>,
+    edges: Vec<GraphEdge>,
+}
+
+impl Graph {
+    fn new(
+        module: &InputModule,
+        dep_graph: &DepGraph,
+        indirect_deps: &DepGraph,
+        program_info: &SplitProgramInfo,
+    ) -> Self {
+        let modules = program_info
+            .output_modules
+            .iter()
+            .enumerate()
+            .map(|(index, (identifier, _))| GraphModule {
+                name: identifier.name(),
+                color: COLORS[index % COLORS.len()],
+            })
+            .collect();
+
+        let mut symbols: Vec<_> = program_info.symbol_output_module.iter().collect();
+        symbols.sort();
+        let node_index: HashMap<DepNode, usize> = symbols
+            .iter()
+            .enumerate()
+            .map(|(index, (&node, _))| (node, index))
+            .collect();
+        let nodes = symbols
+            .iter()
+            .map(|(node, &output_module_index)| GraphNode {
+                name: symbol_name(module, node),
+                module: output_module_index,
+                size: symbol_size(module, node),
+            })
+            .collect();
+
+        // Calls to split point imports are drawn as edges to the
+        // corresponding export.
+        let split_func_map: HashMap<InputFuncId, InputFuncId> = program_info
+            .output_modules
+            .iter()
+            .flat_map(|(_, info)| info.split_points.iter())
+            .map(|split_point| (split_point.import_func, split_point.export_func))
+            .collect();
+
+        let mut edges = Vec::new();
+        for (from_node, &from) in symbols.iter().map(|(node, _)| (node, &node_index[node])) {
+            let Some(deps) = dep_graph.get(from_node) else {
+                continue;
+            };
+            let mut deps: Vec<_> = deps.iter().collect();
+            deps.sort();
+            for dep in deps {
+                let (to_node, split) = match dep {
+                    DepNode::Function(func_id) => match split_func_map.get(func_id) {
+                        Some(&export_func) => (DepNode::Function(export_func), true),
+                        None => (*dep, false),
+                    },
+                    _ => (*dep, false),
+                };
+                let Some(&to) = node_index.get(&to_node) else {
+                    continue;
+                };
+                edges.push(GraphEdge {
+                    from,
+                    to,
+                    cross_module: program_info.symbol_output_module[from_node]
+                        != program_info.symbol_output_module[&to_node],
+                    indirect: indirect_deps
+                        .get(from_node)
+                        .is_some_and(|deps| deps.contains(dep)),
+                    split,
+                    count: 1,
+                });
+            }
+        }
+        Self {
+            modules,
+            nodes,
+            edges,
+        }
+    }
+
+    /// Collapses the symbols of each output module into a single node, and
+    /// the dependencies between modules into one edge of each kind.
+    fn module_graph(&self) -> Self {
+        let mut nodes: Vec<GraphNode> = self
+            .modules
+            .iter()
+            .enumerate()
+            .map(|(index, module)| GraphNode {
+                name: module.name.clone(),
+                module: index,
+                size: 0,
+            })
+            .collect();
+        for node in self.nodes.iter() {
+            nodes[node.module].size += node.size;
+        }
+        let mut edge_counts = BTreeMap::<(usize, usize, bool, bool), usize>::new();
+        for edge in self.edges.iter().filter(|edge| edge.cross_module) {
+            let from = self.nodes[edge.from].module;
+            let to = self.nodes[edge.to].module;
+            *edge_counts
+                .entry((from, to, edge.indirect, edge.split))
+                .or_default() += 1;
+        }
+        let edges = edge_counts
+            .into_iter()
+            .map(|((from, to, indirect, split), count)| GraphEdge {
+                from,
+                to,
+                cross_module: true,
+                indirect,
+                split,
+                count,
+            })
+            .collect();
+        Self {
+            modules: self
+                .modules
+                .iter()
+                .map(|module| GraphModule {
+                    name: module.name.clone(),
+                    color: module.color,
+                })
+                .collect(),
+            nodes,
+            edges,
+        }
+    }
+
+    fn modules_to_dot(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "digraph wasm_split {{").unwrap();
+        writeln!(out, "  node [shape=box, style=filled];").unwrap();
+        for (node_index, node) in self.nodes.iter().enumerate() {
+            writeln!(
+                out,
+                "  n{node_index} [label={}, fillcolor={}];",
+                quote(&format!("{}\n{} B", node.name, node.size)),
+                quote(self.modules[node.module].color),
+            )
+            .unwrap();
+        }
+        for edge in self.edges.iter() {
+            let mut attrs = vec![format!("label={}", edge.count)];
+            if edge.split {
+                attrs.push("color=blue".to_string());
+            }
+            if edge.indirect {
+                attrs.push("style=dashed".to_string());
+            }
+            writeln!(
+                out,
+                "  n{} -> n{} [{}];",
+                edge.from,
+                edge.to,
+                attrs.join(", ")
+            )
+            .unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+        out
+    }
+
+    fn to_dot(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "digraph wasm_split {{").unwrap();
+        writeln!(out, "  node [shape=box, style=filled, fixedsize=shape];").unwrap();
+        for (module_index, module) in self.modules.iter().enumerate() {
+            writeln!(out, "  subgraph cluster_{module_index} {{").unwrap();
+            writeln!(out, "    label={};", quote(&module.name)).unwrap();
+            for (node_index, node) in self.nodes.iter().enumerate() {
+                if node.module != module_index {
+                    continue;
+                }
+                // Area is proportional to the size in bytes.
+                let scale = 0.2 + (node.size as f64).sqrt() / 40.0;
+                writeln!(
+                    out,
+                    "    n{node_index} [label={}, tooltip={}, fillcolor={}, width={:.2}, height={:.2}];",
+                    quote(&format!("{}\n{} B", truncate(&node.name, 40), node.size)),
+                    quote(&node.name),
+                    quote(module.color),
+                    scale * 2.0,
+                    scale,
+                )
+                .unwrap();
+            }
+            writeln!(out, "  }}").unwrap();
+        }
+        for edge in self.edges.iter() {
+            let mut attrs = Vec::new();
+            if edge.split {
+                attrs.push("color=blue, penwidth=2");
+            } else if edge.cross_module {
+                attrs.push("color=red, penwidth=2");
+            }
+            if edge.indirect {
+                attrs.push("style=dashed");
+            }
+            writeln!(
+                out,
+                "  n{} -> n{} [{}];",
+                edge.from,
+                edge.to,
+                attrs.join(", ")
+            )
+            .unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+        out
+    }
+
+    fn to_html(&self) -> String {
+        // Prevent the data from terminating the script element.
+        let data = serde_json::to_string(self).unwrap().replace("</", "<\\/");
+        HTML_TEMPLATE.replace("{{GRAPH}}", &data)
+    }
+}
+
+fn quote(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{escaped}\"")
+}
+
+fn truncate(s: &str, max_chars: usize) -> String {
+    match s.char_indices().nth(max_chars) {
+        Some((index, _)) => format!("{}...", &s[..index]),
+        None => s.to_string(),
+    }
+}
+
+pub(crate) fn make_graph(
+    module: &InputModule,
+    dep_graph: &DepGraph,
+    indirect_deps: &DepGraph,
+    program_info: &SplitProgramInfo,
+    format: GraphFormat,
+    level: GraphLevel,
+) -> String {
+    let graph = Graph::new(module, dep_graph, indirect_deps, program_info);
+    match (level, format) {
+        (GraphLevel::Modules, GraphFormat::Dot) => graph.module_graph().modules_to_dot(),
+        (GraphLevel::Modules, GraphFormat::Html) => graph.module_graph().to_html(),
+        (GraphLevel::Symbols, GraphFormat::Dot) => graph.to_dot(),
+        (GraphLevel::Symbols, GraphFormat::Html) => graph.to_html(),
+    }
+}
+
+const HTML_TEMPLATE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>wasm-split dependency graph</title>
+<style>
+body { margin: 0; font: 12px sans-serif; overflow: hidden; }
+#legend { position: fixed; top: 8px; left: 8px; background: #fffe; padding: 6px; border: 1px solid #ccc; }
+#legend span { display: inline-block; width: 10px; height: 10px; margin-right: 4px; }
+#tip { position: fixed; pointer-events: none; background: #ffe; border: 1px solid #999; padding: 2px 4px; display: none; max-width: 60em; word-break: break-all; }
+</style>
+</head>
+<body>
+<canvas id="canvas"></canvas>
+<div id="legend"></div>
+<div id="tip"></div>
+<script>
+const graph = {{GRAPH}};
+const canvas = document.getElementById("canvas");
+const ctx = canvas.getContext("2d");
+const legend = document.getElementById("legend");
+const tip = document.getElementById("tip");
+
+for (const module of graph.modules) {
+  const count = graph.nodes.filter((node) => graph.modules[node.module] === module).length;
+  legend.insertAdjacentHTML("beforeend",
+    `<div><span style="background:${module.color}"></span>${module.name} (${count})</div>`);
+}
+legend.insertAdjacentHTML("beforeend",
+  `<div style="color:red">cross-module</div><div style="color:blue">split point</div><div>- - indirect</div>`);
+
+// Lay out the nodes of each module in a sunflower pattern, largest first, and
+// the modules themselves around a circle.
+const radius = (node) => 2 + Math.sqrt(node.size) / 4;
+const goldenAngle = Math.PI * (3 - Math.sqrt(5));
+const extents = graph.modules.map((_, moduleIndex) => {
+  const nodes = graph.nodes.filter((node) => node.module === moduleIndex);
+  nodes.sort((a, b) => b.size - a.size);
+  const area = nodes.reduce((sum, node) => sum + (2 * radius(node) + 2) ** 2, 0);
+  const spacing = Math.sqrt(area / Math.max(nodes.length, 1) / Math.PI);
+  nodes.forEach((node, i) => {
+    const r = spacing * Math.sqrt(i);
+    node.x = r * Math.cos(i * goldenAngle);
+    node.y = r * Math.sin(i * goldenAngle);
+  });
+  return { nodes, extent: spacing * Math.sqrt(nodes.length) + radius(nodes[0] ?? { size: 0 }) };
+});
+const circumference = extents.reduce((sum, { extent }) => sum + 2 * extent + 100, 0);
+const ring = extents.length > 1 ? circumference / (2 * Math.PI) : 0;
+let angle = 0;
+for (const { nodes, extent } of extents) {
+  angle += (extent + 50) / Math.max(ring, 1);
+  const cx = ring * Math.cos(angle), cy = ring * Math.sin(angle);
+  angle += (extent + 50) / Math.max(ring, 1);
+  for (const node of nodes) {
+    node.x += cx;
+    node.y += cy;
+  }
+}
+
+let scale = Math.min(1, 400 / Math.max(ring + 200, 1)), offsetX = 0, offsetY = 0;
+function draw() {
+  canvas.width = window.innerWidth;
+  canvas.height = window.innerHeight;
+  ctx.setTransform(scale, 0, 0, scale, canvas.width / 2 + offsetX, canvas.height / 2 + offsetY);
+  for (const edge of graph.edges) {
+    const a = graph.nodes[edge.from], b = graph.nodes[edge.to];
+    ctx.strokeStyle = edge.split ? "blue" : edge.cross_module ? "red" : "#0002";
+    ctx.lineWidth = (edge.split || edge.cross_module ? 2 : 1) / scale;
+    ctx.setLineDash(edge.indirect ? [6 / scale, 4 / scale] : []);
+    ctx.beginPath();
+    ctx.moveTo(a.x, a.y);
+    ctx.lineTo(b.x, b.y);
+    ctx.stroke();
+  }
+  ctx.setLineDash([]);
+  for (const node of graph.nodes) {
+    ctx.fillStyle = graph.modules[node.module].color;
+    ctx.strokeStyle = "#0006";
+    ctx.lineWidth = 1 / scale;
+    ctx.beginPath();
+    ctx.arc(node.x, node.y, radius(node), 0, 2 * Math.PI);
+    ctx.fill();
+    ctx.stroke();
+  }
+}
+function toGraph(event) {
+  return [(event.clientX - canvas.width / 2 - offsetX) / scale,
+          (event.clientY - canvas.height / 2 - offsetY) / scale];
+}
+let drag = undefined;
+canvas.addEventListener("mousedown", (event) => { drag = [event.clientX, event.clientY]; });
+window.addEventListener("mouseup", () => { drag = undefined; });
+canvas.addEventListener("mousemove", (event) => {
+  if (drag) {
+    offsetX += event.clientX - drag[0];
+    offsetY += event.clientY - drag[1];
+    drag = [event.clientX, event.clientY];
+    draw();
+    return;
+  }
+  const [x, y] = toGraph(event);
+  const node = graph.nodes.find((node) => Math.hypot(node.x - x, node.y - y) <= radius(node));
+  if (node === undefined) {
+    tip.style.display = "none";
+    return;
+  }
+  tip.textContent = `${node.name} (${graph.modules[node.module].name}, ${node.size} B)`;
+  tip.style.left = event.clientX + 12 + "px";
+  tip.style.top = event.clientY + 12 + "px";
+  tip.style.display = "block";
+});
+canvas.addEventListener("wheel", (event) => {
+  event.preventDefault();
+  scale *= Math.exp(-event.deltaY / 500);
+  draw();
+}, { passive: false });
+window.addEventListener("resize", draw);
+draw();
+</script>
+</body>
+</html>
+"##;
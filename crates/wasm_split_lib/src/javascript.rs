@@ -0,0 +1,639 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{
+    manifest::integrity,
+    profile::Instrumentation,
+    report::ModuleKind,
+    split_point::{split_ancestors, SplitModuleIdentifier, SplitProgramInfo},
+    OutputModule,
+};
+
+/// Environment in which the generated loader runs.
+///
+/// The loader verifies the integrity digest of each fetched module with
+/// `crypto.subtle`, which browsers only provide in secure contexts (HTTPS or
+/// `localhost`) and Node.js from version 19. Elsewhere, the digest is only
+/// checked by `fetch`, which reports a mismatch as a fetch failure.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum LoaderTarget {
+    /// ES module for browsers and Deno, used with the `web` target of
+    /// `wasm-bindgen`. Modules are fetched relative to the loader.
+    #[default]
+    Web,
+    /// CommonJS module for Node.js, used with the `nodejs` target of
+    /// `wasm-bindgen`. Modules are read from disk relative to the loader.
+    Node,
+    /// Classic script for workers, loaded with `importScripts` after the
+    /// `no-modules` output of `wasm-bindgen`. Modules are fetched relative to
+    /// the worker script. The loader functions are exposed as the global
+    /// `__wasm_split`, which must be provided as the import module of the
+    /// loader functions, `./__wasm_split.js` by default.
+    Worker,
+    /// Classic script for pages, loaded with a `<script>` element after the
+    /// `no-modules` output of `wasm-bindgen`. Modules are fetched relative to
+    /// the script. The loader functions are exposed as the global
+    /// `__wasm_split`, as for `Worker`.
+    NoModules,
+    /// Template for the loader, in which the following placeholders are
+    /// replaced:
+    ///
+    /// - `{{RUNTIME}}`: statements that define the loader functions. These
+    ///   call functions that the template must define: `mainExports()`,
+    ///   returning the exports of the main module; `moduleUrl(file)`,
+    ///   returning the location of an output file; `readModule(url,
+    ///   integrity)`, resolving to the bytes at that location, which should
+    ///   pass the integrity digest (or `null`) on to `fetch`; and
+    ///   `readModuleSync(url)`, only called if there is a `main_cold` module.
+    /// - `{{EXPORTS}}`: comma-separated names of the loader functions, which
+    ///   the template must export to the main module and the application.
+    /// - `{{MODULES}}`: JSON array describing each output module, for
+    ///   templates that do not use `{{RUNTIME}}`. Each element has the
+    ///   `name`, `kind` (`main`, `split`, `chunk` or `cold`), `file`,
+    ///   `integrity` (`null` for main) and `deps` (names of the modules that
+    ///   must be loaded first) of the module. Dependencies precede the
+    ///   modules that use them.
+    Custom(String),
+}
+
+const WEB_TEMPLATE: &str = r#"import { initSync } from "./main.js";
+{{RUNTIME}}
+function mainExports() {
+  return initSync(undefined, undefined);
+}
+function moduleUrl(file) {
+  return new URL("./" + file, import.meta.url);
+}
+async function readModule(url, integrity) {
+  const response = await fetch(url, { integrity: integrity ?? "" });
+  if (!response.ok) throw new Error("HTTP status " + response.status);
+  return response.arrayBuffer();
+}
+function readModuleSync(url) {
+  // Note that browsers may limit the size of modules compiled synchronously
+  // on the main thread.
+  const request = new XMLHttpRequest();
+  request.open("GET", url, false);
+  request.overrideMimeType("text/plain; charset=x-user-defined");
+  request.send();
+  if (request.status !== 200) throw new Error("HTTP status " + request.status);
+  return Uint8Array.from(request.responseText, (c) => c.charCodeAt(0) & 0xff);
+}
+export { {{EXPORTS}} };
+"#;
+
+const NODE_TEMPLATE: &str = r#"const fs = require("node:fs");
+const path = require("node:path");
+{{RUNTIME}}
+function mainExports() {
+  return require("./main.js").__wasm;
+}
+function moduleUrl(file) {
+  return path.join(__dirname, file);
+}
+function readModule(url) {
+  return fs.promises.readFile(url);
+}
+function readModuleSync(url) {
+  return fs.readFileSync(url);
+}
+Object.assign(module.exports, { {{EXPORTS}} });
+"#;
+
+const CLASSIC_SCRIPT_TEMPLATE: &str = r#"(() => {
+const baseUrl = {{BASE_URL}};
+{{RUNTIME}}
+function mainExports() {
+  return wasm_bindgen.initSync(undefined);
+}
+function moduleUrl(file) {
+  return new URL(file, baseUrl);
+}
+async function readModule(url, integrity) {
+  const response = await fetch(url, { integrity: integrity ?? "" });
+  if (!response.ok) throw new Error("HTTP status " + response.status);
+  return response.arrayBuffer();
+}
+function readModuleSync(url) {
+  const request = new XMLHttpRequest();
+  request.open("GET", url, false);
+  request.overrideMimeType("text/plain; charset=x-user-defined");
+  request.send();
+  if (request.status !== 200) throw new Error("HTTP status " + request.status);
+  return Uint8Array.from(request.responseText, (c) => c.charCodeAt(0) & 0xff);
+}
+globalThis.__wasm_split = { {{EXPORTS}} };
+})();
+"#;
+
+/// Functions used by every target, followed by the loaders of each module.
+const RUNTIME: &str = r#"// Status codes passed to the load callback. These must match the constants in
+// the `wasm_split` crate.
+const LOAD_SUCCESS = 0;
+const LOAD_FETCH_FAILED = 1;
+const LOAD_INSTANTIATE_FAILED = 2;
+const LOAD_DEPENDENCY_FAILED = 3;
+const LOAD_INTEGRITY_FAILED = 4;
+// Overrides the policy for retrying failed fetches, e.g.
+// `setRetryPolicy({ attempts: 5, backoffMs: 1000 })`.
+function setRetryPolicy(policy) {
+  retryPolicy = { ...retryPolicy, ...policy };
+}
+async function readWithRetry(url, integrity) {
+  for (let attempt = 1;; ++attempt) {
+    try {
+      return await readModule(url, integrity);
+    } catch (e) {
+      if (attempt >= retryPolicy.attempts) throw e;
+      console.warn("Failed to fetch " + url + ", retrying", e);
+      const delay = retryPolicy.backoffMs * 2 ** (attempt - 1);
+      await new Promise((resolve) => setTimeout(resolve, delay));
+    }
+  }
+}
+function formatIntegrity(digest) {
+  return "sha384-" + btoa(String.fromCharCode(...digest));
+}
+// Checks the subresource integrity digest of a fetched module. Without
+// `crypto.subtle`, which browsers only provide in secure contexts, the check
+// is left to `fetch`, to which `readModule` passes the digest.
+async function checkIntegrity(bytes, integrity) {
+  if (integrity === null || globalThis.crypto?.subtle === undefined) return true;
+  const digest = new Uint8Array(await crypto.subtle.digest("SHA-384", bytes));
+  return formatIntegrity(digest) === integrity;
+}
+function getImports() {
+  const exports = mainExports();
+  return {
+    env: {
+      memory: exports.memory,
+    },
+    __wasm_split: {
+      __indirect_function_table: exports.__indirect_function_table,
+      __stack_pointer: exports.__stack_pointer,
+      __tls_base: exports.__tls_base,
+      memory: exports.memory,
+    },
+  };
+}
+function makeLoad(url, integrity, deps) {
+  let pending = undefined;
+  // One of "not-loaded", "loading", "loaded" or "failed".
+  let loadStatus = "not-loaded";
+  async function load() {
+    for (let dep of deps) {
+      if (await dep() !== LOAD_SUCCESS) return LOAD_DEPENDENCY_FAILED;
+    }
+    let bytes;
+    try {
+      bytes = await readWithRetry(url, integrity);
+    } catch (e) {
+      console.error("Failed to fetch " + url, e);
+      return LOAD_FETCH_FAILED;
+    }
+    if (!(await checkIntegrity(bytes, integrity))) {
+      console.error("Integrity check failed for " + url);
+      return LOAD_INTEGRITY_FAILED;
+    }
+    try {
+      await WebAssembly.instantiate(bytes, getImports());
+    } catch (e) {
+      console.error("Failed to instantiate " + url, e);
+      return LOAD_INSTANTIATE_FAILED;
+    }
+    return LOAD_SUCCESS;
+  }
+  const loadWithCallback = async (callbackIndex, callbackData) => {
+    if (pending === undefined) {
+      loadStatus = "loading";
+      // Failed loads are not cached, so that a later call tries again.
+      pending = load().then((status) => {
+        loadStatus = status === LOAD_SUCCESS ? "loaded" : "failed";
+        if (status !== LOAD_SUCCESS) pending = undefined;
+        return status;
+      });
+    }
+    const status = await pending;
+    if (callbackIndex === undefined) return status;
+    mainExports().__indirect_function_table.get(callbackIndex)(callbackData, status);
+  };
+  loadWithCallback.status = () => loadStatus;
+  return loadWithCallback;
+}
+"#;
+
+const COLD_RUNTIME: &str = r#"let mainColdModule = undefined;
+let mainColdInstantiated = false;
+let mainColdIntegrityFailed = false;
+const mainColdCompiled = readWithRetry(mainColdUrl, mainColdIntegrity)
+  .then(async (bytes) => {
+    if (!(await checkIntegrity(bytes, mainColdIntegrity))) {
+      mainColdIntegrityFailed = true;
+      throw new Error("Integrity check failed for " + mainColdUrl);
+    }
+    return WebAssembly.compile(bytes);
+  })
+  .then((module) => (mainColdModule = module));
+// Errors are reported when the module is needed.
+mainColdCompiled.catch(() => {});
+function instantiateMainCold() {
+  if (mainColdInstantiated) return;
+  new WebAssembly.Instance(mainColdModule, getImports());
+  mainColdInstantiated = true;
+}
+async function __wasm_split_load_main_cold() {
+  try {
+    await mainColdCompiled;
+  } catch (e) {
+    console.error("Failed to fetch " + mainColdUrl, e);
+    return mainColdIntegrityFailed ? LOAD_INTEGRITY_FAILED : LOAD_FETCH_FAILED;
+  }
+  try {
+    instantiateMainCold();
+  } catch (e) {
+    console.error("Failed to instantiate " + mainColdUrl, e);
+    return LOAD_INSTANTIATE_FAILED;
+  }
+  return LOAD_SUCCESS;
+}
+// Called by main when it calls a function of the cold module before the
+// module has been instantiated.
+function __wasm_split_load_main_cold_sync() {
+  if (mainColdIntegrityFailed) {
+    throw new Error("Integrity check failed for " + mainColdUrl);
+  }
+  if (mainColdModule === undefined) {
+    // The background fetch has not completed yet.
+    let bytes;
+    try {
+      bytes = readModuleSync(mainColdUrl);
+    } catch (e) {
+      throw new Error("Failed to fetch " + mainColdUrl, { cause: e });
+    }
+    if (mainColdIntegrity !== null && formatIntegrity(sha384(bytes)) !== mainColdIntegrity) {
+      mainColdIntegrityFailed = true;
+      throw new Error("Integrity check failed for " + mainColdUrl);
+    }
+    mainColdModule = new WebAssembly.Module(bytes);
+  }
+  instantiateMainCold();
+}
+"#;
+
+const SHA384_RUNTIME: &str = r#"// Synchronous SHA-384, for checking the integrity of the cold module when it
+// is fetched synchronously, which `crypto.subtle` does not support. 64-bit
+// words are stored as pairs of 32-bit integers, high half first.
+const sha512Constants = `
+428a2f98d728ae22 7137449123ef65cd b5c0fbcfec4d3b2f e9b5dba58189dbbc
+3956c25bf348b538 59f111f1b605d019 923f82a4af194f9b ab1c5ed5da6d8118
+d807aa98a3030242 12835b0145706fbe 243185be4ee4b28c 550c7dc3d5ffb4e2
+72be5d74f27b896f 80deb1fe3b1696b1 9bdc06a725c71235 c19bf174cf692694
+e49b69c19ef14ad2 efbe4786384f25e3 0fc19dc68b8cd5b5 240ca1cc77ac9c65
+2de92c6f592b0275 4a7484aa6ea6e483 5cb0a9dcbd41fbd4 76f988da831153b5
+983e5152ee66dfab a831c66d2db43210 b00327c898fb213f bf597fc7beef0ee4
+c6e00bf33da88fc2 d5a79147930aa725 06ca6351e003826f 142929670a0e6e70
+27b70a8546d22ffc 2e1b21385c26c926 4d2c6dfc5ac42aed 53380d139d95b3df
+650a73548baf63de 766a0abb3c77b2a8 81c2c92e47edaee6 92722c851482353b
+a2bfe8a14cf10364 a81a664bbc423001 c24b8b70d0f89791 c76c51a30654be30
+d192e819d6ef5218 d69906245565a910 f40e35855771202a 106aa07032bbd1b8
+19a4c116b8d2d0c8 1e376c085141ab53 2748774cdf8eeb99 34b0bcb5e19b48a8
+391c0cb3c5c95a63 4ed8aa4ae3418acb 5b9cca4f7763e373 682e6ff3d6b2b8a3
+748f82ee5defb2fc 78a5636f43172f60 84c87814a1f0ab72 8cc702081a6439ec
+90befffa23631e28 a4506cebde82bde9 bef9a3f7b2c67915 c67178f2e372532b
+ca273eceea26619c d186b8c721c0c207 eada7dd6cde0eb1e f57d4f7fee6ed178
+06f067aa72176fba 0a637dc5a2c898a6 113f9804bef90dae 1b710b35131c471b
+28db77f523047d84 32caab7b40c72493 3c9ebe0a15c9bebc 431d67c49c100d4c
+4cc5d4becb3e42b6 597f299cfc657e2a 5fcb6fab3ad6faec 6c44198c4a475817
+`;
+const sha384InitialHash = `
+cbbb9d5dc1059ed8 629a292a367cd507 9159015a3070dd17 152fecd8f70e5939
+67332667ffc00b31 8eb44a8768581511 db0c2e0d64f98fa7 47b5481dbefa4fa4
+`;
+function parseWords(hex) {
+  return Int32Array.from(hex.trim().split(/\s+/).flatMap((word) =>
+    [parseInt(word.slice(0, 8), 16), parseInt(word.slice(8), 16)]));
+}
+function sha384(bytes) {
+  const k = parseWords(sha512Constants);
+  const hash = parseWords(sha384InitialHash);
+  const padded = new Uint8Array(Math.ceil((bytes.length + 17) / 128) * 128);
+  padded.set(bytes);
+  padded[bytes.length] = 0x80;
+  const view = new DataView(padded.buffer);
+  view.setUint32(padded.length - 8, Math.floor(bytes.length / 0x20000000));
+  view.setUint32(padded.length - 4, bytes.length << 3);
+  // Rotations right by `n` of the 64-bit word `(h, l)`, for `n < 32`. Larger
+  // rotations swap the halves first.
+  const rotrHigh = (h, l, n) => (h >>> n) | (l << (32 - n));
+  const rotrLow = (h, l, n) => (l >>> n) | (h << (32 - n));
+  // Carry from the sum of low halves, each converted to unsigned.
+  const carry = (low) => (low / 0x100000000) | 0;
+  const w = new Int32Array(160);
+  for (let offset = 0; offset < padded.length; offset += 128) {
+    for (let i = 0; i < 32; ++i) w[i] = view.getInt32(offset + 4 * i);
+    for (let i = 32; i < 160; i += 2) {
+      let h = w[i - 30], l = w[i - 29];
+      const s0h = rotrHigh(h, l, 1) ^ rotrHigh(h, l, 8) ^ (h >>> 7);
+      const s0l = rotrLow(h, l, 1) ^ rotrLow(h, l, 8) ^ ((l >>> 7) | (h << 25));
+      h = w[i - 4], l = w[i - 3];
+      const s1h = rotrHigh(h, l, 19) ^ rotrLow(h, l, 29) ^ (h >>> 6);
+      const s1l = rotrLow(h, l, 19) ^ rotrHigh(h, l, 29) ^ ((l >>> 6) | (h << 26));
+      const low = (s0l >>> 0) + (s1l >>> 0) + (w[i - 13] >>> 0) + (w[i - 31] >>> 0);
+      w[i] = s0h + s1h + w[i - 14] + w[i - 32] + carry(low);
+      w[i + 1] = low;
+    }
+    let [ah, al, bh, bl, ch, cl, dh, dl, eh, el, fh, fl, gh, gl, hh, hl] = hash;
+    for (let i = 0; i < 160; i += 2) {
+      const sigma1h = rotrHigh(eh, el, 14) ^ rotrHigh(eh, el, 18) ^ rotrLow(eh, el, 9);
+      const sigma1l = rotrLow(eh, el, 14) ^ rotrLow(eh, el, 18) ^ rotrHigh(eh, el, 9);
+      const chooseH = (eh & fh) ^ (~eh & gh), chooseL = (el & fl) ^ (~el & gl);
+      const t1Low = (hl >>> 0) + (sigma1l >>> 0) + (chooseL >>> 0) + (k[i + 1] >>> 0) +
+        (w[i + 1] >>> 0);
+      const t1h = hh + sigma1h + chooseH + k[i] + w[i] + carry(t1Low);
+      const t1l = t1Low | 0;
+      const sigma0h = rotrHigh(ah, al, 28) ^ rotrLow(ah, al, 2) ^ rotrLow(ah, al, 7);
+      const sigma0l = rotrLow(ah, al, 28) ^ rotrHigh(ah, al, 2) ^ rotrHigh(ah, al, 7);
+      const majorityH = (ah & bh) ^ (ah & ch) ^ (bh & ch);
+      const majorityL = (al & bl) ^ (al & cl) ^ (bl & cl);
+      const t2Low = (sigma0l >>> 0) + (majorityL >>> 0);
+      const t2h = sigma0h + majorityH + carry(t2Low);
+      hh = gh, hl = gl, gh = fh, gl = fl, fh = eh, fl = el;
+      const eLow = (dl >>> 0) + (t1l >>> 0);
+      eh = (dh + t1h + carry(eLow)) | 0, el = eLow | 0;
+      dh = ch, dl = cl, ch = bh, cl = bl, bh = ah, bl = al;
+      const aLow = (t1l >>> 0) + (t2Low >>> 0);
+      ah = (t1h + t2h + carry(aLow)) | 0, al = aLow | 0;
+    }
+    const state = [ah, al, bh, bl, ch, cl, dh, dl, eh, el, fh, fl, gh, gl, hh, hl];
+    for (let i = 0; i < 16; i += 2) {
+      const low = (hash[i + 1] >>> 0) + (state[i + 1] >>> 0);
+      hash[i] += state[i] + carry(low);
+      hash[i + 1] = low;
+    }
+  }
+  const digest = new DataView(new ArrayBuffer(48));
+  for (let i = 0; i < 12; ++i) digest.setInt32(4 * i, hash[i]);
+  return new Uint8Array(digest.buffer);
+}
+"#;
+
+const SPLIT_MODULES_RUNTIME: &str = r#"function getSplitModule(name) {
+  const load = splitModules[name];
+  if (load === undefined) throw new Error("Unknown split module: " + name);
+  return load;
+}
+// Starts loading the named split module, e.g. when the user is likely to need
+// it soon. Resolves to the load status.
+function preload(name) {
+  return getSplitModule(name)();
+}
+// Returns one of "not-loaded", "loading", "loaded" or "failed".
+function status(name) {
+  return getSplitModule(name).status();
+}
+function splitModuleNames() {
+  return Object.keys(splitModules);
+}
+// Used by `wasm_split::modules()`.
+function __wasm_split_module_count() {
+  return Object.keys(splitModules).length;
+}
+function __wasm_split_module_name(index, ptr, len) {
+  const name = new TextEncoder().encode(Object.keys(splitModules)[index]);
+  const memory = mainExports().memory;
+  new Uint8Array(memory.buffer, ptr, len).set(name.subarray(0, len));
+  return name.length;
+}
+// Used by `wasm_split` to delay loading a module again after a failed load.
+function __wasm_split_set_timeout(ms, callbackIndex, callbackData) {
+  setTimeout(() => {
+    mainExports().__indirect_function_table.get(callbackIndex)(callbackData, LOAD_SUCCESS);
+  }, ms);
+}
+"#;
+
+/// Output module as described by the `{{MODULES}}` placeholder.
+#[derive(Serialize)]
+struct LoaderModule {
+    name: String,
+    kind: ModuleKind,
+    file: String,
+    integrity: Option<String>,
+    deps: Vec<String>,
+}
+
+/// Returns the output modules in an order in which dependencies precede the
+/// modules that use them.
+fn get_loader_modules(
+    split_program_info: &SplitProgramInfo,
+    modules: &[OutputModule],
+) -> Vec<LoaderModule> {
+    let has_cold = split_program_info
+        .output_module_identifiers
+        .contains_key(&SplitModuleIdentifier::Cold);
+    let mut split_deps = HashMap::<String, Vec<String>>::new();
+    for (identifier, _) in split_program_info.output_modules.iter() {
+        match identifier {
+            SplitModuleIdentifier::Split(split) => {
+                let deps = split_deps.entry(split.clone()).or_default();
+                // Nested split modules load their parent chain first.
+                if let Some(parent) = split_program_info.split_parents.get(split) {
+                    deps.insert(0, parent.clone());
+                }
+                if has_cold {
+                    deps.insert(0, "main_cold".to_string());
+                }
+            }
+            SplitModuleIdentifier::Chunk(splits) => {
+                for split in splits {
+                    split_deps
+                        .entry(split.clone())
+                        .or_default()
+                        .push(identifier.name());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut identifiers: Vec<_> = split_program_info
+        .output_modules
+        .iter()
+        .map(|(identifier, _)| identifier)
+        .filter(|identifier| matches!(identifier, SplitModuleIdentifier::Cold))
+        .chain(
+            split_program_info
+                .output_modules
+                .iter()
+                .map(|(identifier, _)| identifier)
+                .filter(|identifier| matches!(identifier, SplitModuleIdentifier::Chunk(_))),
+        )
+        .collect();
+    let mut split_identifiers: Vec<_> = split_program_info
+        .output_modules
+        .iter()
+        .rev()
+        .map(|(identifier, _)| identifier)
+        .filter(|identifier| {
+            matches!(
+                identifier,
+                SplitModuleIdentifier::Main | SplitModuleIdentifier::Split(_)
+            )
+        })
+        .collect();
+    // Parents must be defined before their nested split modules.
+    split_identifiers.sort_by_key(|identifier| match identifier {
+        SplitModuleIdentifier::Split(split) => {
+            split_ancestors(&split_program_info.split_parents, split).count()
+        }
+        _ => 0,
+    });
+    identifiers.extend(split_identifiers);
+
+    identifiers
+        .into_iter()
+        .map(|identifier| {
+            let module = &modules[split_program_info.output_module_identifiers[identifier]];
+            let name = identifier.name();
+            LoaderModule {
+                kind: match identifier {
+                    SplitModuleIdentifier::Main => ModuleKind::Main,
+                    SplitModuleIdentifier::Split(_) => ModuleKind::Split,
+                    SplitModuleIdentifier::Chunk(_) => ModuleKind::Chunk,
+                    SplitModuleIdentifier::Cold => ModuleKind::Cold,
+                },
+                file: module.file_name(),
+                // The main module is processed further by `wasm-bindgen`,
+                // which changes its digest.
+                integrity: (*identifier != SplitModuleIdentifier::Main)
+                    .then(|| integrity(&module.wasm)),
+                deps: split_deps.remove(&name).unwrap_or_default(),
+                name,
+            }
+        })
+        .collect()
+}
+
+fn json(value: &impl Serialize) -> String {
+    serde_json::to_string(value).unwrap()
+}
+
+/// Generates the loader, which loads the split modules on demand.
+pub fn generate_loader(
+    split_program_info: &SplitProgramInfo,
+    modules: &[OutputModule],
+    target: &LoaderTarget,
+    instrumentation: Option<&Instrumentation>,
+    retry_attempts: u32,
+    retry_backoff_ms: u32,
+) -> String {
+    let loader_modules = get_loader_modules(split_program_info, modules);
+
+    let mut runtime = RUNTIME.to_string();
+    let mut exports: Vec<String> = [
+        "setRetryPolicy",
+        "preload",
+        "status",
+        "splitModuleNames",
+        "__wasm_split_module_count",
+        "__wasm_split_module_name",
+        "__wasm_split_set_timeout",
+    ]
+    .map(str::to_string)
+    .to_vec();
+    runtime.push_str(&format!(
+        "let retryPolicy = {{ attempts: {}, backoffMs: {} }};\n",
+        retry_attempts, retry_backoff_ms
+    ));
+    let mut split_modules = Vec::new();
+    for module in loader_modules.iter() {
+        let url = format!("moduleUrl({})", json(&module.file));
+        let integrity = json(&module.integrity);
+        match module.kind {
+            ModuleKind::Cold => {
+                runtime.push_str(&format!(
+                    r#"// Functions of the main module that were not called in the profile. The
+// module is fetched and compiled in the background, and instantiated before
+// any split module or when main first calls one of its functions.
+const mainColdUrl = {url};
+const mainColdIntegrity = {integrity};
+"#
+                ));
+                runtime.push_str(SHA384_RUNTIME);
+                runtime.push_str(COLD_RUNTIME);
+                exports.push("__wasm_split_load_main_cold_sync".to_string());
+            }
+            ModuleKind::Chunk => {
+                runtime.push_str(&format!(
+                    "const __wasm_split_load_{} = makeLoad({url}, {integrity}, []);\n",
+                    module.name
+                ));
+            }
+            ModuleKind::Main | ModuleKind::Split => {
+                let name = &module.name;
+                if module.kind == ModuleKind::Split {
+                    split_modules.push(format!("  {name}: __wasm_split_load_{name},\n"));
+                }
+                runtime.push_str(&format!(
+                    "const __wasm_split_load_{name} = makeLoad({url}, {integrity}, [{deps}]);\n",
+                    deps = module
+                        .deps
+                        .iter()
+                        .map(|dep| format!("__wasm_split_load_{dep}"))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ));
+                exports.push(format!("__wasm_split_load_{name}"));
+            }
+        }
+    }
+    runtime.push_str(&format!(
+        "const splitModules = {{\n{}}};\n",
+        split_modules.concat()
+    ));
+    runtime.push_str(SPLIT_MODULES_RUNTIME);
+
+    if let Some(instrumentation) = instrumentation {
+        runtime.push_str(&format!(
+            r#"// Returns the number of calls of each function so far, in the order of
+// the symbol map.
+function profileCounters() {{
+  const exports = mainExports();
+  const address = exports.__wasm_split_profile_counters();
+  return new BigUint64Array(exports.memory.buffer, address, {count}).slice();
+}}
+// Returns a bitmap of the functions called so far, in which bit `i % 8` of
+// byte `i / 8` is set if function `i` of the symbol map was called, to be
+// combined into a profile by `wasm-split profile-merge`.
+function profileBitmap() {{
+  const bitmap = new Uint8Array({size});
+  profileCounters().forEach((count, i) => {{
+    if (count !== 0n) bitmap[i >> 3] |= 1 << (i & 7);
+  }});
+  return bitmap;
+}}
+"#,
+            count = instrumentation.function_names.len(),
+            size = instrumentation.function_names.len().div_ceil(8),
+        ));
+        exports.push("profileCounters".to_string());
+        exports.push("profileBitmap".to_string());
+    }
+
+    let template = match target {
+        LoaderTarget::Web => WEB_TEMPLATE.to_string(),
+        LoaderTarget::Node => NODE_TEMPLATE.to_string(),
+        LoaderTarget::Worker => {
+            CLASSIC_SCRIPT_TEMPLATE.replace("{{BASE_URL}}", "self.location.href")
+        }
+        LoaderTarget::NoModules => {
+            CLASSIC_SCRIPT_TEMPLATE.replace("{{BASE_URL}}", "document.currentScript.src")
+        }
+        LoaderTarget::Custom(template) => template.clone(),
+    };
+    template
+        .replace("{{MODULES}}", &json(&loader_modules))
+        .replace("{{EXPORTS}}", &exports.join(", "))
+        .replace("{{RUNTIME}}", runtime.trim_end())
+}
@@ -0,0 +1,401 @@
+//! Splits a WebAssembly module into a main module and separately-loaded split
+//! modules, at the split points defined by the `wasm_split` attribute.
+//!
+//! The input must be a relocatable module, i.e. linked with
+//! `-Clink-args=--emit-relocs`.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! let input = std::fs::read("main.wasm")?;
+//! let output = wasm_split_lib::Splitter::new().split(&input)?;
+//! output.write_to_dir("dist".as_ref())?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::path::Path;
+
+use anyhow::{bail, Result};
+
+mod bindgen;
+mod config;
+mod dep_graph;
+mod emit;
+mod graph;
+mod javascript;
+mod manifest;
+mod profile;
+mod read;
+mod report;
+mod split_point;
+mod why;
+
+pub use config::{Pin, SplitConfig};
+pub use graph::{GraphFormat, GraphLevel};
+pub use javascript::LoaderTarget;
+pub use manifest::{Manifest, ManifestEntry};
+pub use profile::Profile;
+pub use report::{ChunkMergeReport, ModuleKind, ModuleReport, SplitReport, SymbolReport};
+pub use why::{why, WhyPath, WhyResult, WhyRoot};
+
+/// Builder for splitting a module.
+#[derive(Clone, Debug)]
+pub struct Splitter {
+    verbose: bool,
+    report: bool,
+    graph: Option<GraphFormat>,
+    graph_level: GraphLevel,
+    min_chunk_size: usize,
+    config: SplitConfig,
+    profile: Option<Profile>,
+    instrument: bool,
+    hash_file_names: bool,
+    target: LoaderTarget,
+    retry_attempts: u32,
+    retry_backoff_ms: u32,
+}
+
+impl Default for Splitter {
+    fn default() -> Self {
+        Self {
+            verbose: false,
+            report: false,
+            graph: None,
+            graph_level: GraphLevel::default(),
+            min_chunk_size: 0,
+            config: SplitConfig::default(),
+            profile: None,
+            instrument: false,
+            hash_file_names: false,
+            target: LoaderTarget::Web,
+            retry_attempts: 3,
+            retry_backoff_ms: 500,
+        }
+    }
+}
+
+impl Splitter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Include verbose split information in the output, as
+    /// `SplitOutput::verbose_info`.
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Include a `SplitReport` in the output.
+    pub fn report(mut self, report: bool) -> Self {
+        self.report = report;
+        self
+    }
+
+    /// Include a visualization of the dependency graph in the output.
+    pub fn graph(mut self, format: Option<GraphFormat>) -> Self {
+        self.graph = format;
+        self
+    }
+
+    /// Whether the graph shows output modules or individual symbols.
+    pub fn graph_level(mut self, level: GraphLevel) -> Self {
+        self.graph_level = level;
+        self
+    }
+
+    /// Merge chunks (code shared by more than one split module) with less
+    /// than this many bytes of code and data into a larger chunk or into
+    /// main, to avoid many small fetches.
+    pub fn min_chunk_size(mut self, min_chunk_size: usize) -> Self {
+        self.min_chunk_size = min_chunk_size;
+        self
+    }
+
+    /// Manual overrides for the placement of symbols.
+    pub fn config(mut self, config: SplitConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Place functions according to a profile of the functions called during
+    /// typical sessions. Split functions that were called are moved to main,
+    /// and main functions that were not called are moved to a `main_cold`
+    /// module, which is loaded in the background once main starts.
+    ///
+    /// If main calls a cold function before the background load completes,
+    /// the loader fetches and compiles `main_cold` synchronously, with a
+    /// synchronous `XMLHttpRequest` on the web. This blocks the thread, and
+    /// fails where synchronous requests are unavailable, as in Deno and
+    /// service workers, or where browsers limit the size of modules compiled
+    /// synchronously on the main thread. Profiles should therefore include
+    /// every function called during startup.
+    pub fn profile(mut self, profile: Option<Profile>) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Record which functions are called, for collecting a profile. Every
+    /// function increments a call counter, which the generated loader returns
+    /// from `profileCounters()`, and as a bitmap of the called functions from
+    /// `profileBitmap()`. The output includes a symbol map naming the function
+    /// of each counter and bit, for `Profile::from_bitmaps`.
+    pub fn instrument(mut self, instrument: bool) -> Self {
+        self.instrument = instrument;
+        self
+    }
+
+    /// Name the split modules and chunks `<name>.<hash>.wasm` after their
+    /// contents, so that they can be served with immutable cache headers, and
+    /// include a `Manifest` in the output. The main module keeps its name,
+    /// since it is processed further by `wasm-bindgen`.
+    pub fn hash_file_names(mut self, hash_file_names: bool) -> Self {
+        self.hash_file_names = hash_file_names;
+        self
+    }
+
+    /// Environment in which the generated loader runs.
+    pub fn target(mut self, target: LoaderTarget) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Default number of times the generated loader fetches a module before
+    /// reporting a failure. This applies to each load, which the program
+    /// repeats according to `wasm_split::RetryPolicy`.
+    pub fn retry_attempts(mut self, retry_attempts: u32) -> Self {
+        self.retry_attempts = retry_attempts;
+        self
+    }
+
+    /// Default delay in milliseconds before the first retry of a failed fetch.
+    /// The delay doubles after each failed attempt.
+    pub fn retry_backoff_ms(mut self, retry_backoff_ms: u32) -> Self {
+        self.retry_backoff_ms = retry_backoff_ms;
+        self
+    }
+
+    /// Splits `input`, which must be a relocatable wasm module.
+    pub fn split(&self, input: &[u8]) -> Result<SplitOutput> {
+        let module = read::InputModule::parse(input)?;
+        let dep_graph = dep_graph::get_dependencies(&module)?;
+        let split_points = split_point::get_split_points(&module)?;
+        let split_program_info = split_point::compute_split_modules(
+            &module,
+            &dep_graph,
+            &split_points,
+            &split_point::SplitOptions {
+                min_chunk_size: self.min_chunk_size,
+                config: self.config.clone(),
+                profile: self.profile.clone(),
+            },
+        )?;
+        let instrumentation = if self.instrument {
+            Some(profile::Instrumentation::new(&module)?)
+        } else {
+            None
+        };
+
+        let verbose_info = self.verbose.then(|| {
+            let mut info = format!("split_points={split_points:?}\n");
+            for (name, split_deps) in split_program_info.output_modules.iter() {
+                info.push_str(&split_deps.describe(format!("{:?}", name).as_str(), &module));
+            }
+            for merge in split_program_info.chunk_merges.iter() {
+                info.push_str(&format!("{merge}\n"));
+            }
+            let mut split_parents: Vec<_> = split_program_info.split_parents.iter().collect();
+            split_parents.sort();
+            for (split, parent) in split_parents {
+                info.push_str(&format!("Split module {split} is nested in {parent}\n"));
+            }
+            info
+        });
+
+        let mut modules = Vec::new();
+        emit::emit_modules(
+            &module,
+            &split_program_info,
+            instrumentation.as_ref(),
+            &mut |output_module_index: usize, data: &[u8]| -> Result<()> {
+                let identifier = &split_program_info.output_modules[output_module_index].0;
+                modules.push(OutputModule {
+                    name: identifier.name(),
+                    wasm: data.to_vec(),
+                    content_hash: (self.hash_file_names
+                        && *identifier != split_point::SplitModuleIdentifier::Main)
+                        .then(|| manifest::content_hash(data)),
+                });
+                Ok(())
+            },
+        )?;
+
+        let report = if self.report {
+            Some(report::make_report(
+                &module,
+                &split_program_info,
+                &modules
+                    .iter()
+                    .map(|module| module.wasm.len())
+                    .collect::<Vec<_>>(),
+                &emit::get_table_ranges(&module, &split_program_info)?,
+            ))
+        } else {
+            None
+        };
+
+        let graph = match self.graph {
+            Some(format) => Some(Graph {
+                format,
+                contents: graph::make_graph(
+                    &module,
+                    &dep_graph,
+                    &dep_graph::get_indirect_dependencies(&module)?,
+                    &split_program_info,
+                    format,
+                    self.graph_level,
+                ),
+            }),
+            None => None,
+        };
+
+        // The loader is imported by the JavaScript of `wasm-bindgen`, which is
+        // placed alongside main.
+        let Some(loader_file_name) = split_program_info
+            .loader_module
+            .strip_prefix("./")
+            .filter(|file_name| !file_name.contains('/'))
+        else {
+            bail!(
+                "Loader module {:?} must name a file in the same directory, e.g. \"./__wasm_split.js\"",
+                split_program_info.loader_module
+            );
+        };
+        let loader = javascript::generate_loader(
+            &split_program_info,
+            &modules,
+            &self.target,
+            instrumentation.as_ref(),
+            self.retry_attempts,
+            self.retry_backoff_ms,
+        );
+
+        Ok(SplitOutput {
+            manifest: self.hash_file_names.then(|| Manifest::new(&modules)),
+            modules,
+            report,
+            graph,
+            profile_symbols: instrumentation
+                .as_ref()
+                .map(|instrumentation| instrumentation.symbol_map()),
+            loader_file_name: loader_file_name.to_string(),
+            loader,
+            verbose_info,
+            orphan_split_exports: split_program_info
+                .orphan_exports
+                .iter()
+                .map(|&export_id| module.exports[export_id].name.to_string())
+                .collect(),
+        })
+    }
+
+    /// Splits `bindgen_output`, the wasm module produced by running
+    /// `wasm-bindgen --no-demangle --keep-lld-exports` on `original`, which
+    /// must be a relocatable wasm module.
+    ///
+    /// The relocations of `original` are mapped to `bindgen_output` by
+    /// function name. The main module of the result replaces the
+    /// `wasm-bindgen` wasm output, and is loaded by its JavaScript bindings.
+    ///
+    /// The JavaScript bindings themselves are not split: the glue for imports
+    /// and exports used only by split modules stays in them, and split modules
+    /// reach those imports through main.
+    pub fn split_bindgen_output(
+        &self,
+        original: &[u8],
+        bindgen_output: &[u8],
+    ) -> Result<SplitOutput> {
+        let input = bindgen::relocate_bindgen_output(original, bindgen_output)?;
+        self.split(&input)
+    }
+}
+
+/// Result of splitting a module.
+#[derive(Clone, Debug)]
+pub struct SplitOutput {
+    /// The main module followed by the split modules and the chunks shared
+    /// between them.
+    pub modules: Vec<OutputModule>,
+    /// Contents of the loader, which must be placed alongside the modules.
+    pub loader: String,
+    /// File name of the loader, `__wasm_split.js` unless overridden with
+    /// `import_module` in `#[wasm_split]` or the `WASM_SPLIT_IMPORT_MODULE`
+    /// environment variable at build time.
+    pub loader_file_name: String,
+    /// Present if requested with `Splitter::report`.
+    pub report: Option<SplitReport>,
+    /// Present if requested with `Splitter::graph`.
+    pub graph: Option<Graph>,
+    /// Contents of `manifest.json`, present if requested with
+    /// `Splitter::hash_file_names`.
+    pub manifest: Option<Manifest>,
+    /// Contents of `profile_symbols.txt`, present if requested with
+    /// `Splitter::instrument`.
+    pub profile_symbols: Option<String>,
+    /// Symbols of each output module and the reasons they were included,
+    /// present if requested with `Splitter::verbose`.
+    pub verbose_info: Option<String>,
+    /// Names of split exports without a corresponding import, which are
+    /// omitted from the output. These are usually instantiations of generic
+    /// split functions that are never called, but may also indicate a
+    /// mismatch between the `wasm_split` versions used by the program.
+    pub orphan_split_exports: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Graph {
+    pub format: GraphFormat,
+    pub contents: String,
+}
+
+impl SplitOutput {
+    /// Writes the modules, the loader, and the graph, profile symbol
+    /// map and manifest, if any, to `dir`, creating it if necessary.
+    pub fn write_to_dir(&self, dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(dir)?;
+        for module in self.modules.iter() {
+            std::fs::write(dir.join(module.file_name()), &module.wasm)?;
+        }
+        std::fs::write(dir.join(&self.loader_file_name), &self.loader)?;
+        if let Some(graph) = &self.graph {
+            std::fs::write(dir.join(graph.format.file_name()), &graph.contents)?;
+        }
+        if let Some(profile_symbols) = &self.profile_symbols {
+            std::fs::write(dir.join("profile_symbols.txt"), profile_symbols)?;
+        }
+        if let Some(manifest) = &self.manifest {
+            std::fs::write(dir.join("manifest.json"), manifest.to_json())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct OutputModule {
+    /// Name of the module, e.g. `main` or the module name given to
+    /// `wasm_split`.
+    pub name: String,
+    pub wasm: Vec<u8>,
+    /// Hash of `wasm` included in the file name, if requested with
+    /// `Splitter::hash_file_names`.
+    pub content_hash: Option<String>,
+}
+
+impl OutputModule {
+    pub fn file_name(&self) -> String {
+        match &self.content_hash {
+            Some(hash) => format!("{}.{hash}.wasm", self.name),
+            None => self.name.clone() + ".wasm",
+        }
+    }
+}
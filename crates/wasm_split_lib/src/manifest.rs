@@ -0,0 +1,69 @@
+use std::collections::BTreeMap;
+
+use base64::Engine;
+use serde::Serialize;
+use sha2::{Digest, Sha384};
+
+use crate::OutputModule;
+
+/// Describes the emitted modules, for servers and build tools that need to
+/// know the file of each module, e.g. to preload them.
+#[derive(Clone, Debug, Serialize)]
+pub struct Manifest {
+    /// Entries by module name. A sorted map keeps the JSON reproducible.
+    pub modules: BTreeMap<String, ManifestEntry>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ManifestEntry {
+    /// File name of the module, relative to `__wasm_split.js`.
+    pub file: String,
+    pub size: usize,
+    /// Subresource integrity digest of the module, e.g. `sha384-...`. Absent
+    /// for main, which is rewritten by `wasm-bindgen` after splitting.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub integrity: Option<String>,
+}
+
+impl Manifest {
+    pub(crate) fn new(modules: &[OutputModule]) -> Self {
+        Self {
+            modules: modules
+                .iter()
+                .map(|module| {
+                    (
+                        module.name.clone(),
+                        ManifestEntry {
+                            file: module.file_name(),
+                            size: module.wasm.len(),
+                            integrity: module
+                                .content_hash
+                                .is_some()
+                                .then(|| integrity(&module.wasm)),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+/// Returns the subresource integrity digest of `data`.
+pub(crate) fn integrity(data: &[u8]) -> String {
+    format!(
+        "sha384-{}",
+        base64::engine::general_purpose::STANDARD.encode(Sha384::digest(data))
+    )
+}
+
+/// Returns the hash included in content-hashed file names.
+pub(crate) fn content_hash(data: &[u8]) -> String {
+    Sha384::digest(data)[..8]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
@@ -0,0 +1,223 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    num::TryFromIntError,
+};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::{
+    dep_graph::{symbol_name, DepNode},
+    read::{DefinedFunc, GlobalId, InputModule, SymbolIndex},
+};
+
+/// Functions observed while running typical sessions, used to guide
+/// placement.
+///
+/// The file format has one function name (as shown in the split report) per
+/// line. Empty lines and lines starting with `#` are ignored.
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    functions: HashSet<String>,
+}
+
+impl Profile {
+    pub fn parse(contents: &str) -> Self {
+        Self {
+            functions: contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+
+    pub fn contains(&self, function_name: &str) -> bool {
+        self.functions.contains(function_name)
+    }
+
+    /// Combines profile bitmaps returned by `profileBitmap()` in instrumented
+    /// builds. `symbol_map` is the `profile_symbols.txt` file written with the
+    /// instrumented build, which names the function of each bit.
+    pub fn from_bitmaps<'a>(
+        symbol_map: &str,
+        bitmaps: impl IntoIterator<Item = &'a [u8]>,
+    ) -> Result<Self> {
+        let symbols: Vec<&str> = symbol_map.lines().collect();
+        let mut called = vec![false; symbols.len()];
+        for (bitmap_index, bitmap) in bitmaps.into_iter().enumerate() {
+            if bitmap.len() != symbols.len().div_ceil(8) {
+                bail!(
+                    "Bitmap {bitmap_index} has {} bytes, but the symbol map requires {}",
+                    bitmap.len(),
+                    symbols.len().div_ceil(8)
+                );
+            }
+            for (index, called) in called.iter_mut().enumerate() {
+                *called |= bitmap[index / 8] & (1 << (index % 8)) != 0;
+            }
+        }
+        Ok(Self {
+            functions: symbols
+                .into_iter()
+                .zip(called)
+                .filter(|(_, called)| *called)
+                .map(|(symbol, _)| symbol.to_string())
+                .collect(),
+        })
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut functions: Vec<_> = self.functions.iter().collect();
+        functions.sort();
+        for function in functions {
+            writeln!(f, "{function}")?;
+        }
+        Ok(())
+    }
+}
+
+const PAGE_SIZE: u64 = 65536;
+const COUNTER_SIZE: u64 = 8;
+
+/// Call counters added by `Splitter::instrument`.
+///
+/// Counter `i`, a little-endian `u64`, is incremented by a prologue of the
+/// `i`th defined function every time it is called. The counters are in memory
+/// 0 at the original `__heap_base`, which is moved past the counters so that
+/// allocators never hand out their memory. If the counters extend past the
+/// initial memory, the initial memory and `__heap_end` are grown to match.
+///
+/// The generated loader turns the counters into the bitmap returned by
+/// `profileBitmap()`, in which bit `i % 8` of byte `i / 8` is set if counter
+/// `i` is not zero.
+#[derive(Debug)]
+pub(crate) struct Instrumentation {
+    pub counters_address: u32,
+    pub extra_pages: u64,
+    pub function_names: Vec<String>,
+    /// New addresses of the `__heap_base` and `__heap_end` symbols.
+    pub relocated_symbols: HashMap<SymbolIndex, u32>,
+    /// New values of the globals exported as `__heap_base` and `__heap_end`.
+    pub relocated_globals: HashMap<GlobalId, u32>,
+}
+
+/// Returns the index and address of the linker-defined symbol `name`.
+fn find_absolute_symbol(module: &InputModule, name: &str) -> Option<(SymbolIndex, u64)> {
+    module
+        .symbols
+        .iter()
+        .enumerate()
+        .find_map(|(symbol_index, symbol)| match symbol {
+            wasmparser::SymbolInfo::Data {
+                flags,
+                name: symbol_name,
+                symbol: Some(definition),
+            } if *symbol_name == name && flags.contains(wasmparser::SymbolFlags::ABSOLUTE) => {
+                Some((symbol_index, definition.offset as u64))
+            }
+            _ => None,
+        })
+}
+
+impl Instrumentation {
+    pub fn new(module: &InputModule) -> Result<Self> {
+        let Some(memory) = module.memories.first() else {
+            bail!("Instrumentation requires a memory defined by the module");
+        };
+        if memory.memory64 {
+            bail!("Instrumentation does not support 64-bit memories");
+        }
+        let Some((heap_base_symbol, heap_base)) = find_absolute_symbol(module, "__heap_base")
+        else {
+            bail!("Instrumentation requires the __heap_base symbol");
+        };
+        let counters_size = module.defined_funcs.len() as u64 * COUNTER_SIZE;
+        let counters_address = heap_base.next_multiple_of(16);
+        let new_heap_base = (counters_address + counters_size).next_multiple_of(16);
+        let extra_pages = new_heap_base
+            .saturating_sub(memory.initial * PAGE_SIZE)
+            .div_ceil(PAGE_SIZE);
+        if memory
+            .maximum
+            .is_some_and(|maximum| maximum < memory.initial + extra_pages)
+        {
+            bail!("Instrumentation requires {extra_pages} more pages than the maximum memory size");
+        }
+        let heap_end = find_absolute_symbol(module, "__heap_end");
+        let mut relocated_symbols = HashMap::from([(heap_base_symbol, new_heap_base)]);
+        if let Some((heap_end_symbol, heap_end)) = heap_end {
+            relocated_symbols.insert(heap_end_symbol, heap_end + extra_pages * PAGE_SIZE);
+        }
+        let relocated_symbols = relocated_symbols
+            .into_iter()
+            .map(|(symbol_index, address)| Ok((symbol_index, u32::try_from(address)?)))
+            .collect::<Result<HashMap<_, _>, TryFromIntError>>()
+            .map_err(|_| anyhow!("No address space left for the instrumentation counters"))?;
+        let relocated_globals = module
+            .exports
+            .iter()
+            .filter(|export| export.kind == wasmparser::ExternalKind::Global)
+            .filter_map(|export| {
+                let symbol_index = match export.name {
+                    "__heap_base" => heap_base_symbol,
+                    "__heap_end" => heap_end?.0,
+                    _ => return None,
+                };
+                Some((export.index as GlobalId, relocated_symbols[&symbol_index]))
+            })
+            .collect();
+        let num_imported_funcs = module.imported_funcs.len();
+        Ok(Self {
+            counters_address: counters_address as u32,
+            extra_pages,
+            function_names: (0..module.defined_funcs.len())
+                .map(|index| symbol_name(module, &DepNode::Function(num_imported_funcs + index)))
+                .collect(),
+            relocated_symbols,
+            relocated_globals,
+        })
+    }
+
+    /// Returns the symbol map, which names the function of each counter, and
+    /// of each bit of the profile bitmap, one per line.
+    pub fn symbol_map(&self) -> String {
+        self.function_names
+            .iter()
+            .map(|name| format!("{name}\n"))
+            .collect()
+    }
+
+    /// Returns `body`, the encoding of `func`, with a prologue that increments
+    /// the counter of the defined function `index`.
+    pub fn add_prologue(&self, func: &DefinedFunc, index: usize, body: &[u8]) -> Result<Vec<u8>> {
+        use wasm_encoder::{Encode, Instruction, MemArg};
+        let address = (self.counters_address as u64 + index as u64 * COUNTER_SIZE) as i32;
+        let mem_arg = MemArg {
+            offset: 0,
+            align: COUNTER_SIZE.ilog2(),
+            memory_index: 0,
+        };
+        let mut prologue = Vec::new();
+        for instruction in [
+            Instruction::I32Const(address),
+            Instruction::I32Const(address),
+            Instruction::I64Load(mem_arg),
+            Instruction::I64Const(1),
+            Instruction::I64Add,
+            Instruction::I64Store(mem_arg),
+        ] {
+            instruction.encode(&mut prologue);
+        }
+        let operators_offset =
+            func.body.get_operators_reader()?.original_position() - func.body.range().start;
+        let mut output = Vec::with_capacity(body.len() + prologue.len());
+        output.extend_from_slice(&body[..operators_offset]);
+        output.extend_from_slice(&prologue);
+        output.extend_from_slice(&body[operators_offset..]);
+        Ok(output)
+    }
+}
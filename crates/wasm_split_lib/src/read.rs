@@ -6,14 +6,11 @@ pub use wasmparser::{
     SymbolInfo, Table, TagType,
 };
 use wasmparser::{Payload, TypeRef};
-pub type InputRange = Range<usize>;
 
 pub struct CustomSection<'a> {
     pub name: &'a str,
     pub data_offset: usize,
     pub data: &'a [u8],
-    pub section_index: usize,
-    pub range: InputRange,
 }
 
 pub type FuncTypeId = usize;
@@ -60,13 +57,13 @@ fn convert_name_map<'a>(name_map: wasmparser::NameMap<'a>) -> Result<HashMap<usi
 fn convert_indirect_name_map<'a>(
     indirect_name_map: wasmparser::IndirectNameMap<'a>,
 ) -> Result<HashMap<usize, wasmparser::NameMap<'a>>> {
-    Ok(indirect_name_map
+    indirect_name_map
         .into_iter()
         .map(|r| -> Result<(usize, wasmparser::NameMap<'a>)> {
             let indirect_naming = r?;
             Ok((indirect_naming.index as usize, indirect_naming.names))
         })
-        .collect::<Result<HashMap<_, _>, _>>()?)
+        .collect::<Result<HashMap<_, _>, _>>()
 }
 
 impl<'a> Names<'a> {
@@ -281,16 +278,14 @@ impl<'a> InputModule<'a> {
                 Payload::CodeSectionEntry(body) => {
                     let index = module.defined_funcs.len();
                     module.defined_funcs.push(DefinedFunc {
-                        type_id: function_types[index].clone(),
+                        type_id: function_types[index],
                         body,
                     });
                 }
                 Payload::CustomSection(reader) => {
                     module.custom_sections.push(CustomSection {
                         name: reader.name(),
-                        section_index,
                         data: reader.data(),
-                        range: reader.range(),
                         data_offset: reader.data_offset(),
                     });
                     section_index += 1;
@@ -309,11 +304,8 @@ impl<'a> InputModule<'a> {
                 let reader =
                     wasmparser::LinkingSectionReader::new(section.data, section.data_offset)?;
                 for subsection in reader.subsections() {
-                    match subsection? {
-                        wasmparser::Linking::SymbolTable(map) => {
-                            module.symbols = map.into_iter().collect::<Result<Vec<_>, _>>()?;
-                        }
-                        _ => {}
+                    if let wasmparser::Linking::SymbolTable(map) = subsection? {
+                        module.symbols = map.into_iter().collect::<Result<Vec<_>, _>>()?;
                     }
                 }
             } else if section.name.starts_with("reloc.") {
@@ -0,0 +1,154 @@
+use std::{collections::HashMap, ops::Range};
+
+use serde::Serialize;
+
+use crate::{
+    dep_graph::{symbol_name, symbol_size, DepNode},
+    read::InputModule,
+    split_point::{SplitModuleIdentifier, SplitProgramInfo},
+};
+
+/// Machine-readable description of how a module was split.
+#[derive(Clone, Debug, Serialize)]
+pub struct SplitReport {
+    pub modules: Vec<ModuleReport>,
+    /// Chunks that were merged into other modules due to their size.
+    pub chunk_merges: Vec<ChunkMergeReport>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ChunkMergeReport {
+    pub chunk: String,
+    pub size: usize,
+    pub into: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ModuleReport {
+    /// Name of the output module, without the `.wasm` extension.
+    pub name: String,
+    pub kind: ModuleKind,
+    /// For chunks, the split modules that share the chunk.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub splits: Vec<String>,
+    /// For nested split modules, the split module that loads this one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// Size of the emitted module.
+    pub size: usize,
+    /// Total size of the function bodies in the module.
+    pub code_size: usize,
+    /// Total size of the data symbols in the module.
+    pub data_size: usize,
+    pub functions: Vec<SymbolReport>,
+    pub data_symbols: Vec<SymbolReport>,
+    /// Functions called by the module that are defined in other modules.
+    pub shared_imports: Vec<String>,
+    /// Range of indirect function table entries defined by the module.
+    pub indirect_table_range: Option<Range<usize>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModuleKind {
+    Main,
+    Split,
+    Chunk,
+    Cold,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SymbolReport {
+    pub name: String,
+    pub size: usize,
+    /// Chain of symbols through which this symbol was reached, starting with
+    /// its immediate parent and ending with a root of the module.
+    pub parents: Vec<String>,
+}
+
+impl SplitReport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+pub(crate) fn make_report(
+    module: &InputModule,
+    program_info: &SplitProgramInfo,
+    module_sizes: &[usize],
+    table_ranges: &[Range<usize>],
+) -> SplitReport {
+    let symbol_report = |node: &DepNode, parents: &HashMap<DepNode, DepNode>| {
+        let mut chain = Vec::new();
+        let mut current = node;
+        while let Some(parent) = parents.get(current) {
+            chain.push(symbol_name(module, parent));
+            current = parent;
+        }
+        SymbolReport {
+            name: symbol_name(module, node),
+            size: symbol_size(module, node),
+            parents: chain,
+        }
+    };
+    let modules = program_info
+        .output_modules
+        .iter()
+        .enumerate()
+        .map(|(output_module_index, (identifier, info))| {
+            let (kind, splits) = match identifier {
+                SplitModuleIdentifier::Main => (ModuleKind::Main, Vec::new()),
+                SplitModuleIdentifier::Split(_) => (ModuleKind::Split, Vec::new()),
+                SplitModuleIdentifier::Chunk(splits) => (ModuleKind::Chunk, splits.clone()),
+                SplitModuleIdentifier::Cold => (ModuleKind::Cold, Vec::new()),
+            };
+            let mut symbols: Vec<_> = info.included_symbols.iter().collect();
+            symbols.sort();
+            let mut functions = Vec::new();
+            let mut data_symbols = Vec::new();
+            for node in symbols {
+                match node {
+                    DepNode::Function(_) => functions.push(symbol_report(node, &info.parents)),
+                    DepNode::DataSymbol(_) => data_symbols.push(symbol_report(node, &info.parents)),
+                }
+            }
+            let mut shared_imports: Vec<_> = info.shared_imports.iter().collect();
+            shared_imports.sort();
+            let table_range = &table_ranges[output_module_index];
+            ModuleReport {
+                name: identifier.name(),
+                kind,
+                splits,
+                parent: match identifier {
+                    SplitModuleIdentifier::Split(split) => {
+                        program_info.split_parents.get(split).cloned()
+                    }
+                    _ => None,
+                },
+                size: module_sizes[output_module_index],
+                code_size: functions.iter().map(|f| f.size).sum(),
+                data_size: data_symbols.iter().map(|d| d.size).sum(),
+                functions,
+                data_symbols,
+                shared_imports: shared_imports
+                    .into_iter()
+                    .map(|&func_id| symbol_name(module, &DepNode::Function(func_id)))
+                    .collect(),
+                indirect_table_range: (!table_range.is_empty()).then(|| table_range.clone()),
+            }
+        })
+        .collect();
+    let chunk_merges = program_info
+        .chunk_merges
+        .iter()
+        .map(|merge| ChunkMergeReport {
+            chunk: merge.chunk.name(),
+            size: merge.size,
+            into: merge.into.name(),
+        })
+        .collect();
+    SplitReport {
+        modules,
+        chunk_merges,
+    }
+}
@@ -0,0 +1,949 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Write;
+
+use crate::config::SplitConfig;
+use crate::dep_graph::{all_symbols, symbol_name, symbol_size, DepGraph, DepNode};
+use crate::emit::get_indirect_functions;
+use crate::profile::Profile;
+use crate::read::{ExportId, ImportId, InputFuncId, InputModule};
+use anyhow::{anyhow, bail};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SplitPoint {
+    pub module_name: String,
+    pub import: ImportId,
+    pub import_func: InputFuncId,
+    pub export: ExportId,
+    pub export_func: InputFuncId,
+}
+
+const SPLIT_IMPORT_PATTERN: &str = "__wasm_split_00(.*)00_import_([0-9a-f]{32})";
+const SPLIT_EXPORT_PATTERN: &str = "__wasm_split_00(.*)00_export_([0-9a-f]{32})";
+
+pub fn get_split_points(module: &InputModule) -> anyhow::Result<Vec<SplitPoint>> {
+    macro_rules! process_imports_or_exports {
+        ($pattern:expr, $map:ident, $member:ident, $id_ty:ty) => {
+            let mut $map = HashMap::<(String, String), $id_ty>::new();
+            {
+                lazy_static! {
+                    static ref PATTERN: Regex = Regex::new($pattern).unwrap();
+                }
+
+                for (id, item) in module.$member.iter().enumerate() {
+                    let Some(captures) = PATTERN.captures(&item.name) else {
+                        continue;
+                    };
+                    let (_, [module_name, unique_id]) = captures.extract();
+                    $map.insert((module_name.into(), unique_id.into()), id);
+                }
+            }
+        };
+    }
+
+    process_imports_or_exports!(SPLIT_IMPORT_PATTERN, import_map, imports, ImportId);
+    process_imports_or_exports!(SPLIT_EXPORT_PATTERN, export_map, exports, ExportId);
+
+    let mut split_points = import_map
+        .drain()
+        .map(|(key, import_id)| -> anyhow::Result<SplitPoint> {
+            let export_id = export_map.remove(&key).ok_or_else(|| {
+                anyhow::anyhow!("No corresponding export for split import {key:?}")
+            })?;
+            let export = module.exports[export_id];
+            let wasmparser::Export {
+                kind: wasmparser::ExternalKind::Func,
+                index,
+                ..
+            } = export
+            else {
+                bail!("Expected exported function but received: {export:?}");
+            };
+            let &import_func = module.imported_func_map.get(&import_id).ok_or_else(|| {
+                anyhow!(
+                    "Expected imported function but received: {:?}",
+                    &module.imports[import_id]
+                )
+            })?;
+            Ok(SplitPoint {
+                module_name: key.0,
+                import: import_id,
+                import_func,
+                export: export_id,
+                export_func: index as InputFuncId,
+            })
+        })
+        .collect::<anyhow::Result<Vec<SplitPoint>>>()?;
+    // Keep the order of the imports, rather than that of the map.
+    split_points.sort_by_key(|split_point| split_point.import);
+
+    Ok(split_points)
+}
+
+/// Returns the split exports without a corresponding import, in the order of
+/// the exports. These correspond to split points that are never called (e.g.
+/// unused instantiations of a generic split function), for which the linker
+/// has already removed the import. They are dropped from all output modules.
+pub fn get_orphan_split_exports(
+    module: &InputModule,
+    split_points: &[SplitPoint],
+) -> Vec<ExportId> {
+    lazy_static! {
+        static ref EXPORT_PATTERN: Regex = Regex::new(SPLIT_EXPORT_PATTERN).unwrap();
+    }
+    let split_exports: HashSet<ExportId> = split_points
+        .iter()
+        .map(|split_point| split_point.export)
+        .collect();
+    module
+        .exports
+        .iter()
+        .enumerate()
+        .filter(|(export_id, export)| {
+            EXPORT_PATTERN.is_match(export.name) && !split_exports.contains(export_id)
+        })
+        .map(|(export_id, _)| export_id)
+        .collect()
+}
+
+/// Module from which split points and other functions of the loader are
+/// imported unless overridden with `import_module` in `#[wasm_split]` or the
+/// `WASM_SPLIT_IMPORT_MODULE` environment variable.
+pub const DEFAULT_LOADER_MODULE: &str = "./__wasm_split.js";
+
+/// Returns the module from which the program imports the loader functions,
+/// which all have names starting with `__wasm_split_`.
+pub fn get_loader_module(module: &InputModule) -> anyhow::Result<String> {
+    let mut loader_modules: Vec<&str> = module
+        .imports
+        .iter()
+        .filter(|import| import.name.starts_with("__wasm_split_"))
+        .map(|import| import.module)
+        .collect();
+    loader_modules.sort();
+    loader_modules.dedup();
+    match loader_modules[..] {
+        [] => Ok(DEFAULT_LOADER_MODULE.to_string()),
+        [loader_module] => Ok(loader_module.to_string()),
+        _ => bail!("Loader functions are imported from more than one module: {loader_modules:?}"),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ReachabilityGraph {
+    pub reachable: HashSet<DepNode>,
+    pub parents: HashMap<DepNode, DepNode>,
+}
+
+#[derive(Debug, Default)]
+pub struct OutputModuleInfo {
+    pub included_symbols: HashSet<DepNode>,
+    pub parents: HashMap<DepNode, DepNode>,
+    pub shared_imports: HashSet<InputFuncId>,
+    pub split_points: Vec<SplitPoint>,
+}
+
+impl OutputModuleInfo {
+    /// Describes the symbols of the module, with the path through which each
+    /// function was reached.
+    pub fn describe(&self, module_name: &str, module: &InputModule) -> String {
+        describe_deps(module_name, module, &self.included_symbols, &self.parents)
+    }
+}
+
+impl From<ReachabilityGraph> for OutputModuleInfo {
+    fn from(reachability: ReachabilityGraph) -> Self {
+        Self {
+            included_symbols: reachability.reachable,
+            parents: reachability.parents,
+            ..Default::default()
+        }
+    }
+}
+
+fn describe_deps(
+    module_name: &str,
+    module: &InputModule,
+    reachable: &HashSet<DepNode>,
+    parents: &HashMap<DepNode, DepNode>,
+) -> String {
+    let format_dep = |dep: &DepNode| match dep {
+        DepNode::Function(index) => {
+            let name = module.names.functions.get(index);
+            format!("func[{index}] <{name:?}>")
+        }
+        DepNode::DataSymbol(index) => {
+            let symbol = module.symbols[*index];
+            format!("{symbol:?}")
+        }
+    };
+
+    let mut output = String::new();
+    writeln!(output, "SPLIT: ============== {module_name}").unwrap();
+    let mut total_size: usize = 0;
+    let mut reachable: Vec<_> = reachable.iter().collect();
+    reachable.sort();
+    for dep in reachable {
+        let DepNode::Function(index) = dep else {
+            continue;
+        };
+        let size = index
+            .checked_sub(module.imported_funcs.len())
+            .map(|defined_index| module.defined_funcs[defined_index].body.range().len())
+            .unwrap_or_default();
+        total_size += size;
+        writeln!(output, "   {} size={size:?}", format_dep(dep)).unwrap();
+        let mut node = dep;
+        while let Some(parent) = parents.get(node) {
+            writeln!(output, "      <== {}", format_dep(parent)).unwrap();
+            node = parent;
+        }
+    }
+    writeln!(
+        output,
+        "SPLIT: ============== {module_name}  : total size: {total_size}"
+    )
+    .unwrap();
+    output
+}
+
+pub fn find_reachable_deps(
+    deps: &DepGraph,
+    roots: &HashSet<DepNode>,
+    exclude: &HashSet<DepNode>,
+) -> ReachabilityGraph {
+    // Roots and children are visited in sorted order so that the recorded
+    // parents do not depend on the iteration order of the sets.
+    let mut queue: VecDeque<DepNode> = roots.iter().copied().collect();
+    queue.make_contiguous().sort();
+    let mut seen = HashSet::<DepNode>::new();
+    let mut parents = HashMap::<DepNode, DepNode>::new();
+    while let Some(node) = queue.pop_front() {
+        seen.insert(node);
+        let Some(children) = deps.get(&node) else {
+            continue;
+        };
+        let mut children: Vec<_> = children.iter().collect();
+        children.sort();
+        for child in children {
+            if seen.contains(child) || exclude.contains(child) {
+                continue;
+            }
+            parents.entry(*child).or_insert(node);
+            queue.push_back(*child);
+        }
+    }
+    ReachabilityGraph {
+        reachable: seen,
+        parents,
+    }
+}
+
+pub fn get_main_module_roots(
+    module: &InputModule,
+    split_points: &[SplitPoint],
+) -> HashSet<DepNode> {
+    let orphan_exports = get_orphan_split_exports(module, split_points);
+    let mut roots: HashSet<DepNode> = HashSet::new();
+    if let Some(id) = module.start {
+        roots.insert(DepNode::Function(id));
+    }
+    for (export_id, export) in module.exports.iter().enumerate() {
+        if orphan_exports.contains(&export_id) {
+            continue;
+        }
+        let wasmparser::Export {
+            index,
+            kind: wasmparser::ExternalKind::Func,
+            ..
+        } = export
+        else {
+            continue;
+        };
+        roots.insert(DepNode::Function(*index as usize));
+    }
+    for func_id in 0..module.imported_funcs.len() {
+        roots.insert(DepNode::Function(func_id));
+    }
+    for split_point in split_points.iter() {
+        roots.remove(&DepNode::Function(split_point.export_func));
+        roots.remove(&DepNode::Function(split_point.import_func));
+    }
+    roots
+}
+
+pub fn get_split_points_by_module(
+    split_points: &[SplitPoint],
+) -> HashMap<String, Vec<&SplitPoint>> {
+    split_points
+        .iter()
+        .fold(HashMap::new(), |mut map, split_point| {
+            map.entry(split_point.module_name.clone())
+                .or_insert_with(Vec::new)
+                .push(split_point);
+            map
+        })
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub enum SplitModuleIdentifier {
+    Main,
+    Split(String),
+    Chunk(Vec<String>),
+    /// Functions reachable from main that were not called in the profile.
+    /// This module is fetched in the background once main starts, and
+    /// instantiated synchronously when main first calls one of its functions.
+    Cold,
+}
+
+impl SplitModuleIdentifier {
+    pub fn name(&self) -> String {
+        match self {
+            Self::Main => "main".to_string(),
+            Self::Split(name) => name.clone(),
+            Self::Chunk(names) => names.join("_"),
+            Self::Cold => "main_cold".to_string(),
+        }
+    }
+}
+
+/// Options that control how symbols are assigned to output modules.
+#[derive(Debug, Default, Clone)]
+pub struct SplitOptions {
+    /// Chunks with less code and data than this, in bytes, are merged into a
+    /// chunk shared by a superset of their split modules, or into main.
+    pub min_chunk_size: usize,
+    pub config: SplitConfig,
+    pub profile: Option<Profile>,
+}
+
+/// Records that a chunk was merged into another module.
+#[derive(Debug, Clone)]
+pub struct ChunkMerge {
+    pub chunk: SplitModuleIdentifier,
+    pub size: usize,
+    pub into: SplitModuleIdentifier,
+}
+
+impl std::fmt::Display for ChunkMerge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Merged chunk {} ({} bytes, below the minimum chunk size) into {}",
+            self.chunk.name(),
+            self.size,
+            self.into.name()
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SplitProgramInfo {
+    pub output_modules: Vec<(SplitModuleIdentifier, OutputModuleInfo)>,
+    pub output_module_identifiers: HashMap<SplitModuleIdentifier, usize>,
+    pub shared_funcs: HashSet<InputFuncId>,
+    pub symbol_output_module: HashMap<DepNode, usize>,
+    pub chunk_merges: Vec<ChunkMerge>,
+    /// Parent of each nested split module, which is loaded before it.
+    pub split_parents: HashMap<String, String>,
+    /// Module from which main imports the loader functions, see
+    /// `get_loader_module`.
+    pub loader_module: String,
+    /// Split exports that are omitted from main, see
+    /// `get_orphan_split_exports`.
+    pub orphan_exports: Vec<ExportId>,
+}
+
+/// Returns the parent of `split`, the parent's parent, and so on.
+pub fn split_ancestors<'a>(
+    split_parents: &'a HashMap<String, String>,
+    split: &str,
+) -> impl Iterator<Item = &'a String> {
+    std::iter::successors(split_parents.get(split), |parent| {
+        split_parents.get(parent.as_str())
+    })
+}
+
+/// Determines the parent of each nested split module, i.e. one whose split
+/// points are only called from a single other split module. Split modules
+/// whose split points are called from main, or from several split modules,
+/// are loaded directly and have no parent.
+///
+/// The reachable sets must still include the split point imports.
+fn get_split_module_parents(
+    split_points: &[SplitPoint],
+    main_reachable: &HashSet<DepNode>,
+    split_module_candidates: &HashMap<String, ReachabilityGraph>,
+) -> HashMap<String, String> {
+    let mut callers = HashMap::<&str, HashSet<&str>>::new();
+    let mut called_from_main = HashSet::<&str>::new();
+    for split_point in split_points {
+        let import = DepNode::Function(split_point.import_func);
+        let module_name = split_point.module_name.as_str();
+        if main_reachable.contains(&import) {
+            called_from_main.insert(module_name);
+        }
+        let module_callers = callers.entry(module_name).or_default();
+        for (caller, deps) in split_module_candidates.iter() {
+            if caller != module_name && deps.reachable.contains(&import) {
+                module_callers.insert(caller);
+            }
+        }
+    }
+    let mut split_parents: HashMap<String, String> = callers
+        .into_iter()
+        .filter(|(module_name, _)| !called_from_main.contains(module_name))
+        .filter_map(|(module_name, module_callers)| {
+            let mut module_callers = module_callers.into_iter();
+            match (module_callers.next(), module_callers.next()) {
+                (Some(parent), None) => Some((module_name.to_string(), parent.to_string())),
+                _ => None,
+            }
+        })
+        .collect();
+
+    // Break cycles of split modules that are only called from each other,
+    // which could otherwise never finish loading. Modules whose ancestors lead
+    // into a cycle without returning to them keep their parent.
+    let mut modules: Vec<String> = split_parents.keys().cloned().collect();
+    modules.sort();
+    for module_name in modules {
+        let mut seen = HashSet::from([module_name.as_str()]);
+        let mut current = module_name.as_str();
+        let in_cycle = loop {
+            let Some(parent) = split_parents.get(current) else {
+                break false;
+            };
+            if *parent == module_name {
+                break true;
+            }
+            if !seen.insert(parent.as_str()) {
+                break false;
+            }
+            current = parent.as_str();
+        };
+        if in_cycle {
+            split_parents.remove(&module_name);
+        }
+    }
+    split_parents
+}
+
+/// Merges chunks smaller than `min_chunk_size` into the smallest chunk shared
+/// by a superset of the same split modules, which is loaded whenever the
+/// merged chunk would have been, or otherwise into main.
+fn merge_small_chunks(
+    module: &InputModule,
+    split_module_contents: &mut HashMap<SplitModuleIdentifier, OutputModuleInfo>,
+    min_chunk_size: usize,
+) -> Vec<ChunkMerge> {
+    let mut chunks: Vec<Vec<String>> = split_module_contents
+        .keys()
+        .filter_map(|identifier| match identifier {
+            SplitModuleIdentifier::Chunk(splits) => Some(splits.clone()),
+            _ => None,
+        })
+        .collect();
+    // Process chunks shared by more split modules first, so that the chunks
+    // they may be merged into are already final.
+    chunks.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+
+    let mut merges = Vec::new();
+    for splits in chunks {
+        let identifier = SplitModuleIdentifier::Chunk(splits);
+        let size: usize = split_module_contents[&identifier]
+            .included_symbols
+            .iter()
+            .map(|symbol| symbol_size(module, symbol))
+            .sum();
+        if size >= min_chunk_size {
+            continue;
+        }
+        let SplitModuleIdentifier::Chunk(splits) = &identifier else {
+            unreachable!();
+        };
+        let into = split_module_contents
+            .keys()
+            .filter_map(|other| match other {
+                SplitModuleIdentifier::Chunk(other_splits)
+                    if other_splits.len() > splits.len()
+                        && splits.iter().all(|split| other_splits.contains(split)) =>
+                {
+                    Some(other_splits)
+                }
+                _ => None,
+            })
+            .min_by_key(|other_splits| (other_splits.len(), *other_splits))
+            .map(|other_splits| SplitModuleIdentifier::Chunk(other_splits.clone()))
+            .unwrap_or(SplitModuleIdentifier::Main);
+        let contents = split_module_contents.remove(&identifier).unwrap();
+        let into_contents = split_module_contents.get_mut(&into).unwrap();
+        into_contents
+            .included_symbols
+            .extend(contents.included_symbols);
+        into_contents.parents.extend(contents.parents);
+        merges.push(ChunkMerge {
+            chunk: identifier,
+            size,
+            into,
+        });
+    }
+    merges
+}
+
+fn is_split_point_func(split_points: &[SplitPoint], symbol: &DepNode) -> bool {
+    split_points.iter().any(|split_point| {
+        *symbol == DepNode::Function(split_point.import_func)
+            || *symbol == DepNode::Function(split_point.export_func)
+    })
+}
+
+/// Returns the symbols matching the `force_main` patterns, which are added to
+/// the main module roots.
+fn get_forced_main_roots(
+    module: &InputModule,
+    config: &SplitConfig,
+    split_points: &[SplitPoint],
+) -> anyhow::Result<HashSet<DepNode>> {
+    let mut roots = HashSet::new();
+    for pattern in config.force_main.iter() {
+        let mut matched = false;
+        for symbol in all_symbols(module) {
+            let name = symbol_name(module, &symbol);
+            if !pattern.is_match(&name) {
+                continue;
+            }
+            if is_split_point_func(split_points, &symbol) {
+                bail!(
+                    "force_main pattern {:?} matches split point function {name}",
+                    pattern.as_str()
+                );
+            }
+            matched = true;
+            roots.insert(symbol);
+        }
+        if !matched {
+            bail!(
+                "force_main pattern {:?} does not match any symbol",
+                pattern.as_str()
+            );
+        }
+    }
+    Ok(roots)
+}
+
+/// Moves the symbols matching the `exclude` and `pin` patterns to the
+/// requested output modules.
+///
+/// A symbol may only be moved to a split module or chunk that is loaded
+/// whenever the symbol may be used, i.e. one that is shared by every split
+/// module from which the symbol is reachable, or by one of its ancestors. Any
+/// symbol may be moved to main.
+fn apply_placement_overrides(
+    module: &InputModule,
+    config: &SplitConfig,
+    split_points: &[SplitPoint],
+    dep_candidate_modules: &HashMap<DepNode, Vec<String>>,
+    split_parents: &HashMap<String, String>,
+    split_module_contents: &mut HashMap<SplitModuleIdentifier, OutputModuleInfo>,
+) -> anyhow::Result<()> {
+    let pin_targets = config
+        .pin
+        .iter()
+        .map(|pin| {
+            split_module_contents
+                .keys()
+                .find(|identifier| identifier.name() == pin.module)
+                .cloned()
+                .ok_or_else(|| {
+                    anyhow!(
+                        "pin {:?} refers to unknown output module {:?}",
+                        pin.symbols.as_str(),
+                        pin.module
+                    )
+                })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut placement: Vec<(DepNode, SplitModuleIdentifier)> = split_module_contents
+        .iter()
+        .flat_map(|(identifier, contents)| {
+            contents
+                .included_symbols
+                .iter()
+                .map(move |symbol| (*symbol, identifier.clone()))
+        })
+        .collect();
+    placement.sort();
+
+    let mut exclude_matched = vec![false; config.exclude.len()];
+    let mut pin_matched = vec![false; config.pin.len()];
+    let mut moves = Vec::new();
+    for (symbol, current) in placement {
+        let name = symbol_name(module, &symbol);
+        let mut targets = Vec::<(SplitModuleIdentifier, String)>::new();
+        for (pattern, matched) in config.exclude.iter().zip(exclude_matched.iter_mut()) {
+            if pattern.is_match(&name) {
+                *matched = true;
+                targets.push((
+                    SplitModuleIdentifier::Main,
+                    format!("exclude {:?}", pattern.as_str()),
+                ));
+            }
+        }
+        for ((pin, target), matched) in config
+            .pin
+            .iter()
+            .zip(pin_targets.iter())
+            .zip(pin_matched.iter_mut())
+        {
+            if pin.symbols.is_match(&name) {
+                *matched = true;
+                targets.push((target.clone(), format!("pin {:?}", pin.symbols.as_str())));
+            }
+        }
+        let Some((target, rule)) = targets.first() else {
+            continue;
+        };
+        if let Some((_, other_rule)) = targets.iter().find(|(other, _)| other != target) {
+            bail!("{name} matches conflicting placement overrides {rule} and {other_rule}");
+        }
+        if *target == current {
+            continue;
+        }
+        if is_split_point_func(split_points, &symbol) {
+            bail!("{rule}: cannot move split point function {name}");
+        }
+        let target_splits = match target {
+            SplitModuleIdentifier::Main => None,
+            SplitModuleIdentifier::Cold => {
+                bail!("{rule}: symbols cannot be placed in the cold module")
+            }
+            SplitModuleIdentifier::Split(split) => Some(std::slice::from_ref(split)),
+            SplitModuleIdentifier::Chunk(splits) => Some(splits.as_slice()),
+        };
+        if let Some(target_splits) = target_splits {
+            let Some(splits) = dep_candidate_modules.get(&symbol) else {
+                bail!(
+                    "{rule}: cannot place {name} in {} because it is reachable from the main module",
+                    target.name()
+                );
+            };
+            if let Some(split) = splits.iter().find(|split| {
+                !target_splits.contains(split)
+                    && !split_ancestors(split_parents, split)
+                        .any(|ancestor| target_splits.contains(ancestor))
+            }) {
+                bail!(
+                    "{rule}: cannot place {name} in {} because it is reachable from split module {split}, which does not load {}",
+                    target.name(),
+                    target.name()
+                );
+            }
+        }
+        moves.push((symbol, current, target.clone()));
+    }
+
+    for (pattern, matched) in config.exclude.iter().zip(exclude_matched) {
+        if !matched {
+            bail!(
+                "exclude pattern {:?} does not match any symbol",
+                pattern.as_str()
+            );
+        }
+    }
+    for (pin, matched) in config.pin.iter().zip(pin_matched) {
+        if !matched {
+            bail!(
+                "pin pattern {:?} does not match any symbol",
+                pin.symbols.as_str()
+            );
+        }
+    }
+
+    for (symbol, from, to) in moves {
+        move_symbol(split_module_contents, symbol, &from, to);
+    }
+    Ok(())
+}
+
+fn move_symbol(
+    split_module_contents: &mut HashMap<SplitModuleIdentifier, OutputModuleInfo>,
+    symbol: DepNode,
+    from: &SplitModuleIdentifier,
+    to: SplitModuleIdentifier,
+) {
+    let from_contents = split_module_contents.get_mut(from).unwrap();
+    from_contents.included_symbols.remove(&symbol);
+    let parent = from_contents.parents.remove(&symbol);
+    let to_contents = split_module_contents.entry(to).or_default();
+    to_contents.included_symbols.insert(symbol);
+    if let Some(parent) = parent {
+        to_contents.parents.insert(symbol, parent);
+    }
+}
+
+/// Moves functions according to `profile`: split functions that were called
+/// are moved to main, and main functions that were not called are moved to
+/// the cold module.
+///
+/// Only functions that main calls directly are moved to the cold module,
+/// since calls through the indirect function table would fail if the cold
+/// module is not loaded yet. Symbols in `keep_in_main` are never moved to the
+/// cold module.
+fn apply_profile(
+    module: &InputModule,
+    profile: &Profile,
+    split_points: &[SplitPoint],
+    keep_in_main: &HashSet<DepNode>,
+    dep_candidate_modules: &HashMap<DepNode, Vec<String>>,
+    split_module_contents: &mut HashMap<SplitModuleIdentifier, OutputModuleInfo>,
+) -> anyhow::Result<()> {
+    let indirect_funcs = get_indirect_functions(module)?;
+
+    let mut moves = Vec::new();
+    for (identifier, contents) in split_module_contents.iter() {
+        for &symbol in contents.included_symbols.iter() {
+            let DepNode::Function(func_id) = symbol else {
+                continue;
+            };
+            if func_id < module.imported_funcs.len() || is_split_point_func(split_points, &symbol) {
+                continue;
+            }
+            let hot = profile.contains(&symbol_name(module, &symbol));
+            if *identifier == SplitModuleIdentifier::Main {
+                if !hot
+                    && !dep_candidate_modules.contains_key(&symbol)
+                    && !keep_in_main.contains(&symbol)
+                    && !indirect_funcs.contains(&func_id)
+                {
+                    moves.push((symbol, identifier.clone(), SplitModuleIdentifier::Cold));
+                }
+            } else if hot {
+                moves.push((symbol, identifier.clone(), SplitModuleIdentifier::Main));
+            }
+        }
+    }
+    for (symbol, from, to) in moves {
+        move_symbol(split_module_contents, symbol, &from, to);
+    }
+    Ok(())
+}
+
+pub fn compute_split_modules(
+    module: &InputModule,
+    dep_graph: &DepGraph,
+    split_points: &[SplitPoint],
+    options: &SplitOptions,
+) -> anyhow::Result<SplitProgramInfo> {
+    let split_points_by_module = get_split_points_by_module(split_points);
+
+    let split_func_map: HashMap<InputFuncId, InputFuncId> = split_points
+        .iter()
+        .map(|split_point| (split_point.import_func, split_point.export_func))
+        .collect();
+
+    let remove_ignored_deps = |deps: &mut HashSet<DepNode>| {
+        for split_point in split_points.iter() {
+            deps.remove(&DepNode::Function(split_point.import_func));
+        }
+    };
+    let remove_ignored_funcs = |deps: &mut HashSet<InputFuncId>| {
+        for split_point in split_points.iter() {
+            deps.remove(&split_point.import_func);
+        }
+    };
+
+    let forced_main_roots = get_forced_main_roots(module, &options.config, split_points)?;
+    let mut main_roots = get_main_module_roots(module, split_points);
+    main_roots.extend(forced_main_roots.iter());
+
+    let mut main_deps = find_reachable_deps(dep_graph, &main_roots, &HashSet::new());
+
+    // Determine reachable symbols (excluding main module symbols) for each
+    // split module. Symbols may be reachable from more than one split module;
+    // these symbols will be moved to a separate module.
+    let mut split_module_candidates: HashMap<String, ReachabilityGraph> = split_points_by_module
+        .iter()
+        .map(|(module_name, entry_points)| {
+            let mut roots = HashSet::<DepNode>::new();
+            for entry_point in entry_points.iter() {
+                roots.insert(DepNode::Function(entry_point.export_func));
+            }
+            let split_functions = find_reachable_deps(dep_graph, &roots, &main_deps.reachable);
+            (module_name.clone(), split_functions)
+        })
+        .collect();
+
+    // The split point imports are still included at this point, which
+    // determines the split modules from which each split module is loaded.
+    let split_parents =
+        get_split_module_parents(split_points, &main_deps.reachable, &split_module_candidates);
+    remove_ignored_deps(&mut main_deps.reachable);
+    for split_functions in split_module_candidates.values_mut() {
+        remove_ignored_deps(&mut split_functions.reachable);
+    }
+
+    // Set of split modules from which each symbol is reachable.
+    let mut dep_candidate_modules = HashMap::<DepNode, Vec<String>>::new();
+    for (module_name, deps) in split_module_candidates.iter() {
+        for dep in deps.reachable.iter() {
+            dep_candidate_modules
+                .entry(*dep)
+                .or_default()
+                .push(module_name.clone());
+        }
+    }
+
+    let mut program_info = SplitProgramInfo::default();
+
+    let mut split_module_contents = HashMap::<SplitModuleIdentifier, OutputModuleInfo>::new();
+
+    split_module_contents.insert(SplitModuleIdentifier::Main, main_deps.into());
+
+    for modules in dep_candidate_modules.values_mut() {
+        modules.sort();
+    }
+
+    for (&dep, modules) in dep_candidate_modules.iter() {
+        if modules.len() > 1 {
+            // A symbol reachable from a nested split module and one of its
+            // ancestors is placed with the ancestor, which is always loaded
+            // first.
+            let owners: Vec<String> = modules
+                .iter()
+                .filter(|module| {
+                    !split_ancestors(&split_parents, module)
+                        .any(|ancestor| modules.contains(ancestor))
+                })
+                .cloned()
+                .collect();
+            for module in modules.iter() {
+                if owners.len() == 1 && owners[0] == *module {
+                    continue;
+                }
+                let module_contents = split_module_candidates.get_mut(module).unwrap();
+                module_contents.reachable.remove(&dep);
+            }
+            if owners.len() > 1 {
+                split_module_contents
+                    .entry(SplitModuleIdentifier::Chunk(owners))
+                    .or_default()
+                    .included_symbols
+                    .insert(dep);
+            }
+        }
+    }
+
+    split_module_contents.extend(
+        split_module_candidates
+            .drain()
+            .map(|(module_name, deps)| (SplitModuleIdentifier::Split(module_name), deps.into())),
+    );
+
+    if options.min_chunk_size > 0 {
+        program_info.chunk_merges =
+            merge_small_chunks(module, &mut split_module_contents, options.min_chunk_size);
+    }
+
+    if let Some(profile) = &options.profile {
+        // Keep the roots, everything the `force_main` overrides require, and
+        // the descriptor functions that `wasm-bindgen` interprets.
+        let mut keep_roots = forced_main_roots.clone();
+        keep_roots.extend(
+            module
+                .exports
+                .iter()
+                .filter(|export| {
+                    export.kind == wasmparser::ExternalKind::Func
+                        && export.name.starts_with("__wbindgen_describe")
+                })
+                .map(|export| DepNode::Function(export.index as usize)),
+        );
+        let mut keep_in_main =
+            find_reachable_deps(dep_graph, &keep_roots, &HashSet::new()).reachable;
+        keep_in_main.extend(main_roots.iter());
+        apply_profile(
+            module,
+            profile,
+            split_points,
+            &keep_in_main,
+            &dep_candidate_modules,
+            &mut split_module_contents,
+        )?;
+    }
+
+    apply_placement_overrides(
+        module,
+        &options.config,
+        split_points,
+        &dep_candidate_modules,
+        &split_parents,
+        &mut split_module_contents,
+    )?;
+    // Chunks whose symbols were all moved elsewhere would only cost a fetch.
+    split_module_contents.retain(|identifier, contents| {
+        !matches!(identifier, SplitModuleIdentifier::Chunk(_))
+            || !contents.included_symbols.is_empty()
+    });
+    program_info.split_parents = split_parents;
+    program_info.loader_module = get_loader_module(module)?;
+    program_info.orphan_exports = get_orphan_split_exports(module, split_points);
+
+    for contents in split_module_contents.values_mut() {
+        for symbol in contents.included_symbols.iter() {
+            let Some(neighbors) = dep_graph.get(symbol) else {
+                continue;
+            };
+            for mut called_func_id in neighbors.iter().filter_map(|symbol| match symbol {
+                DepNode::Function(func_id) => Some(*func_id),
+                _ => None,
+            }) {
+                called_func_id = *split_func_map
+                    .get(&called_func_id)
+                    .unwrap_or(&called_func_id);
+                if !contents
+                    .included_symbols
+                    .contains(&DepNode::Function(called_func_id))
+                {
+                    contents.shared_imports.insert(called_func_id);
+                    program_info.shared_funcs.insert(called_func_id);
+                }
+            }
+        }
+        remove_ignored_funcs(&mut contents.shared_imports);
+    }
+    remove_ignored_funcs(&mut program_info.shared_funcs);
+
+    for split_point in split_points {
+        program_info.shared_funcs.insert(split_point.export_func);
+        let output_module = split_module_contents
+            .get_mut(&SplitModuleIdentifier::Split(
+                split_point.module_name.to_string(),
+            ))
+            .unwrap();
+        output_module.split_points.push(split_point.clone());
+    }
+
+    program_info.output_modules = split_module_contents.drain().collect();
+    program_info
+        .output_modules
+        .sort_by_key(|(identifier, _)| (*identifier).clone());
+    program_info.output_module_identifiers = program_info
+        .output_modules
+        .iter()
+        .enumerate()
+        .map(|(index, (identifier, _))| (identifier.clone(), index))
+        .collect();
+
+    for (output_index, (_, info)) in program_info.output_modules.iter().enumerate() {
+        for &symbol in info.included_symbols.iter() {
+            program_info
+                .symbol_output_module
+                .insert(symbol, output_index);
+        }
+    }
+
+    Ok(program_info)
+}
@@ -0,0 +1,183 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet, VecDeque},
+    fmt,
+};
+
+use anyhow::Result;
+use regex::Regex;
+
+use crate::{
+    dep_graph::{all_symbols, get_dependencies, symbol_name, DepNode},
+    read::InputModule,
+    split_point::{get_orphan_split_exports, get_split_points},
+};
+
+/// Symbol at which a dependency path starts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WhyRoot {
+    Export(String),
+    Start,
+    Import(String),
+    /// Entry point of the named split module.
+    SplitPoint(String),
+}
+
+impl fmt::Display for WhyRoot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Export(name) => write!(f, "export {name:?}"),
+            Self::Start => write!(f, "start function"),
+            Self::Import(name) => write!(f, "import {name:?}"),
+            Self::SplitPoint(module_name) => write!(f, "split point of {module_name:?}"),
+        }
+    }
+}
+
+/// Path through the dependency graph from a root to a symbol.
+#[derive(Clone, Debug)]
+pub struct WhyPath {
+    pub root: WhyRoot,
+    /// Symbols along the path, starting with the root symbol and ending with
+    /// the queried symbol.
+    pub symbols: Vec<String>,
+}
+
+/// Dependency paths for one symbol matching a `why` query.
+#[derive(Clone, Debug)]
+pub struct WhyResult {
+    pub symbol: String,
+    /// Paths to the symbol, shortest first.
+    pub paths: Vec<WhyPath>,
+}
+
+/// Finds paths from the roots of the program (exports, the start function,
+/// imports and split points) to each function or data symbol whose name
+/// matches `symbol_pattern`.
+///
+/// At most `max_paths` paths are returned for each symbol.
+pub fn why(input: &[u8], symbol_pattern: &str, max_paths: usize) -> Result<Vec<WhyResult>> {
+    let pattern = Regex::new(symbol_pattern)?;
+    let module = InputModule::parse(input)?;
+    let dep_graph = get_dependencies(&module)?;
+    let split_points = get_split_points(&module)?;
+
+    let mut roots = HashMap::<DepNode, WhyRoot>::new();
+    for (func_id, &import_id) in module.imported_funcs.iter().enumerate() {
+        let import = &module.imports[import_id];
+        roots.insert(
+            DepNode::Function(func_id),
+            WhyRoot::Import(format!("{}::{}", import.module, import.name)),
+        );
+    }
+    let orphan_exports = get_orphan_split_exports(&module, &split_points);
+    for (export_id, export) in module.exports.iter().enumerate() {
+        if export.kind == wasmparser::ExternalKind::Func && !orphan_exports.contains(&export_id) {
+            roots.insert(
+                DepNode::Function(export.index as usize),
+                WhyRoot::Export(export.name.to_string()),
+            );
+        }
+    }
+    if let Some(start) = module.start {
+        roots.insert(DepNode::Function(start), WhyRoot::Start);
+    }
+    for split_point in split_points.iter() {
+        roots.remove(&DepNode::Function(split_point.import_func));
+        roots.insert(
+            DepNode::Function(split_point.export_func),
+            WhyRoot::SplitPoint(split_point.module_name.clone()),
+        );
+    }
+
+    let mut dependents = HashMap::<DepNode, Vec<DepNode>>::new();
+    for (node, deps) in dep_graph.iter() {
+        for dep in deps.iter() {
+            dependents.entry(*dep).or_default().push(*node);
+        }
+    }
+    for nodes in dependents.values_mut() {
+        nodes.sort();
+    }
+
+    // Distance of each symbol from the nearest root, following dependencies.
+    let mut root_distances: HashMap<DepNode, usize> = roots.keys().map(|&root| (root, 0)).collect();
+    let mut queue: VecDeque<DepNode> = roots.keys().copied().collect();
+    while let Some(node) = queue.pop_front() {
+        let distance = root_distances[&node] + 1;
+        for dep in dep_graph.get(&node).into_iter().flatten() {
+            if !root_distances.contains_key(dep) {
+                root_distances.insert(*dep, distance);
+                queue.push_back(*dep);
+            }
+        }
+    }
+
+    let mut results = Vec::new();
+    for target in all_symbols(&module) {
+        let symbol = symbol_name(&module, &target);
+        if !pattern.is_match(&symbol) {
+            continue;
+        }
+        let paths = find_paths(target, &dependents, &roots, &root_distances, max_paths)
+            .into_iter()
+            .map(|(root, path)| WhyPath {
+                root: root.clone(),
+                symbols: path
+                    .iter()
+                    .rev()
+                    .map(|node| symbol_name(&module, node))
+                    .collect(),
+            })
+            .collect();
+        results.push(WhyResult { symbol, paths });
+    }
+    Ok(results)
+}
+
+/// Returns the shortest paths from `target` back to a root, in order of
+/// increasing length.
+///
+/// Partial paths are explored in order of their length plus the distance of
+/// their last symbol from the nearest root, so that only dependents leading to
+/// a root are followed and the search ends once `max_paths` paths are found.
+fn find_paths<'a>(
+    target: DepNode,
+    dependents: &HashMap<DepNode, Vec<DepNode>>,
+    roots: &'a HashMap<DepNode, WhyRoot>,
+    root_distances: &HashMap<DepNode, usize>,
+    max_paths: usize,
+) -> Vec<(&'a WhyRoot, Vec<DepNode>)> {
+    let mut paths = Vec::new();
+    let Some(&distance) = root_distances.get(&target) else {
+        return paths;
+    };
+    // Paths of equal estimated length are explored in the order in which they
+    // were found, to keep the result deterministic.
+    let mut queue = BinaryHeap::from([Reverse((distance, 0, vec![target]))]);
+    let mut next_id = 1;
+    while let Some(Reverse((_, _, path))) = queue.pop() {
+        let node = *path.last().unwrap();
+        if let Some(root) = roots.get(&node) {
+            paths.push((root, path));
+            if paths.len() >= max_paths {
+                break;
+            }
+            continue;
+        }
+        let on_path: HashSet<&DepNode> = path.iter().collect();
+        for dependent in dependents.get(&node).into_iter().flatten() {
+            let Some(&distance) = root_distances.get(dependent) else {
+                continue;
+            };
+            if on_path.contains(dependent) {
+                continue;
+            }
+            let mut new_path = path.clone();
+            new_path.push(*dependent);
+            queue.push(Reverse((new_path.len() - 1 + distance, next_id, new_path)));
+            next_id += 1;
+        }
+    }
+    paths
+}
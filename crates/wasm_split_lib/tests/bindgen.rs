@@ -0,0 +1,206 @@
+//! Splitting the output of `wasm-bindgen` must map the relocations of the
+//! original module onto the rewritten code and data.
+
+mod common;
+
+use common::{
+    call, data_address, function, function_address, parse_output, ref_func, DataSegment,
+    DataSymbol, Fixture,
+};
+use wasm_split_lib::Splitter;
+
+const SPLIT_ID: &str = "0123456789abcdef0123456789abcdef";
+
+/// Builds the module passed to `wasm-bindgen`, with a split module `a`.
+fn original() -> Fixture {
+    let mut fixture = Fixture {
+        imports: vec![("env".to_string(), "log".to_string())],
+        functions: vec![
+            function(
+                "main",
+                vec![
+                    call("log"),
+                    call(&common::split_import("a", SPLIT_ID)),
+                    call("renamed"),
+                    function_address("callback"),
+                    data_address("main_data"),
+                ],
+            ),
+            function("renamed", vec![call("callback")]),
+            function("callback", vec![call("log")]),
+            function("__wbindgen_describe_main", vec![call("log")]),
+        ],
+        exports: vec![
+            ("main".to_string(), "main".to_string()),
+            (
+                "__wbindgen_describe_main".to_string(),
+                "__wbindgen_describe_main".to_string(),
+            ),
+        ],
+        data_segments: vec![DataSegment {
+            address: Some(1024),
+            data: [[0x11; 8], [0xaa; 8]].concat(),
+        }],
+        data_symbols: vec![
+            DataSymbol {
+                name: "main_data".to_string(),
+                segment: 0,
+                offset: 0,
+                size: 8,
+            },
+            DataSymbol {
+                name: "a_data".to_string(),
+                segment: 0,
+                offset: 8,
+                size: 8,
+            },
+        ],
+        ..Default::default()
+    };
+    fixture.add_split_point(
+        "a",
+        SPLIT_ID,
+        function(
+            "a_entry",
+            vec![
+                call("a_helper"),
+                ref_func("a_helper"),
+                data_address("a_data"),
+                function_address("callback"),
+                function_address("a_helper"),
+            ],
+        ),
+    );
+    fixture.functions.push(function(
+        "a_helper",
+        vec![call("log"), call("callback"), data_address("a_data")],
+    ));
+    fixture
+}
+
+/// Applies the kinds of changes `wasm-bindgen` makes to `original`.
+fn bindgen_output(original: &Fixture) -> Fixture {
+    let mut output = original.clone();
+    // New imports shift the indices of all defined functions.
+    output.imports.insert(
+        0,
+        (
+            "wbg".to_string(),
+            "__wbg_alert_0123456789abcdef".to_string(),
+        ),
+    );
+    // Descriptors are interpreted and removed.
+    output
+        .functions
+        .retain(|function| function.name != "__wbindgen_describe_main");
+    output
+        .exports
+        .retain(|(name, _)| name != "__wbindgen_describe_main");
+    // Functions are added, and renamed.
+    output.functions.insert(
+        1,
+        function(
+            "__wbindgen_added",
+            vec![call("__wbg_alert_0123456789abcdef"), call("callback")],
+        ),
+    );
+    output.exports.push((
+        "__wbindgen_added".to_string(),
+        "__wbindgen_added".to_string(),
+    ));
+    for function in output.functions.iter_mut() {
+        if function.name == "renamed" {
+            function.name = "renamed_by_bindgen".to_string();
+        }
+        for instruction in function.body.iter_mut() {
+            if let common::Instruction::Call(name) = instruction {
+                if name == "renamed" {
+                    *name = "renamed_by_bindgen".to_string();
+                }
+            }
+        }
+    }
+    // Data is added in a new segment before the original one, which now
+    // starts earlier, without moving the original data.
+    output.data_segments = vec![
+        DataSegment {
+            address: Some(512),
+            data: vec![0x77; 16],
+        },
+        DataSegment {
+            address: Some(1016),
+            data: [vec![0x66; 8], original.data_segments[0].data.clone()].concat(),
+        },
+    ];
+    for symbol in output.data_symbols.iter_mut() {
+        symbol.segment = 1;
+        symbol.offset += 8;
+    }
+    output
+}
+
+#[test]
+fn relocations_resolve_in_bindgen_output() {
+    let original = original();
+    let bindgen_output = bindgen_output(&original);
+    let output = Splitter::new()
+        .split_bindgen_output(&original.encode(), &bindgen_output.encode_without_relocs())
+        .unwrap();
+
+    common::check_references(&bindgen_output, &output);
+
+    let modules = parse_output(&output);
+    let names: Vec<&str> = modules.iter().map(|module| module.name.as_str()).collect();
+    assert_eq!(names, ["main", "a"]);
+    let [main, a] = &modules[..] else {
+        unreachable!();
+    };
+    assert_eq!(
+        main.function_names(),
+        ["__wbindgen_added", "callback", "main", "renamed_by_bindgen"]
+    );
+    assert_eq!(a.function_names(), ["a_entry", "a_helper"]);
+    let (address, contents) = bindgen_output.data_symbol_contents("a_data");
+    assert!(common::module_has_data(a, address, contents));
+    assert!(!common::module_has_data(main, address, contents));
+}
+
+/// Renames `__wbindgen_added` in the output of `wasm-bindgen` to `name`, so
+/// that `name` refers to two functions.
+fn bindgen_output_with_duplicate(original: &Fixture, name: &str) -> Fixture {
+    let mut output = bindgen_output(original);
+    for function in output.functions.iter_mut() {
+        if function.name == "__wbindgen_added" {
+            function.name = name.to_string();
+        }
+    }
+    output
+        .exports
+        .retain(|(export_name, _)| export_name != "__wbindgen_added");
+    output
+}
+
+#[test]
+fn duplicate_names_with_relocations_are_rejected() {
+    let original = original();
+    let bindgen_output = bindgen_output_with_duplicate(&original, "a_helper");
+    let error = Splitter::new()
+        .split_bindgen_output(&original.encode(), &bindgen_output.encode_without_relocs())
+        .unwrap_err();
+    assert_eq!(
+        format!("{error:#}"),
+        "a_helper names more than one function in the original module or the wasm-bindgen \
+         output, so its relocations cannot be mapped"
+    );
+}
+
+#[test]
+fn duplicate_names_without_relocations_are_accepted() {
+    let original = original();
+    // `log` is only called, and calls are resolved from the operators of the
+    // output.
+    let bindgen_output = bindgen_output_with_duplicate(&original, "log");
+    Splitter::new()
+        .split_bindgen_output(&original.encode(), &bindgen_output.encode_without_relocs())
+        .unwrap();
+}
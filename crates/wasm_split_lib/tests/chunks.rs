@@ -0,0 +1,150 @@
+//! Chunks shared by several split modules that are smaller than the minimum
+//! chunk size are merged into a larger chunk or into main.
+
+mod common;
+
+use common::{call, function, split_id, split_import, Fixture, Instruction};
+use wasm_split_lib::{SplitOutput, Splitter};
+
+/// Builds a module with split modules `a`, `b` and `c`, a small function used
+/// by `a` and `b`, and large functions used by all three and by `b` and `c`.
+fn fixture() -> Fixture {
+    let large_body = || -> Vec<Instruction> { (0..20).map(|_| call("log")).collect() };
+    let mut fixture = Fixture {
+        imports: vec![("env".to_string(), "log".to_string())],
+        functions: vec![function(
+            "main",
+            ["a", "b", "c"]
+                .iter()
+                .enumerate()
+                .map(|(i, module_name)| call(&split_import(module_name, &split_id(i as u32))))
+                .collect(),
+        )],
+        exports: vec![("main".to_string(), "main".to_string())],
+        ..Default::default()
+    };
+    fixture.add_split_point(
+        "a",
+        &split_id(0),
+        function("a_entry", vec![call("small_a_b"), call("large_a_b_c")]),
+    );
+    fixture.add_split_point(
+        "b",
+        &split_id(1),
+        function(
+            "b_entry",
+            vec![call("small_a_b"), call("large_a_b_c"), call("large_b_c")],
+        ),
+    );
+    fixture.add_split_point(
+        "c",
+        &split_id(2),
+        function("c_entry", vec![call("large_a_b_c"), call("large_b_c")]),
+    );
+    fixture.functions.extend([
+        function("small_a_b", vec![call("log")]),
+        function("large_a_b_c", large_body()),
+        function("large_b_c", large_body()),
+    ]);
+    fixture
+}
+
+fn split(fixture: &Fixture, min_chunk_size: usize) -> SplitOutput {
+    let output = Splitter::new()
+        .report(true)
+        .min_chunk_size(min_chunk_size)
+        .split(&fixture.encode())
+        .unwrap();
+    common::check_references(fixture, &output);
+    output
+}
+
+/// Returns the chunk, size and target of each merge.
+fn merges(output: &SplitOutput) -> Vec<(&str, usize, &str)> {
+    output
+        .report
+        .as_ref()
+        .unwrap()
+        .chunk_merges
+        .iter()
+        .map(|merge| (merge.chunk.as_str(), merge.size, merge.into.as_str()))
+        .collect()
+}
+
+fn expected_function_names(modules: &[(&str, &[&str])]) -> Vec<(String, Vec<String>)> {
+    modules
+        .iter()
+        .map(|(module_name, functions)| {
+            (
+                module_name.to_string(),
+                functions.iter().map(|name| name.to_string()).collect(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn chunks_are_kept_without_minimum_size() {
+    let fixture = fixture();
+    let output = split(&fixture, 0);
+    assert_eq!(merges(&output), []);
+    assert_eq!(
+        common::module_functions(&output),
+        expected_function_names(&[
+            ("main", &["main"]),
+            ("a", &["a_entry"]),
+            ("b", &["b_entry"]),
+            ("c", &["c_entry"]),
+            ("a_b", &["small_a_b"]),
+            ("a_b_c", &["large_a_b_c"]),
+            ("b_c", &["large_b_c"]),
+        ])
+    );
+}
+
+#[test]
+fn small_chunk_is_merged_into_superset_chunk() {
+    let fixture = fixture();
+    let output = split(&fixture, 50);
+    let merges = merges(&output);
+    let [("a_b", size, "a_b_c")] = merges[..] else {
+        panic!("Unexpected merges {merges:?}");
+    };
+    assert!(size < 50);
+    assert_eq!(
+        common::module_functions(&output),
+        expected_function_names(&[
+            ("main", &["main"]),
+            ("a", &["a_entry"]),
+            ("b", &["b_entry"]),
+            ("c", &["c_entry"]),
+            ("a_b_c", &["large_a_b_c", "small_a_b"]),
+            ("b_c", &["large_b_c"]),
+        ])
+    );
+}
+
+#[test]
+fn chunks_without_superset_are_merged_into_main() {
+    let fixture = fixture();
+    let output = split(&fixture, 10000);
+    let merges: Vec<(&str, &str)> = merges(&output)
+        .into_iter()
+        .map(|(chunk, _, into)| (chunk, into))
+        .collect();
+    // Chunks shared by more split modules are merged first, so `a_b` is no
+    // longer a candidate for `a_b_c`.
+    assert_eq!(
+        merges,
+        [("a_b_c", "main"), ("a_b", "main"), ("b_c", "main")]
+    );
+    assert_eq!(
+        common::module_functions(&output),
+        expected_function_names(&[
+            ("main", &["large_a_b_c", "large_b_c", "main", "small_a_b"]),
+            ("a", &["a_entry"]),
+            ("b", &["b_entry"]),
+            ("c", &["c_entry"]),
+        ])
+    );
+}
@@ -0,0 +1,740 @@
+//! Builds relocatable modules, as produced by `wasm-ld --emit-relocs`, from a
+//! description of their functions and data, and inspects the split output.
+
+// Each test uses a different subset of the helpers.
+#![allow(dead_code)]
+
+use std::{collections::HashMap, path::PathBuf, process::Command};
+
+use wasm_encoder::{
+    ConstExpr, CustomSection, DataSection, DataSymbolDefinition, ElementSection, Elements, Encode,
+    EntityType, ExportKind, ExportSection, FunctionSection, ImportSection, LinkingSection,
+    MemorySection, MemoryType, Module, NameMap, NameSection, RefType, Section, SymbolTable,
+    TableSection, TableType, TypeSection,
+};
+use wasm_split_lib::SplitOutput;
+use wasmparser::{DataKind, ElementItems, ElementKind, Operator, Payload};
+
+// Relocation types, see
+// https://github.com/WebAssembly/tool-conventions/blob/main/Linking.md
+const R_WASM_FUNCTION_INDEX_LEB: u8 = 0;
+const R_WASM_TABLE_INDEX_SLEB: u8 = 1;
+const R_WASM_MEMORY_ADDR_SLEB: u8 = 4;
+const WASM_SYM_ABSOLUTE: u32 = 0x200;
+
+/// Loader module from which split points are imported.
+pub const LOADER_MODULE: &str = "./__wasm_split.js";
+
+/// Returns a distinct identifier for each split point, in place of the hash
+/// generated by the `wasm_split` attribute.
+pub fn split_id(index: u32) -> String {
+    format!("{index:032x}")
+}
+
+pub fn split_import(module_name: &str, id: &str) -> String {
+    format!("__wasm_split_00{module_name}00_import_{id}")
+}
+
+pub fn split_export(module_name: &str, id: &str) -> String {
+    format!("__wasm_split_00{module_name}00_export_{id}")
+}
+
+/// Instruction of a function body, referring to functions and data symbols by
+/// name.
+#[derive(Clone, Debug)]
+pub enum Instruction {
+    Call(String),
+    /// `i32.const` with the table index of a function.
+    FunctionAddress(String),
+    /// `i32.const` with the address of a data symbol.
+    DataAddress(String),
+    /// `ref.func` followed by `drop`.
+    RefFunc(String),
+}
+
+pub fn call(name: &str) -> Instruction {
+    Instruction::Call(name.to_string())
+}
+
+pub fn function_address(name: &str) -> Instruction {
+    Instruction::FunctionAddress(name.to_string())
+}
+
+pub fn data_address(name: &str) -> Instruction {
+    Instruction::DataAddress(name.to_string())
+}
+
+pub fn ref_func(name: &str) -> Instruction {
+    Instruction::RefFunc(name.to_string())
+}
+
+#[derive(Clone, Debug)]
+pub struct Function {
+    pub name: String,
+    pub body: Vec<Instruction>,
+}
+
+pub fn function(name: &str, body: Vec<Instruction>) -> Function {
+    Function {
+        name: name.to_string(),
+        body,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DataSegment {
+    /// Address of an active segment, or `None` for a passive segment.
+    pub address: Option<u32>,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+pub struct DataSymbol {
+    pub name: String,
+    pub segment: u32,
+    pub offset: u32,
+    pub size: u32,
+}
+
+/// Description of a module. All functions have type `[] -> []`, and the
+/// function symbols precede the data symbols.
+#[derive(Clone, Debug, Default)]
+pub struct Fixture {
+    /// Module and name of each imported function.
+    pub imports: Vec<(String, String)>,
+    pub functions: Vec<Function>,
+    /// Name and function of each export.
+    pub exports: Vec<(String, String)>,
+    pub data_segments: Vec<DataSegment>,
+    pub data_symbols: Vec<DataSymbol>,
+    /// Name and address of each absolute data symbol, such as `__heap_base`,
+    /// following the other data symbols.
+    pub absolute_symbols: Vec<(String, u32)>,
+}
+
+impl Fixture {
+    /// Adds a split point of `module_name`, i.e. the import called by main and
+    /// the exported entry point `entry`.
+    pub fn add_split_point(&mut self, module_name: &str, id: &str, entry: Function) {
+        self.imports
+            .push((LOADER_MODULE.to_string(), split_import(module_name, id)));
+        self.exports
+            .push((split_export(module_name, id), entry.name.clone()));
+        self.functions.push(entry);
+    }
+
+    pub fn func_index(&self, name: &str) -> u32 {
+        self.imports
+            .iter()
+            .map(|(_, name)| name)
+            .chain(self.functions.iter().map(|function| &function.name))
+            .position(|func_name| func_name == name)
+            .unwrap_or_else(|| panic!("No function {name}")) as u32
+    }
+
+    /// Returns the function called by a call to `name`, which is the entry
+    /// point for the import of a split point.
+    pub fn call_target(&self, name: &str) -> String {
+        let export_name = name.replacen("_import_", "_export_", 1);
+        self.exports
+            .iter()
+            .find(|(name, _)| name.starts_with("__wasm_split_00") && *name == export_name)
+            .map_or(name, |(_, function)| function)
+            .to_string()
+    }
+
+    fn data_symbol(&self, name: &str) -> (u32, &DataSymbol) {
+        let index = self
+            .data_symbols
+            .iter()
+            .position(|symbol| symbol.name == name)
+            .unwrap_or_else(|| panic!("No data symbol {name}"));
+        (
+            (self.imports.len() + self.functions.len() + index) as u32,
+            &self.data_symbols[index],
+        )
+    }
+
+    /// Returns the symbol index and address of a data symbol in an active
+    /// segment, or of an absolute symbol.
+    fn data_symbol_address(&self, name: &str) -> (u32, u32) {
+        if let Some(index) = self
+            .absolute_symbols
+            .iter()
+            .position(|(symbol_name, _)| symbol_name == name)
+        {
+            let symbol =
+                self.imports.len() + self.functions.len() + self.data_symbols.len() + index;
+            return (symbol as u32, self.absolute_symbols[index].1);
+        }
+        let (symbol, _) = self.data_symbol(name);
+        (symbol, self.data_symbol_contents(name).0)
+    }
+
+    /// Returns the address and contents of a data symbol in an active segment.
+    pub fn data_symbol_contents(&self, name: &str) -> (u32, &[u8]) {
+        let (_, symbol) = self.data_symbol(name);
+        let segment = &self.data_segments[symbol.segment as usize];
+        let offset = symbol.offset as usize;
+        (
+            segment.address.unwrap() + symbol.offset,
+            &segment.data[offset..offset + symbol.size as usize],
+        )
+    }
+
+    /// Functions whose table index is taken, in order of their table index
+    /// starting from 1.
+    fn indirect_functions(&self) -> Vec<&str> {
+        let mut indirect = Vec::new();
+        for function in self.functions.iter() {
+            for instruction in function.body.iter() {
+                if let Instruction::FunctionAddress(name) = instruction {
+                    if !indirect.contains(&name.as_str()) {
+                        indirect.push(name.as_str());
+                    }
+                }
+            }
+        }
+        indirect
+    }
+
+    /// Encodes the module with a symbol table and code relocations.
+    pub fn encode(&self) -> Vec<u8> {
+        self.encode_module(true)
+    }
+
+    /// Encodes the module without a symbol table and relocations, as
+    /// `wasm-bindgen` does.
+    pub fn encode_without_relocs(&self) -> Vec<u8> {
+        self.encode_module(false)
+    }
+
+    fn encode_module(&self, relocatable: bool) -> Vec<u8> {
+        let num_imports = self.imports.len() as u32;
+        let indirect_functions = self.indirect_functions();
+        let mut module = Module::new();
+        let mut num_sections = 0;
+
+        let mut types = TypeSection::new();
+        types.function([], []);
+        add_section(&mut module, &mut num_sections, &types);
+
+        let mut imports = ImportSection::new();
+        for (module_name, name) in self.imports.iter() {
+            imports.import(module_name, name, EntityType::Function(0));
+        }
+        add_section(&mut module, &mut num_sections, &imports);
+
+        let mut functions = FunctionSection::new();
+        for _ in self.functions.iter() {
+            functions.function(0);
+        }
+        add_section(&mut module, &mut num_sections, &functions);
+
+        if !indirect_functions.is_empty() {
+            let mut tables = TableSection::new();
+            let size = indirect_functions.len() as u32 + 1;
+            tables.table(TableType {
+                element_type: RefType::FUNCREF,
+                minimum: size,
+                maximum: Some(size),
+            });
+            add_section(&mut module, &mut num_sections, &tables);
+        }
+
+        let mut memories = MemorySection::new();
+        memories.memory(MemoryType {
+            minimum: 1,
+            maximum: None,
+            memory64: false,
+            shared: false,
+            page_size_log2: None,
+        });
+        add_section(&mut module, &mut num_sections, &memories);
+
+        let mut exports = ExportSection::new();
+        exports.export("memory", ExportKind::Memory, 0);
+        for (name, function) in self.exports.iter() {
+            exports.export(name, ExportKind::Func, self.func_index(function));
+        }
+        add_section(&mut module, &mut num_sections, &exports);
+
+        if !indirect_functions.is_empty() {
+            let mut elements = ElementSection::new();
+            let funcs: Vec<u32> = indirect_functions
+                .iter()
+                .map(|name| self.func_index(name))
+                .collect();
+            elements.active(
+                Some(0),
+                &ConstExpr::i32_const(1),
+                Elements::Functions(&funcs),
+            );
+            add_section(&mut module, &mut num_sections, &elements);
+        }
+
+        // The code section is encoded manually to record the offsets of the
+        // relocations, which use padded LEB128 encodings.
+        let mut code = Vec::new();
+        let mut code_relocs = Vec::new();
+        (self.functions.len() as u32).encode(&mut code);
+        for function in self.functions.iter() {
+            let mut body = vec![0]; // No locals.
+            let mut body_relocs = Vec::new();
+            for instruction in function.body.iter() {
+                let (opcode, ty, symbol, value) = match instruction {
+                    Instruction::Call(name) => {
+                        let func = self.func_index(name);
+                        (0x10, R_WASM_FUNCTION_INDEX_LEB, func, func as i32)
+                    }
+                    Instruction::RefFunc(name) => {
+                        let func = self.func_index(name);
+                        (0xd2, R_WASM_FUNCTION_INDEX_LEB, func, func as i32)
+                    }
+                    Instruction::FunctionAddress(name) => {
+                        let table_index = indirect_functions
+                            .iter()
+                            .position(|function| function == name)
+                            .unwrap()
+                            + 1;
+                        (
+                            0x41,
+                            R_WASM_TABLE_INDEX_SLEB,
+                            self.func_index(name),
+                            table_index as i32,
+                        )
+                    }
+                    Instruction::DataAddress(name) => {
+                        let (symbol, address) = self.data_symbol_address(name);
+                        (0x41, R_WASM_MEMORY_ADDR_SLEB, symbol, address as i32)
+                    }
+                };
+                body.push(opcode);
+                body_relocs.push((ty, body.len(), symbol));
+                encode_padded_leb(value, opcode != 0x41, &mut body);
+                if opcode != 0x10 {
+                    body.push(0x1a); // drop
+                }
+            }
+            body.push(0x0b); // end
+            (body.len() as u32).encode(&mut code);
+            for (ty, offset, symbol) in body_relocs {
+                code_relocs.push((ty, code.len() + offset, symbol));
+            }
+            code.extend_from_slice(&body);
+        }
+        let code_section_index = add_section(
+            &mut module,
+            &mut num_sections,
+            &wasm_encoder::RawSection {
+                id: wasm_encoder::SectionId::Code as u8,
+                data: &code,
+            },
+        );
+
+        let mut data = DataSection::new();
+        for segment in self.data_segments.iter() {
+            match segment.address {
+                Some(address) => data.active(
+                    0,
+                    &ConstExpr::i32_const(address as i32),
+                    segment.data.iter().copied(),
+                ),
+                None => data.passive(segment.data.iter().copied()),
+            };
+        }
+        add_section(&mut module, &mut num_sections, &data);
+
+        if relocatable {
+            let mut symbols = SymbolTable::new();
+            for func in 0..num_imports {
+                symbols.function(SymbolTable::WASM_SYM_UNDEFINED, func, None);
+            }
+            for (index, function) in self.functions.iter().enumerate() {
+                symbols.function(0, num_imports + index as u32, Some(&function.name));
+            }
+            for symbol in self.data_symbols.iter() {
+                symbols.data(
+                    0,
+                    &symbol.name,
+                    Some(DataSymbolDefinition {
+                        index: symbol.segment,
+                        offset: symbol.offset,
+                        size: symbol.size,
+                    }),
+                );
+            }
+            for (name, address) in self.absolute_symbols.iter() {
+                symbols.data(
+                    WASM_SYM_ABSOLUTE,
+                    name,
+                    Some(DataSymbolDefinition {
+                        index: 0,
+                        offset: *address,
+                        size: 0,
+                    }),
+                );
+            }
+            let mut linking = LinkingSection::new();
+            linking.symbol_table(&symbols);
+            module.section(&linking);
+
+            let mut reloc = Vec::new();
+            code_section_index.encode(&mut reloc);
+            (code_relocs.len() as u32).encode(&mut reloc);
+            for (ty, offset, symbol) in code_relocs {
+                reloc.push(ty);
+                (offset as u32).encode(&mut reloc);
+                symbol.encode(&mut reloc);
+                if ty == R_WASM_MEMORY_ADDR_SLEB {
+                    0i32.encode(&mut reloc);
+                }
+            }
+            module.section(&CustomSection {
+                name: "reloc.CODE".into(),
+                data: reloc.into(),
+            });
+        }
+
+        let mut function_names = NameMap::new();
+        for (func, (_, name)) in self.imports.iter().enumerate() {
+            function_names.append(func as u32, name);
+        }
+        for (index, function) in self.functions.iter().enumerate() {
+            function_names.append(num_imports + index as u32, &function.name);
+        }
+        let mut names = NameSection::new();
+        names.functions(&function_names);
+        module.section(&names);
+
+        module.finish()
+    }
+}
+
+/// Adds a standard section and returns its index.
+fn add_section(module: &mut Module, num_sections: &mut u32, section: &impl Section) -> u32 {
+    module.section(section);
+    *num_sections += 1;
+    *num_sections - 1
+}
+
+/// Encodes `value` as a 5-byte LEB128, as the linker does for relocated
+/// operands.
+fn encode_padded_leb(mut value: i32, unsigned: bool, output: &mut Vec<u8>) {
+    for i in 0..5 {
+        let continuation = if i < 4 { 0x80 } else { 0 };
+        let byte = if unsigned && i == 4 {
+            (value as u32 >> 28) as u8 & 0x0f
+        } else {
+            value as u8 & 0x7f
+        };
+        output.push(byte | continuation);
+        value >>= 7;
+    }
+}
+
+/// Operator of an output function that refers to a function or address.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OutputOperator {
+    Call(String),
+    RefFunc(String),
+    I32Const(i32),
+    CallIndirect,
+}
+
+#[derive(Clone, Debug)]
+pub struct OutputFunction {
+    pub name: String,
+    pub operators: Vec<OutputOperator>,
+}
+
+impl OutputFunction {
+    /// Whether the function is a stub that calls a function of another module
+    /// through the table.
+    pub fn is_stub(&self) -> bool {
+        self.operators.contains(&OutputOperator::CallIndirect)
+    }
+}
+
+/// Contents of an output module.
+#[derive(Clone, Debug, Default)]
+pub struct ParsedModule {
+    pub name: String,
+    pub functions: Vec<OutputFunction>,
+    /// Address of each active segment, or `None` for passive segments, and its
+    /// contents.
+    pub data_segments: Vec<(Option<u32>, Vec<u8>)>,
+    /// Function names by table index, for the entries initialized by the
+    /// module.
+    pub table_entries: HashMap<u32, String>,
+    pub exports: Vec<String>,
+}
+
+impl ParsedModule {
+    pub fn parse(name: &str, wasm: &[u8]) -> Self {
+        let mut module = Self {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        let mut num_imported_funcs = 0;
+        let mut names = HashMap::new();
+        let mut bodies = Vec::new();
+        let mut elements = Vec::new();
+        for payload in wasmparser::Parser::new(0).parse_all(wasm) {
+            match payload.unwrap() {
+                Payload::ImportSection(reader) => {
+                    for import in reader {
+                        if let wasmparser::TypeRef::Func(_) = import.unwrap().ty {
+                            num_imported_funcs += 1;
+                        }
+                    }
+                }
+                Payload::ExportSection(reader) => {
+                    for export in reader {
+                        module.exports.push(export.unwrap().name.to_string());
+                    }
+                }
+                Payload::ElementSection(reader) => {
+                    for element in reader {
+                        let element = element.unwrap();
+                        let ElementKind::Active { offset_expr, .. } = element.kind else {
+                            continue;
+                        };
+                        let Ok(Operator::I32Const { value }) =
+                            offset_expr.get_operators_reader().read()
+                        else {
+                            panic!("Unexpected element offset");
+                        };
+                        let ElementItems::Functions(funcs) = element.items else {
+                            panic!("Unexpected element items");
+                        };
+                        for (i, func) in funcs.into_iter().enumerate() {
+                            elements.push((value as u32 + i as u32, func.unwrap()));
+                        }
+                    }
+                }
+                Payload::CodeSectionEntry(body) => bodies.push(body),
+                Payload::DataSection(reader) => {
+                    for segment in reader {
+                        let segment = segment.unwrap();
+                        let address = match segment.kind {
+                            DataKind::Passive => None,
+                            DataKind::Active { offset_expr, .. } => {
+                                match offset_expr.get_operators_reader().read().unwrap() {
+                                    Operator::I32Const { value } => Some(value as u32),
+                                    operator => panic!("Unexpected offset {operator:?}"),
+                                }
+                            }
+                        };
+                        module.data_segments.push((address, segment.data.to_vec()));
+                    }
+                }
+                Payload::CustomSection(reader) if reader.name() == "name" => {
+                    for name in wasmparser::NameSectionReader::new(reader.data(), 0) {
+                        if let wasmparser::Name::Function(map) = name.unwrap() {
+                            for naming in map {
+                                let naming = naming.unwrap();
+                                names.insert(naming.index, naming.name.to_string());
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        let name = |func: u32| {
+            names
+                .get(&func)
+                .cloned()
+                .unwrap_or_else(|| format!("func[{func}]"))
+        };
+        for (index, body) in bodies.iter().enumerate() {
+            let mut operators = Vec::new();
+            let mut reader = body.get_operators_reader().unwrap();
+            while !reader.eof() {
+                operators.push(match reader.read().unwrap() {
+                    Operator::Call { function_index } => OutputOperator::Call(name(function_index)),
+                    Operator::RefFunc { function_index } => {
+                        OutputOperator::RefFunc(name(function_index))
+                    }
+                    Operator::I32Const { value } => OutputOperator::I32Const(value),
+                    Operator::CallIndirect { .. } => OutputOperator::CallIndirect,
+                    _ => continue,
+                });
+            }
+            module.functions.push(OutputFunction {
+                name: name(num_imported_funcs + index as u32),
+                operators,
+            });
+        }
+        module.table_entries = elements
+            .into_iter()
+            .map(|(table_index, func)| (table_index, name(func)))
+            .collect();
+        module
+    }
+
+    /// Returns the function named `name` defined by the module, other than
+    /// stubs.
+    pub fn function(&self, name: &str) -> Option<&OutputFunction> {
+        self.functions
+            .iter()
+            .find(|function| function.name == name && !function.is_stub())
+    }
+
+    pub fn function_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .functions
+            .iter()
+            .filter(|function| !function.is_stub())
+            .map(|function| function.name.as_str())
+            .collect();
+        names.sort();
+        names
+    }
+}
+
+pub fn parse_output(output: &SplitOutput) -> Vec<ParsedModule> {
+    output
+        .modules
+        .iter()
+        .map(|module| ParsedModule::parse(&module.name, &module.wasm))
+        .collect()
+}
+
+/// Returns the name of each output module and the functions it defines.
+pub fn module_functions(output: &SplitOutput) -> Vec<(String, Vec<String>)> {
+    parse_output(output)
+        .iter()
+        .map(|module| {
+            (
+                module.name.clone(),
+                module
+                    .function_names()
+                    .into_iter()
+                    .map(str::to_string)
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+/// Checks that the output modules define each function of `fixture` exactly
+/// once, and that every call, `ref.func`, function address and data address
+/// in it refers to the same function or data as in `fixture`.
+pub fn check_references(fixture: &Fixture, output: &SplitOutput) {
+    let modules = parse_output(output);
+    let mut table_entries = HashMap::new();
+    for module in modules.iter() {
+        for (&table_index, name) in module.table_entries.iter() {
+            assert!(
+                table_entries.insert(table_index, name.clone()).is_none(),
+                "Table index {table_index} is initialized more than once"
+            );
+        }
+    }
+    for function in fixture.functions.iter() {
+        let definitions: Vec<(&ParsedModule, &OutputFunction)> = modules
+            .iter()
+            .filter_map(|module| Some((module, module.function(&function.name)?)))
+            .collect();
+        let [(module, output_function)] = definitions[..] else {
+            panic!(
+                "{} is defined by {} output modules",
+                function.name,
+                definitions.len()
+            );
+        };
+        let expected: Vec<OutputOperator> = function
+            .body
+            .iter()
+            .map(|instruction| match instruction {
+                Instruction::Call(name) => OutputOperator::Call(fixture.call_target(name)),
+                Instruction::RefFunc(name) => OutputOperator::RefFunc(name.clone()),
+                Instruction::DataAddress(name) => {
+                    OutputOperator::I32Const(fixture.data_symbol_contents(name).0 as i32)
+                }
+                Instruction::FunctionAddress(_) => {
+                    // Checked below, since the table is renumbered.
+                    OutputOperator::I32Const(0)
+                }
+            })
+            .collect();
+        assert_eq!(
+            output_function.operators.len(),
+            expected.len(),
+            "Operators of {} in {}: {:?}",
+            function.name,
+            module.name,
+            output_function.operators
+        );
+        for ((instruction, operator), expected) in function
+            .body
+            .iter()
+            .zip(output_function.operators.iter())
+            .zip(expected.iter())
+        {
+            match (instruction, operator) {
+                (Instruction::FunctionAddress(name), OutputOperator::I32Const(table_index)) => {
+                    assert_eq!(
+                        table_entries.get(&(*table_index as u32)),
+                        Some(name),
+                        "Table index {table_index} in {} of {}",
+                        function.name,
+                        module.name
+                    );
+                }
+                _ => assert_eq!(operator, expected, "{} in {}", function.name, module.name),
+            }
+            if let Instruction::DataAddress(name) = instruction {
+                let (address, contents) = fixture.data_symbol_contents(name);
+                let holders: Vec<&str> = modules
+                    .iter()
+                    .filter(|module| module_has_data(module, address, contents))
+                    .map(|module| module.name.as_str())
+                    .collect();
+                assert_eq!(
+                    holders.len(),
+                    1,
+                    "Data symbol {name} used by {} is in {holders:?}",
+                    function.name
+                );
+            }
+        }
+    }
+}
+
+/// Whether an active segment of `module` initializes `address` with
+/// `contents`.
+pub fn module_has_data(module: &ParsedModule, address: u32, contents: &[u8]) -> bool {
+    module.data_segments.iter().any(|(segment_address, data)| {
+        let Some(segment_address) = *segment_address else {
+            return false;
+        };
+        let Some(offset) = address.checked_sub(segment_address) else {
+            return false;
+        };
+        data.get(offset as usize..offset as usize + contents.len()) == Some(contents)
+    })
+}
+
+/// Runs `script` with Node.js and returns its output, or `None` if Node.js is
+/// not installed.
+pub fn run_node(name: &str, script: &str) -> Option<String> {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{name}.cjs"));
+    std::fs::write(&path, script).unwrap();
+    let output = match Command::new("node").arg(&path).output() {
+        Ok(output) => output,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            eprintln!("Skipping {name}: node is not installed");
+            return None;
+        }
+        Err(error) => panic!("Failed to run node: {error}"),
+    };
+    assert!(
+        output.status.success(),
+        "node failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Some(String::from_utf8(output.stdout).unwrap())
+}
@@ -0,0 +1,146 @@
+//! Manual placement overrides, and the errors for overrides that cannot be
+//! satisfied.
+
+mod common;
+
+use common::{call, function, split_id, split_import, Fixture};
+use wasm_split_lib::{SplitConfig, Splitter};
+
+/// Builds a module in which main calls split modules `a` and `b`, which share
+/// a function, and `a` calls a chain of functions of its own.
+fn fixture() -> Fixture {
+    let mut fixture = Fixture {
+        imports: vec![("env".to_string(), "log".to_string())],
+        functions: vec![function(
+            "main",
+            vec![
+                call(&split_import("a", &split_id(0))),
+                call(&split_import("b", &split_id(1))),
+                call("main_only"),
+            ],
+        )],
+        exports: vec![("main".to_string(), "main".to_string())],
+        ..Default::default()
+    };
+    fixture.add_split_point(
+        "a",
+        &split_id(0),
+        function("a_entry", vec![call("a_only"), call("shared")]),
+    );
+    fixture.add_split_point("b", &split_id(1), function("b_entry", vec![call("shared")]));
+    fixture.functions.extend([
+        function("main_only", vec![call("log")]),
+        function("a_only", vec![call("a_leaf")]),
+        function("a_leaf", vec![call("log")]),
+        function("shared", vec![call("log")]),
+    ]);
+    fixture
+}
+
+/// Splits the fixture with `config`, and returns the functions of each
+/// output module.
+fn split(config: &str) -> anyhow::Result<Vec<(String, Vec<String>)>> {
+    let fixture = fixture();
+    let output = Splitter::new()
+        .config(SplitConfig::parse(config)?)
+        .split(&fixture.encode())?;
+    common::check_references(&fixture, &output);
+    Ok(common::module_functions(&output))
+}
+
+fn functions_of<'a>(modules: &'a [(String, Vec<String>)], module_name: &str) -> &'a [String] {
+    &modules
+        .iter()
+        .find(|(name, _)| name == module_name)
+        .unwrap_or_else(|| panic!("No module {module_name}"))
+        .1
+}
+
+fn split_error(config: &str) -> String {
+    format!("{:#}", split(config).unwrap_err())
+}
+
+#[test]
+fn force_main_moves_dependencies() {
+    let modules = split("force_main = [\"^a_only$\"]").unwrap();
+    assert_eq!(
+        functions_of(&modules, "main"),
+        ["a_leaf", "a_only", "main", "main_only"]
+    );
+    assert_eq!(functions_of(&modules, "a"), ["a_entry"]);
+}
+
+#[test]
+fn exclude_keeps_symbol_in_main() {
+    let modules = split("exclude = [\"^a_only$\"]").unwrap();
+    assert_eq!(
+        functions_of(&modules, "main"),
+        ["a_only", "main", "main_only"]
+    );
+    assert_eq!(functions_of(&modules, "a"), ["a_entry", "a_leaf"]);
+}
+
+#[test]
+fn pin_moves_symbol_to_module() {
+    let modules = split("[[pin]]\nsymbols = \"^shared$\"\nmodule = \"main\"").unwrap();
+    assert_eq!(
+        functions_of(&modules, "main"),
+        ["main", "main_only", "shared"]
+    );
+    assert!(!modules.iter().any(|(name, _)| name == "a_b"));
+}
+
+#[test]
+fn invalid_config_is_rejected() {
+    assert!(split_error("force_main = [\"(\"]").starts_with("Invalid symbol pattern \"(\""));
+    assert!(split_error("unknown = []").contains("unknown field `unknown`"));
+    assert!(split_error("[[pin]]\nsymbols = \"^shared$\"").contains("missing field `module`"));
+}
+
+#[test]
+fn unsatisfiable_overrides_are_rejected() {
+    let cases = [
+        (
+            "force_main = [\"^missing$\"]",
+            "force_main pattern \"^missing$\" does not match any symbol",
+        ),
+        (
+            "force_main = [\"^a_entry$\"]",
+            "force_main pattern \"^a_entry$\" matches split point function a_entry",
+        ),
+        (
+            "exclude = [\"^missing$\"]",
+            "exclude pattern \"^missing$\" does not match any symbol",
+        ),
+        (
+            "[[pin]]\nsymbols = \"^missing$\"\nmodule = \"a\"",
+            "pin pattern \"^missing$\" does not match any symbol",
+        ),
+        (
+            "[[pin]]\nsymbols = \"^shared$\"\nmodule = \"missing\"",
+            "pin \"^shared$\" refers to unknown output module \"missing\"",
+        ),
+        (
+            "[[pin]]\nsymbols = \"^a_entry$\"\nmodule = \"main\"",
+            "pin \"^a_entry$\": cannot move split point function a_entry",
+        ),
+        (
+            "[[pin]]\nsymbols = \"^main_only$\"\nmodule = \"a\"",
+            "pin \"^main_only$\": cannot place main_only in a because it is reachable from the \
+             main module",
+        ),
+        (
+            "[[pin]]\nsymbols = \"^shared$\"\nmodule = \"a\"",
+            "pin \"^shared$\": cannot place shared in a because it is reachable from split \
+             module b, which does not load a",
+        ),
+        (
+            "exclude = [\"^shared$\"]\n[[pin]]\nsymbols = \"^shared$\"\nmodule = \"a_b\"",
+            "shared matches conflicting placement overrides exclude \"^shared$\" and pin \
+             \"^shared$\"",
+        ),
+    ];
+    for (config, expected) in cases {
+        assert_eq!(split_error(config), expected, "{config}");
+    }
+}
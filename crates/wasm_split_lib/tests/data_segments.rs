@@ -0,0 +1,118 @@
+//! Data symbols are moved to the modules that use them, even when a segment
+//! holds the data of several modules.
+
+mod common;
+
+use common::{call, data_address, function, DataSegment, DataSymbol, Fixture};
+use wasm_split_lib::Splitter;
+
+const SPLIT_ID_A: &str = "0123456789abcdef0123456789abcdef";
+const SPLIT_ID_B: &str = "fedcba9876543210fedcba9876543210";
+
+fn data_symbol(name: &str, segment: u32, offset: u32, size: u32) -> DataSymbol {
+    DataSymbol {
+        name: name.to_string(),
+        segment,
+        offset,
+        size,
+    }
+}
+
+/// Builds a module whose first segment interleaves the data of main, `a`, `b`
+/// and the chunk shared by `a` and `b`, followed by a passive segment and a
+/// segment used only by `b`.
+fn fixture() -> Fixture {
+    let mut fixture = Fixture {
+        functions: vec![
+            function(
+                "main",
+                vec![
+                    call(&common::split_import("a", SPLIT_ID_A)),
+                    call(&common::split_import("b", SPLIT_ID_B)),
+                    data_address("main_data"),
+                ],
+            ),
+            function("shared", vec![data_address("shared_data")]),
+        ],
+        exports: vec![("main".to_string(), "main".to_string())],
+        data_segments: vec![
+            DataSegment {
+                address: Some(1024),
+                data: [[0xaa; 8], [0x11; 8], [0xbb; 8], [0xcc; 8]].concat(),
+            },
+            DataSegment {
+                address: None,
+                data: vec![0x55; 4],
+            },
+            DataSegment {
+                address: Some(2048),
+                data: vec![0xbd; 4],
+            },
+        ],
+        data_symbols: vec![
+            data_symbol("a_data", 0, 0, 8),
+            data_symbol("main_data", 0, 8, 8),
+            data_symbol("b_data", 0, 16, 8),
+            data_symbol("shared_data", 0, 24, 8),
+            data_symbol("b_data_2", 2, 0, 4),
+        ],
+        ..Default::default()
+    };
+    fixture.add_split_point(
+        "a",
+        SPLIT_ID_A,
+        function("a_entry", vec![data_address("a_data"), call("shared")]),
+    );
+    fixture.add_split_point(
+        "b",
+        SPLIT_ID_B,
+        function(
+            "b_entry",
+            vec![
+                data_address("b_data"),
+                data_address("b_data_2"),
+                call("shared"),
+            ],
+        ),
+    );
+    fixture
+}
+
+/// Returns the contents of the active segments of `module`, without empty
+/// segments.
+fn active_data(module: &common::ParsedModule) -> Vec<(u32, Vec<u8>)> {
+    module
+        .data_segments
+        .iter()
+        .filter_map(|(address, data)| Some(((*address)?, data.clone())))
+        .filter(|(_, data)| !data.is_empty())
+        .collect()
+}
+
+#[test]
+fn data_symbols_are_split_within_segments() {
+    let fixture = fixture();
+    let output = Splitter::new().split(&fixture.encode()).unwrap();
+    common::check_references(&fixture, &output);
+
+    let modules = common::parse_output(&output);
+    let names: Vec<&str> = modules.iter().map(|module| module.name.as_str()).collect();
+    assert_eq!(names, ["main", "a", "b", "a_b"]);
+    let [main, a, b, a_b] = &modules[..] else {
+        unreachable!();
+    };
+
+    // Main keeps the index of each input segment, so that `memory.init` and
+    // `data.drop` of passive segments remain valid.
+    assert_eq!(main.data_segments.len(), 3);
+    assert_eq!(main.data_segments[0], (Some(1032), vec![0x11; 8]));
+    assert_eq!(main.data_segments[1], (None, vec![0x55; 4]));
+    assert_eq!(active_data(main), [(1032, vec![0x11; 8])]);
+
+    assert_eq!(active_data(a), [(1024, vec![0xaa; 8])]);
+    assert_eq!(
+        active_data(b),
+        [(1040, vec![0xbb; 8]), (2048, vec![0xbd; 4])]
+    );
+    assert_eq!(active_data(a_b), [(1048, vec![0xcc; 8])]);
+}
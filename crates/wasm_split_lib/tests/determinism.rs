@@ -0,0 +1,306 @@
+//! Splitting the same input must produce byte-for-byte identical output, since
+//! build caches and content hashes depend on it.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use wasm_encoder::{
+    ConstExpr, CustomSection, DataSection, DataSymbolDefinition, Encode, EntityType, ExportKind,
+    ExportSection, FunctionSection, ImportSection, LinkingSection, MemorySection, MemoryType,
+    Module, NameMap, NameSection, SymbolTable, TypeSection,
+};
+use wasm_split_lib::{GraphFormat, Splitter};
+
+const SPLIT_ID_A: &str = "0123456789abcdef0123456789abcdef";
+const SPLIT_ID_B: &str = "fedcba9876543210fedcba9876543210";
+
+// Relocation types, see
+// https://github.com/WebAssembly/tool-conventions/blob/main/Linking.md
+const R_WASM_FUNCTION_INDEX_LEB: u8 = 0;
+const R_WASM_TABLE_INDEX_SLEB: u8 = 1;
+const R_WASM_MEMORY_ADDR_SLEB: u8 = 4;
+
+/// Number of functions in each group of the fixture, enough that hash map
+/// iteration order would be likely to differ between runs.
+const GROUP_SIZE: u32 = 16;
+
+enum Instruction {
+    Call(u32),
+    /// `i32.const` with the table index of a function.
+    FunctionAddress(u32),
+    /// `i32.const` with the address of a data symbol.
+    DataAddress(u32),
+}
+
+struct Function {
+    name: String,
+    body: Vec<Instruction>,
+}
+
+/// Builds a relocatable module with a main export, split modules `a` and `b`,
+/// functions and data used by each, and functions shared by both.
+fn fixture() -> Vec<u8> {
+    let imports = [
+        ("env", "log".to_string()),
+        (
+            "./__wasm_split.js",
+            format!("__wasm_split_00a00_import_{SPLIT_ID_A}"),
+        ),
+        (
+            "./__wasm_split.js",
+            format!("__wasm_split_00b00_import_{SPLIT_ID_B}"),
+        ),
+    ];
+    let num_imports = imports.len() as u32;
+    let (log, import_a, import_b) = (0, 1, 2);
+
+    // Defined functions: main, the two split entry points, then the shared,
+    // `a`-only and `b`-only groups.
+    let main = num_imports;
+    let export_a = main + 1;
+    let export_b = main + 2;
+    let shared = |i: u32| main + 3 + i;
+    let a_only = |i: u32| main + 3 + GROUP_SIZE + i;
+    let b_only = |i: u32| main + 3 + 2 * GROUP_SIZE + i;
+    // Data symbol indices, which follow the function symbols.
+    let num_funcs = main + 3 + 3 * GROUP_SIZE;
+    let (a_data, b_data) = (num_funcs, num_funcs + 1);
+
+    let mut functions = vec![
+        Function {
+            name: "main".to_string(),
+            body: vec![
+                Instruction::Call(log),
+                Instruction::Call(import_a),
+                Instruction::Call(import_b),
+                Instruction::FunctionAddress(log),
+            ],
+        },
+        Function {
+            name: "a_entry".to_string(),
+            body: (0..GROUP_SIZE)
+                .flat_map(|i| [Instruction::Call(shared(i)), Instruction::Call(a_only(i))])
+                .collect(),
+        },
+        Function {
+            name: "b_entry".to_string(),
+            body: (0..GROUP_SIZE)
+                .flat_map(|i| [Instruction::Call(shared(i)), Instruction::Call(b_only(i))])
+                .collect(),
+        },
+    ];
+    for i in 0..GROUP_SIZE {
+        functions.push(Function {
+            name: format!("shared_{i}"),
+            body: vec![Instruction::Call(log)],
+        });
+    }
+    for i in 0..GROUP_SIZE {
+        functions.push(Function {
+            name: format!("a_only_{i}"),
+            body: vec![
+                Instruction::DataAddress(a_data),
+                Instruction::FunctionAddress(shared(i)),
+            ],
+        });
+    }
+    for i in 0..GROUP_SIZE {
+        functions.push(Function {
+            name: format!("b_only_{i}"),
+            body: vec![
+                Instruction::DataAddress(b_data),
+                Instruction::Call(shared((i + 1) % GROUP_SIZE)),
+            ],
+        });
+    }
+
+    let mut module = Module::new();
+
+    let mut types = TypeSection::new();
+    types.function([], []);
+    module.section(&types);
+
+    let mut import_section = ImportSection::new();
+    for (module_name, name) in imports.iter() {
+        import_section.import(module_name, name, EntityType::Function(0));
+    }
+    module.section(&import_section);
+
+    let mut function_section = FunctionSection::new();
+    for _ in functions.iter() {
+        function_section.function(0);
+    }
+    module.section(&function_section);
+
+    let mut memories = MemorySection::new();
+    memories.memory(MemoryType {
+        minimum: 1,
+        maximum: None,
+        memory64: false,
+        shared: false,
+        page_size_log2: None,
+    });
+    module.section(&memories);
+
+    let mut exports = ExportSection::new();
+    exports.export("memory", ExportKind::Memory, 0);
+    exports.export("main", ExportKind::Func, main);
+    exports.export(
+        &format!("__wasm_split_00a00_export_{SPLIT_ID_A}"),
+        ExportKind::Func,
+        export_a,
+    );
+    exports.export(
+        &format!("__wasm_split_00b00_export_{SPLIT_ID_B}"),
+        ExportKind::Func,
+        export_b,
+    );
+    module.section(&exports);
+
+    // The code section is encoded manually to record the offsets of the
+    // relocations, which use padded LEB128 encodings.
+    let mut code = Vec::new();
+    let mut code_relocs = Vec::new();
+    (functions.len() as u32).encode(&mut code);
+    for function in functions.iter() {
+        let mut body = vec![0]; // No locals.
+        let mut body_relocs = Vec::new();
+        for instruction in function.body.iter() {
+            let (opcode, ty, symbol) = match *instruction {
+                Instruction::Call(func) => (0x10, R_WASM_FUNCTION_INDEX_LEB, func),
+                Instruction::FunctionAddress(func) => (0x41, R_WASM_TABLE_INDEX_SLEB, func),
+                Instruction::DataAddress(symbol) => (0x41, R_WASM_MEMORY_ADDR_SLEB, symbol),
+            };
+            body.push(opcode);
+            body_relocs.push((ty, body.len(), symbol));
+            body.extend_from_slice(&[0x80, 0x80, 0x80, 0x80, 0x00]);
+            if opcode == 0x41 {
+                body.push(0x1a); // drop
+            }
+        }
+        body.push(0x0b); // end
+        (body.len() as u32).encode(&mut code);
+        for (ty, offset, symbol) in body_relocs {
+            code_relocs.push((ty, code.len() + offset, symbol));
+        }
+        code.extend_from_slice(&body);
+    }
+    module.section(&wasm_encoder::RawSection {
+        id: wasm_encoder::SectionId::Code as u8,
+        data: &code,
+    });
+
+    let mut data = DataSection::new();
+    data.active(0, &ConstExpr::i32_const(1024), [1u8; 16]);
+    module.section(&data);
+
+    let mut symbols = SymbolTable::new();
+    for func in 0..num_imports {
+        symbols.function(SymbolTable::WASM_SYM_UNDEFINED, func, None);
+    }
+    for (index, function) in functions.iter().enumerate() {
+        symbols.function(0, num_imports + index as u32, Some(&function.name));
+    }
+    for (name, offset) in [("a_data", 0), ("b_data", 8)] {
+        symbols.data(
+            0,
+            name,
+            Some(DataSymbolDefinition {
+                index: 0,
+                offset,
+                size: 8,
+            }),
+        );
+    }
+    let mut linking = LinkingSection::new();
+    linking.symbol_table(&symbols);
+    module.section(&linking);
+
+    // Index of the code section: type, import, function, memory, export, code.
+    let code_section_index = 5u32;
+    let mut reloc = Vec::new();
+    code_section_index.encode(&mut reloc);
+    (code_relocs.len() as u32).encode(&mut reloc);
+    for (ty, offset, symbol) in code_relocs {
+        reloc.push(ty);
+        (offset as u32).encode(&mut reloc);
+        symbol.encode(&mut reloc);
+        if ty == R_WASM_MEMORY_ADDR_SLEB {
+            0i32.encode(&mut reloc);
+        }
+    }
+    module.section(&CustomSection {
+        name: "reloc.CODE".into(),
+        data: reloc.into(),
+    });
+
+    let mut function_names = NameMap::new();
+    for (func, (_, name)) in imports.iter().enumerate() {
+        function_names.append(func as u32, name);
+    }
+    for (index, function) in functions.iter().enumerate() {
+        function_names.append(num_imports + index as u32, &function.name);
+    }
+    let mut names = NameSection::new();
+    names.functions(&function_names);
+    module.section(&names);
+
+    module.finish()
+}
+
+/// Returns the name and hash of every output file.
+fn split_hashes(input: &[u8]) -> Vec<(String, u64)> {
+    let output = Splitter::new()
+        .report(true)
+        .graph(Some(GraphFormat::Dot))
+        .split(input)
+        .unwrap();
+    let hash = |data: &[u8]| {
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+        hasher.finish()
+    };
+    let mut hashes: Vec<_> = output
+        .modules
+        .iter()
+        .map(|module| (module.file_name(), hash(&module.wasm)))
+        .collect();
+    hashes.push((
+        "__wasm_split.js".to_string(),
+        hash(output.loader.as_bytes()),
+    ));
+    hashes.push((
+        "report.json".to_string(),
+        hash(output.report.unwrap().to_json().as_bytes()),
+    ));
+    hashes.push((
+        "graph.dot".to_string(),
+        hash(output.graph.unwrap().contents.as_bytes()),
+    ));
+    hashes
+}
+
+#[test]
+fn split_output_is_deterministic() {
+    let input = fixture();
+    let expected = split_hashes(&input);
+    let names: Vec<_> = expected.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "main.wasm",
+            "a.wasm",
+            "b.wasm",
+            "a_b.wasm",
+            "__wasm_split.js",
+            "report.json",
+            "graph.dot"
+        ]
+    );
+    // Each split uses hash maps seeded differently.
+    for _ in 0..8 {
+        assert_eq!(split_hashes(&input), expected);
+    }
+}
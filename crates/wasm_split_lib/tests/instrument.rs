@@ -0,0 +1,146 @@
+//! Instrumentation of every function with a call counter, for collecting a
+//! profile.
+
+mod common;
+
+use common::{call, data_address, function, DataSegment, DataSymbol, Fixture, OutputOperator};
+use wasm_split_lib::{LoaderTarget, Profile, SplitOutput, Splitter};
+
+const SPLIT_ID: &str = "0123456789abcdef0123456789abcdef";
+
+/// Builds a module whose `alloc` function uses `__heap_base`, with a split
+/// module `a`.
+fn fixture(heap_base: u32) -> Fixture {
+    let mut fixture = Fixture {
+        imports: vec![("env".to_string(), "log".to_string())],
+        functions: vec![
+            function(
+                "main",
+                vec![call(&common::split_import("a", SPLIT_ID)), call("alloc")],
+            ),
+            function("alloc", vec![data_address("__heap_base")]),
+        ],
+        exports: vec![("main".to_string(), "main".to_string())],
+        data_segments: vec![DataSegment {
+            address: Some(1024),
+            data: vec![0xaa; 8],
+        }],
+        data_symbols: vec![DataSymbol {
+            name: "static_data".to_string(),
+            segment: 0,
+            offset: 0,
+            size: 8,
+        }],
+        absolute_symbols: vec![("__heap_base".to_string(), heap_base)],
+    };
+    fixture.add_split_point("a", SPLIT_ID, function("a_entry", vec![call("log")]));
+    fixture
+}
+
+fn instrument(fixture: &Fixture) -> SplitOutput {
+    Splitter::new()
+        .instrument(true)
+        .split(&fixture.encode())
+        .unwrap()
+}
+
+/// Returns the initial size in pages of the memory of the main module.
+fn initial_memory(output: &SplitOutput) -> u64 {
+    wasmparser::Parser::new(0)
+        .parse_all(&output.modules[0].wasm)
+        .find_map(|payload| match payload.unwrap() {
+            wasmparser::Payload::MemorySection(reader) => {
+                Some(reader.into_iter().next().unwrap().unwrap().initial)
+            }
+            _ => None,
+        })
+        .unwrap()
+}
+
+#[test]
+fn functions_increment_counters_after_heap_base() {
+    let output = instrument(&fixture(1032));
+    assert_eq!(
+        output.profile_symbols.as_deref(),
+        Some("main\nalloc\na_entry\n")
+    );
+
+    // One 8-byte counter per function from the original `__heap_base`,
+    // aligned to 16 bytes, which moves past the counters.
+    let counter = |index: i32| OutputOperator::I32Const(1040 + 8 * index);
+    let modules = common::parse_output(&output);
+    let [main, a] = &modules[..] else {
+        panic!("Unexpected modules {:?}", output.modules);
+    };
+    let operators = |module: &common::ParsedModule, name: &str| {
+        module.function(name).unwrap().operators.clone()
+    };
+    assert_eq!(operators(main, "main")[..2], [counter(0), counter(0)]);
+    assert_eq!(
+        operators(main, "alloc"),
+        [counter(1), counter(1), OutputOperator::I32Const(1072)]
+    );
+    assert_eq!(operators(a, "a_entry")[..2], [counter(2), counter(2)]);
+
+    assert!(main
+        .exports
+        .contains(&"__wasm_split_profile_counters".to_string()));
+    assert_eq!(
+        operators(main, "__wasm_split_profile_counters"),
+        [counter(0)]
+    );
+    assert_eq!(initial_memory(&output), 1);
+}
+
+#[test]
+fn counters_past_initial_memory_grow_it() {
+    let output = instrument(&fixture(65_528));
+    let modules = common::parse_output(&output);
+    assert_eq!(
+        modules[0].function("alloc").unwrap().operators[2],
+        OutputOperator::I32Const(65_536 + 32)
+    );
+    assert_eq!(initial_memory(&output), 2);
+}
+
+#[test]
+fn loader_returns_counters_and_bitmap() {
+    let fixture = fixture(1032);
+    let output = Splitter::new()
+        .instrument(true)
+        .target(LoaderTarget::Custom(
+            r#"const memory = new WebAssembly.Memory({ initial: 1 });
+new BigUint64Array(memory.buffer, 1040, 3).set([3n, 0n, 1n]);
+function mainExports() {
+  return { memory, __wasm_split_profile_counters: () => 1040 };
+}
+function moduleUrl(file) {
+  return file;
+}
+{{RUNTIME}}
+console.log(profileCounters().join(" "));
+console.log(profileBitmap().join(" "));
+"#
+            .to_string(),
+        ))
+        .split(&fixture.encode())
+        .unwrap();
+    let Some(stdout) = common::run_node("loader_returns_counters_and_bitmap", &output.loader)
+    else {
+        return;
+    };
+    assert_eq!(stdout, "3 0 1\n5\n");
+}
+
+#[test]
+fn bitmaps_are_merged_into_profile() {
+    let symbols = "main\nalloc\na_entry\n";
+    let profile = Profile::from_bitmaps(symbols, [&[0b001][..], &[0b100][..]]).unwrap();
+    assert_eq!(profile.to_string(), "a_entry\nmain\n");
+
+    let error = Profile::from_bitmaps(symbols, [&[0, 0][..]]).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Bitmap 0 has 2 bytes, but the symbol map requires 1"
+    );
+}
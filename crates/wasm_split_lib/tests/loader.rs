@@ -0,0 +1,341 @@
+//! The generated loader for each target. The runtime is run with Node.js
+//! through custom templates that stub out the main module and the fetching of
+//! modules.
+
+mod common;
+
+use std::path::PathBuf;
+
+use base64::Engine;
+use common::{call, function, split_id, split_import, Fixture};
+use sha2::{Digest, Sha384};
+use wasm_split_lib::{LoaderTarget, Profile, SplitOutput, Splitter};
+
+const SPLIT_ID: &str = "0123456789abcdef0123456789abcdef";
+
+/// Splits a module with a split module `a` and a cold function, using
+/// `template` for the loader.
+fn split_with_cold_module(template: &str) -> SplitOutput {
+    let mut fixture = Fixture {
+        imports: vec![("env".to_string(), "log".to_string())],
+        functions: vec![
+            function(
+                "main",
+                vec![call(&common::split_import("a", SPLIT_ID)), call("cold")],
+            ),
+            function("cold", vec![call("log")]),
+        ],
+        exports: vec![("main".to_string(), "main".to_string())],
+        ..Default::default()
+    };
+    fixture.add_split_point("a", SPLIT_ID, function("a_entry", vec![call("log")]));
+    Splitter::new()
+        .profile(Some(Profile::parse("main\na_entry\n")))
+        .target(LoaderTarget::Custom(template.to_string()))
+        .split(&fixture.encode())
+        .unwrap()
+}
+
+/// Bytes hashed by the test of `sha384`.
+const TEST_BYTES: &str = "Uint8Array.from({ length }, (_, i) => (i * 7 + (i >> 8)) & 0xff)";
+
+fn test_bytes(length: usize) -> Vec<u8> {
+    (0..length)
+        .map(|i| ((i * 7 + (i >> 8)) & 0xff) as u8)
+        .collect()
+}
+
+#[test]
+fn sync_sha384_matches_reference() {
+    // Padding needs an extra block from 112 bytes into each 128-byte block.
+    let lengths = [
+        0, 1, 3, 111, 112, 113, 127, 128, 129, 239, 240, 255, 256, 1000, 100_000,
+    ];
+    let output = split_with_cold_module(&format!(
+        r#"function mainExports() {{}}
+function moduleUrl(file) {{
+  return file;
+}}
+function readModule(url, integrity) {{
+  return new Promise(() => {{}});
+}}
+function readModuleSync(url) {{}}
+{{{{RUNTIME}}}}
+for (const length of {lengths:?}) {{
+  console.log(formatIntegrity(sha384({TEST_BYTES})));
+}}
+"#
+    ));
+    let Some(stdout) = common::run_node("sync_sha384_matches_reference", &output.loader) else {
+        return;
+    };
+    let expected: Vec<String> = lengths
+        .iter()
+        .map(|&length| {
+            format!(
+                "sha384-{}",
+                base64::engine::general_purpose::STANDARD
+                    .encode(Sha384::digest(test_bytes(length)))
+            )
+        })
+        .collect();
+    assert_eq!(stdout.lines().collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn sync_cold_load_rejects_integrity_mismatch() {
+    let output = split_with_cold_module(
+        r#"function mainExports() {
+  throw new Error("Instantiated main_cold");
+}
+function moduleUrl(file) {
+  return file;
+}
+function readModule(url, integrity) {
+  return new Promise(() => {});
+}
+function readModuleSync(url) {
+  return new Uint8Array([0, 97, 115, 109, 1, 0, 0, 0]);
+}
+{{RUNTIME}}
+for (let i = 0; i < 2; ++i) {
+  try {
+    __wasm_split_load_main_cold_sync();
+  } catch (e) {
+    console.log(e.message);
+  }
+}
+"#,
+    );
+    let Some(stdout) =
+        common::run_node("sync_cold_load_rejects_integrity_mismatch", &output.loader)
+    else {
+        return;
+    };
+    assert_eq!(
+        stdout,
+        "Integrity check failed for main_cold.wasm\n".repeat(2)
+    );
+}
+
+/// Splits a module in which main calls `a`, which calls the nested module
+/// `b`, using `target` for the loader.
+fn split_nested(target: LoaderTarget) -> SplitOutput {
+    let mut fixture = Fixture {
+        imports: vec![("env".to_string(), "log".to_string())],
+        functions: vec![function(
+            "main",
+            vec![call(&split_import("a", &split_id(1)))],
+        )],
+        exports: vec![("main".to_string(), "main".to_string())],
+        ..Default::default()
+    };
+    fixture.add_split_point(
+        "a",
+        &split_id(1),
+        function("a_entry", vec![call(&split_import("b", &split_id(2)))]),
+    );
+    fixture.add_split_point("b", &split_id(2), function("b_entry", vec![call("log")]));
+    Splitter::new()
+        .target(target)
+        .split(&fixture.encode())
+        .unwrap()
+}
+
+#[test]
+fn targets_use_their_templates() {
+    let targets = [
+        (
+            LoaderTarget::Web,
+            [
+                "import { initSync } from \"./main.js\";",
+                "export { setRetryPolicy, ",
+            ],
+        ),
+        (
+            LoaderTarget::Node,
+            [
+                "const fs = require(\"node:fs\");",
+                "Object.assign(module.exports, { setRetryPolicy, ",
+            ],
+        ),
+        (
+            LoaderTarget::Worker,
+            [
+                "const baseUrl = self.location.href;",
+                "globalThis.__wasm_split = { setRetryPolicy, ",
+            ],
+        ),
+        (
+            LoaderTarget::NoModules,
+            [
+                "const baseUrl = document.currentScript.src;",
+                "globalThis.__wasm_split = { setRetryPolicy, ",
+            ],
+        ),
+    ];
+    for (target, expected_lines) in targets {
+        let output = split_nested(target.clone());
+        let loader = &output.loader;
+        for line in expected_lines {
+            assert!(
+                loader
+                    .lines()
+                    .any(|loader_line| loader_line.starts_with(line)),
+                "{target:?} loader has no line {line:?}:\n{loader}"
+            );
+        }
+        assert!(
+            !loader.contains("{{"),
+            "{target:?} loader has a placeholder"
+        );
+        for name in ["__wasm_split_load_a", "__wasm_split_load_b", "preload"] {
+            assert!(
+                loader.contains(&format!(", {name}")),
+                "{target:?} loader does not export {name}"
+            );
+        }
+        assert!(loader.contains("const __wasm_split_load_a = makeLoad("));
+    }
+}
+
+#[test]
+fn custom_template_placeholders() {
+    let output = split_nested(LoaderTarget::Custom(
+        "exports: {{EXPORTS}}\nmodules: {{MODULES}}\n".to_string(),
+    ));
+    let mut lines = output.loader.lines();
+    let exports: Vec<&str> = lines
+        .next()
+        .unwrap()
+        .strip_prefix("exports: ")
+        .unwrap()
+        .split(", ")
+        .collect();
+    for name in [
+        "setRetryPolicy",
+        "preload",
+        "status",
+        "splitModuleNames",
+        "__wasm_split_load_main",
+        "__wasm_split_load_a",
+        "__wasm_split_load_b",
+    ] {
+        assert!(exports.contains(&name), "{name} is not in {exports:?}");
+    }
+
+    let modules: serde_json::Value =
+        serde_json::from_str(lines.next().unwrap().strip_prefix("modules: ").unwrap()).unwrap();
+    let integrity = |name: &str| {
+        let module = output
+            .modules
+            .iter()
+            .find(|module| module.name == name)
+            .unwrap();
+        format!(
+            "sha384-{}",
+            base64::engine::general_purpose::STANDARD.encode(Sha384::digest(&module.wasm))
+        )
+    };
+    // Dependencies precede the modules that use them.
+    assert_eq!(
+        modules,
+        serde_json::json!([
+            {
+                "name": "a",
+                "kind": "split",
+                "file": "a.wasm",
+                "integrity": integrity("a"),
+                "deps": [],
+            },
+            { "name": "main", "kind": "main", "file": "main.wasm", "integrity": null, "deps": [] },
+            {
+                "name": "b",
+                "kind": "split",
+                "file": "b.wasm",
+                "integrity": integrity("b"),
+                "deps": ["a"],
+            },
+        ])
+    );
+}
+
+/// Returns a module exporting `callback(data, status)`, which forwards its
+/// arguments to the imported `env.report`, to be placed in the table through
+/// which the loader reports the status of a load.
+fn callback_module() -> Vec<u8> {
+    use wasm_encoder::{
+        CodeSection, EntityType, ExportKind, ExportSection, Function, FunctionSection,
+        ImportSection, Instruction, Module, TypeSection, ValType,
+    };
+    let mut types = TypeSection::new();
+    types.function([ValType::I32, ValType::I32], []);
+    let mut imports = ImportSection::new();
+    imports.import("env", "report", EntityType::Function(0));
+    let mut functions = FunctionSection::new();
+    functions.function(0);
+    let mut exports = ExportSection::new();
+    exports.export("callback", ExportKind::Func, 1);
+    let mut body = Function::new([]);
+    body.instruction(&Instruction::LocalGet(0));
+    body.instruction(&Instruction::LocalGet(1));
+    body.instruction(&Instruction::Call(0));
+    body.instruction(&Instruction::End);
+    let mut code = CodeSection::new();
+    code.function(&body);
+    let mut module = Module::new();
+    module
+        .section(&types)
+        .section(&imports)
+        .section(&functions)
+        .section(&exports)
+        .section(&code);
+    module.finish()
+}
+
+#[test]
+fn node_loader_reports_integrity_mismatch() {
+    let output = split_nested(LoaderTarget::Node);
+    let dir =
+        PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("node_loader_reports_integrity_mismatch");
+    let _ = std::fs::remove_dir_all(&dir);
+    output.write_to_dir(&dir).unwrap();
+    // Modified after splitting, so that its digest no longer matches.
+    let mut a = std::fs::read(dir.join("a.wasm")).unwrap();
+    a.extend_from_slice(&[0, 1, 0]);
+    std::fs::write(dir.join("a.wasm"), a).unwrap();
+    std::fs::write(
+        dir.join("main.js"),
+        format!(
+            r#"const callbackModule = new WebAssembly.Module(new Uint8Array({bytes:?}));
+const {{ callback }} = new WebAssembly.Instance(callbackModule, {{
+  env: {{ report: (data, status) => console.log("callback", data, status) }},
+}}).exports;
+const table = new WebAssembly.Table({{ element: "anyfunc", initial: 2 }});
+table.set(1, callback);
+exports.__wasm = {{ __indirect_function_table: table }};
+"#,
+            bytes = callback_module(),
+        ),
+    )
+    .unwrap();
+
+    let loader_path = dir.join("__wasm_split.js");
+    let Some(stdout) = common::run_node(
+        "node_loader_reports_integrity_mismatch",
+        &format!(
+            r#"const loader = require({loader_path:?});
+(async () => {{
+  await loader.__wasm_split_load_a(1, 42);
+  console.log("status", loader.status("a"));
+  // A nested module fails with its parent.
+  console.log("preload", await loader.preload("b"));
+}})();
+"#
+        ),
+    ) else {
+        return;
+    };
+    // `LOAD_INTEGRITY_FAILED` and `LOAD_DEPENDENCY_FAILED`.
+    assert_eq!(stdout, "callback 42 4\nstatus failed\npreload 3\n");
+}
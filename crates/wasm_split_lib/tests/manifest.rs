@@ -0,0 +1,126 @@
+//! Content-hashed file names and the manifest describing them.
+
+mod common;
+
+use std::path::PathBuf;
+
+use base64::Engine;
+use common::{call, function, split_id, split_import, Fixture};
+use sha2::{Digest, Sha384};
+use wasm_split_lib::{SplitOutput, Splitter};
+
+/// Builds a module in which main calls split modules `a` and `b`.
+fn fixture() -> Fixture {
+    let mut fixture = Fixture {
+        imports: vec![("env".to_string(), "log".to_string())],
+        functions: vec![function(
+            "main",
+            vec![
+                call(&split_import("a", &split_id(1))),
+                call(&split_import("b", &split_id(2))),
+            ],
+        )],
+        exports: vec![("main".to_string(), "main".to_string())],
+        ..Default::default()
+    };
+    fixture.add_split_point("a", &split_id(1), function("a_entry", vec![call("log")]));
+    fixture.add_split_point(
+        "b",
+        &split_id(2),
+        function("b_entry", vec![call("log"), call("log")]),
+    );
+    fixture
+}
+
+fn split(hash_file_names: bool) -> SplitOutput {
+    Splitter::new()
+        .hash_file_names(hash_file_names)
+        .split(&fixture().encode())
+        .unwrap()
+}
+
+#[test]
+fn split_modules_are_named_after_their_contents() {
+    let output = split(true);
+    let file_names: Vec<String> = output.modules.iter().map(|m| m.file_name()).collect();
+    let hash = |wasm: &[u8]| -> String {
+        Sha384::digest(wasm)[..8]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    };
+    assert_eq!(
+        file_names,
+        [
+            "main.wasm".to_string(),
+            format!("a.{}.wasm", hash(&output.modules[1].wasm)),
+            format!("b.{}.wasm", hash(&output.modules[2].wasm)),
+        ]
+    );
+    assert_ne!(file_names[1], file_names[2]);
+    // The loader fetches the hashed files.
+    for file_name in file_names.iter() {
+        assert!(
+            output
+                .loader
+                .contains(&format!("moduleUrl(\"{file_name}\")")),
+            "{file_name} is not loaded"
+        );
+    }
+}
+
+#[test]
+fn manifest_describes_each_module() {
+    let output = split(true);
+    let manifest: serde_json::Value =
+        serde_json::from_str(&output.manifest.as_ref().unwrap().to_json()).unwrap();
+    let integrity = |wasm: &[u8]| {
+        format!(
+            "sha384-{}",
+            base64::engine::general_purpose::STANDARD.encode(Sha384::digest(wasm))
+        )
+    };
+    let [main, a, b] = &output.modules[..] else {
+        unreachable!();
+    };
+    assert_eq!(
+        manifest,
+        serde_json::json!({
+            "modules": {
+                "main": { "file": "main.wasm", "size": main.wasm.len() },
+                "a": {
+                    "file": a.file_name(),
+                    "size": a.wasm.len(),
+                    "integrity": integrity(&a.wasm),
+                },
+                "b": {
+                    "file": b.file_name(),
+                    "size": b.wasm.len(),
+                    "integrity": integrity(&b.wasm),
+                },
+            }
+        })
+    );
+
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("manifest_describes_each_module");
+    let _ = std::fs::remove_dir_all(&dir);
+    output.write_to_dir(&dir).unwrap();
+    for module in output.modules.iter() {
+        assert_eq!(
+            std::fs::read(dir.join(module.file_name())).unwrap(),
+            module.wasm
+        );
+    }
+    assert_eq!(
+        std::fs::read_to_string(dir.join("manifest.json")).unwrap(),
+        output.manifest.unwrap().to_json()
+    );
+}
+
+#[test]
+fn file_names_are_plain_by_default() {
+    let output = split(false);
+    assert!(output.manifest.is_none());
+    let file_names: Vec<String> = output.modules.iter().map(|m| m.file_name()).collect();
+    assert_eq!(file_names, ["main.wasm", "a.wasm", "b.wasm"]);
+}
@@ -0,0 +1,185 @@
+//! Split modules whose split points are only called from one other split
+//! module are nested in it, and loaded after it.
+
+mod common;
+
+use common::{call, function, split_id, split_import, Fixture};
+use wasm_split_lib::{ModuleReport, SplitConfig, SplitOutput, Splitter};
+
+/// Builds a module in which main calls `a`, `a` calls `b` and `c`, `b` calls
+/// `c`, and `d` and `e` only call each other.
+fn fixture() -> Fixture {
+    let mut fixture = Fixture {
+        imports: vec![("env".to_string(), "log".to_string())],
+        functions: vec![function(
+            "main",
+            vec![call(&split_import("a", &split_id(1)))],
+        )],
+        exports: vec![("main".to_string(), "main".to_string())],
+        ..Default::default()
+    };
+    fixture.add_split_point(
+        "a",
+        &split_id(1),
+        function(
+            "a_entry",
+            vec![
+                call(&split_import("b", &split_id(2))),
+                call(&split_import("c", &split_id(3))),
+                call("shared_a_b"),
+            ],
+        ),
+    );
+    fixture.add_split_point(
+        "b",
+        &split_id(2),
+        function(
+            "b_entry",
+            vec![
+                call(&split_import("c", &split_id(3))),
+                call("shared_a_b"),
+                call("b_only"),
+            ],
+        ),
+    );
+    fixture.add_split_point("c", &split_id(3), function("c_entry", vec![call("log")]));
+    fixture.add_split_point(
+        "d",
+        &split_id(4),
+        function("d_entry", vec![call(&split_import("e", &split_id(5)))]),
+    );
+    fixture.add_split_point(
+        "e",
+        &split_id(5),
+        function("e_entry", vec![call(&split_import("d", &split_id(4)))]),
+    );
+    fixture.functions.extend([
+        function("shared_a_b", vec![call("log")]),
+        function("b_only", vec![call("log")]),
+    ]);
+    fixture
+}
+
+fn split(fixture: &Fixture, config: SplitConfig) -> anyhow::Result<SplitOutput> {
+    Splitter::new()
+        .report(true)
+        .config(config)
+        .split(&fixture.encode())
+}
+
+fn module_report<'a>(output: &'a SplitOutput, name: &str) -> &'a ModuleReport {
+    let report = output.report.as_ref().unwrap();
+    report
+        .modules
+        .iter()
+        .find(|module| module.name == name)
+        .unwrap_or_else(|| panic!("No module {name}"))
+}
+
+/// Returns the dependencies of the loader of `module_name`.
+fn loader_deps<'a>(output: &'a SplitOutput, module_name: &str) -> &'a str {
+    let prefix = format!("const __wasm_split_load_{module_name} = makeLoad(");
+    let line = output
+        .loader
+        .lines()
+        .find(|line| line.starts_with(&prefix))
+        .unwrap_or_else(|| panic!("No loader for {module_name}"));
+    let start = line.rfind('[').unwrap();
+    &line[start..]
+}
+
+#[test]
+fn split_called_from_one_split_is_nested() {
+    let fixture = fixture();
+    let output = split(&fixture, SplitConfig::default()).unwrap();
+    common::check_references(&fixture, &output);
+
+    assert_eq!(module_report(&output, "b").parent.as_deref(), Some("a"));
+    // Called from both `a` and `b`.
+    assert_eq!(module_report(&output, "c").parent, None);
+    // Only called from each other, so one of them is loaded directly to
+    // break the cycle.
+    assert_eq!(module_report(&output, "d").parent, None);
+    assert_eq!(module_report(&output, "e").parent.as_deref(), Some("d"));
+    assert_eq!(module_report(&output, "a").parent, None);
+
+    // Symbols shared with an ancestor are placed in the ancestor rather than
+    // in a chunk.
+    let modules = common::parse_output(&output);
+    let names: Vec<&str> = modules.iter().map(|module| module.name.as_str()).collect();
+    assert_eq!(names, ["main", "a", "b", "c", "d", "e"]);
+    assert_eq!(modules[1].function_names(), ["a_entry", "shared_a_b"]);
+    assert_eq!(modules[2].function_names(), ["b_entry", "b_only"]);
+
+    assert_eq!(loader_deps(&output, "b"), "[__wasm_split_load_a]);");
+    assert_eq!(loader_deps(&output, "c"), "[]);");
+    let position = |module_name: &str| {
+        output
+            .loader
+            .find(&format!("const __wasm_split_load_{module_name} ="))
+            .unwrap()
+    };
+    assert!(position("a") < position("b"));
+}
+
+#[test]
+fn symbols_can_be_pinned_to_ancestor() {
+    let fixture = fixture();
+    let pin = |module_name: &str| {
+        SplitConfig::parse(&format!(
+            "[[pin]]\nsymbols = \"^b_only$\"\nmodule = \"{module_name}\"\n"
+        ))
+        .unwrap()
+    };
+    let output = split(&fixture, pin("a")).unwrap();
+    common::check_references(&fixture, &output);
+    let modules = common::parse_output(&output);
+    assert_eq!(
+        modules[1].function_names(),
+        ["a_entry", "b_only", "shared_a_b"]
+    );
+    assert_eq!(modules[2].function_names(), ["b_entry"]);
+
+    let error = split(&fixture, pin("c")).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "pin \"^b_only$\": cannot place b_only in c because it is reachable from split module \
+         b, which does not load c"
+    );
+}
+
+#[test]
+fn chain_into_cycle_keeps_its_parent() {
+    // `p` is only called from `q`, which only calls, and is only called from,
+    // `r`.
+    let mut fixture = Fixture {
+        imports: vec![("env".to_string(), "log".to_string())],
+        functions: vec![function("main", vec![call("log")])],
+        exports: vec![("main".to_string(), "main".to_string())],
+        ..Default::default()
+    };
+    fixture.add_split_point("p", &split_id(1), function("p_entry", vec![call("log")]));
+    fixture.add_split_point(
+        "q",
+        &split_id(2),
+        function(
+            "q_entry",
+            vec![
+                call(&split_import("p", &split_id(1))),
+                call(&split_import("r", &split_id(3))),
+            ],
+        ),
+    );
+    fixture.add_split_point(
+        "r",
+        &split_id(3),
+        function("r_entry", vec![call(&split_import("q", &split_id(2)))]),
+    );
+    let output = split(&fixture, SplitConfig::default()).unwrap();
+    common::check_references(&fixture, &output);
+
+    assert_eq!(module_report(&output, "p").parent.as_deref(), Some("q"));
+    assert_eq!(module_report(&output, "q").parent, None);
+    assert_eq!(module_report(&output, "r").parent.as_deref(), Some("q"));
+    assert_eq!(loader_deps(&output, "p"), "[__wasm_split_load_q]);");
+}
@@ -0,0 +1,61 @@
+//! Split exports without a corresponding import, such as unused
+//! instantiations of generic split functions, are dropped from the output.
+
+mod common;
+
+use common::{call, function, split_export, split_id, split_import, Fixture};
+use wasm_split_lib::{why, Splitter};
+
+/// Builds a module with split module `a`, and a split export of `a` whose
+/// import the linker removed since it is never called.
+fn fixture() -> Fixture {
+    let mut fixture = Fixture {
+        imports: vec![("env".to_string(), "log".to_string())],
+        functions: vec![function(
+            "main",
+            vec![call(&split_import("a", &split_id(0)))],
+        )],
+        exports: vec![("main".to_string(), "main".to_string())],
+        ..Default::default()
+    };
+    fixture.add_split_point("a", &split_id(0), function("a_entry", vec![call("log")]));
+    fixture.functions.extend([
+        function("unused_instantiation", vec![call("unused_helper")]),
+        function("unused_helper", vec![call("log")]),
+    ]);
+    fixture.exports.push((
+        split_export("a", &split_id(1)),
+        "unused_instantiation".to_string(),
+    ));
+    fixture
+}
+
+#[test]
+fn orphan_split_exports_are_omitted() {
+    let output = Splitter::new().split(&fixture().encode()).unwrap();
+    assert_eq!(
+        output.orphan_split_exports,
+        [split_export("a", &split_id(1))]
+    );
+    assert_eq!(
+        common::module_functions(&output),
+        [
+            ("main".to_string(), vec!["main".to_string()]),
+            ("a".to_string(), vec!["a_entry".to_string()]),
+        ]
+    );
+    let modules = common::parse_output(&output);
+    assert!(modules[0].exports.contains(&"main".to_string()));
+    assert!(!modules[0]
+        .exports
+        .contains(&split_export("a", &split_id(1))));
+}
+
+#[test]
+fn orphan_split_exports_are_not_roots() {
+    let results = why(&fixture().encode(), "^unused_helper$", 5).unwrap();
+    let [result] = &results[..] else {
+        panic!("Unexpected results {results:?}");
+    };
+    assert!(result.paths.is_empty());
+}
@@ -0,0 +1,85 @@
+//! Placement of functions according to a profile.
+
+mod common;
+
+use common::{call, function, function_address, Fixture};
+use wasm_split_lib::{Profile, Splitter};
+
+const SPLIT_ID: &str = "0123456789abcdef0123456789abcdef";
+
+/// Builds a module with main functions and functions of a split module `a`
+/// that are hot or cold in the profile, and cold main functions that must
+/// stay in main.
+fn fixture() -> Fixture {
+    let mut fixture = Fixture {
+        imports: vec![("env".to_string(), "log".to_string())],
+        functions: vec![
+            function(
+                "main",
+                vec![
+                    call(&common::split_import("a", SPLIT_ID)),
+                    call("hot_main"),
+                    call("cold_main"),
+                    function_address("cold_indirect"),
+                ],
+            ),
+            function("hot_main", vec![call("log")]),
+            function("cold_main", vec![call("cold_leaf")]),
+            function("cold_leaf", vec![call("log")]),
+            function("cold_indirect", vec![call("log")]),
+            function("__wbindgen_describe_main", vec![call("describe_helper")]),
+            function("describe_helper", vec![call("log")]),
+        ],
+        exports: vec![
+            ("main".to_string(), "main".to_string()),
+            (
+                "__wbindgen_describe_main".to_string(),
+                "__wbindgen_describe_main".to_string(),
+            ),
+        ],
+        ..Default::default()
+    };
+    fixture.add_split_point(
+        "a",
+        SPLIT_ID,
+        function("a_entry", vec![call("a_hot"), call("a_cold")]),
+    );
+    fixture.functions.push(function("a_hot", vec![call("log")]));
+    fixture
+        .functions
+        .push(function("a_cold", vec![call("log")]));
+    fixture
+}
+
+#[test]
+fn functions_are_placed_by_profile() {
+    let fixture = fixture();
+    let profile = Profile::parse("# Called functions\nmain\nhot_main\n\na_entry\na_hot\n");
+    let output = Splitter::new()
+        .profile(Some(profile))
+        .split(&fixture.encode())
+        .unwrap();
+    common::check_references(&fixture, &output);
+
+    let modules = common::parse_output(&output);
+    let names: Vec<&str> = modules.iter().map(|module| module.name.as_str()).collect();
+    assert_eq!(names, ["main", "a", "main_cold"]);
+    let [main, a, main_cold] = &modules[..] else {
+        unreachable!();
+    };
+    // Descriptors are interpreted by `wasm-bindgen` before the cold module can
+    // be loaded, and functions in the table may be called at any time.
+    assert_eq!(
+        main.function_names(),
+        [
+            "__wbindgen_describe_main",
+            "a_hot",
+            "cold_indirect",
+            "describe_helper",
+            "hot_main",
+            "main"
+        ]
+    );
+    assert_eq!(main_cold.function_names(), ["cold_leaf", "cold_main"]);
+    assert_eq!(a.function_names(), ["a_cold", "a_entry"]);
+}
@@ -0,0 +1,228 @@
+//! Contents of the split report and the dependency graph.
+
+mod common;
+
+use common::{
+    call, data_address, function, function_address, split_id, split_import, DataSegment,
+    DataSymbol, Fixture,
+};
+use wasm_split_lib::{GraphFormat, GraphLevel, ModuleKind, SplitOutput, Splitter, SymbolReport};
+
+/// Builds a module in which main calls split modules `a` and `b`, which share
+/// a function calling back into main, and `b` takes the address of a main
+/// function.
+fn fixture() -> Fixture {
+    let mut fixture = Fixture {
+        imports: vec![("env".to_string(), "log".to_string())],
+        functions: vec![
+            function(
+                "main",
+                vec![
+                    call(&split_import("a", &split_id(0))),
+                    call(&split_import("b", &split_id(1))),
+                    function_address("callback"),
+                ],
+            ),
+            function("callback", vec![call("log")]),
+        ],
+        exports: vec![("main".to_string(), "main".to_string())],
+        data_segments: vec![DataSegment {
+            address: Some(1024),
+            data: vec![0xaa; 8],
+        }],
+        data_symbols: vec![DataSymbol {
+            name: "a_data".to_string(),
+            segment: 0,
+            offset: 0,
+            size: 8,
+        }],
+        ..Default::default()
+    };
+    fixture.add_split_point(
+        "a",
+        &split_id(0),
+        function("a_entry", vec![call("a_helper"), data_address("a_data")]),
+    );
+    fixture.add_split_point(
+        "b",
+        &split_id(1),
+        function(
+            "b_entry",
+            vec![call("shared"), function_address("callback")],
+        ),
+    );
+    fixture.functions.extend([
+        function("a_helper", vec![call("shared")]),
+        function("shared", vec![call("log"), call("callback")]),
+    ]);
+    fixture
+}
+
+fn split(format: GraphFormat, level: GraphLevel) -> SplitOutput {
+    Splitter::new()
+        .report(true)
+        .graph(Some(format))
+        .graph_level(level)
+        .split(&fixture().encode())
+        .unwrap()
+}
+
+/// Returns the name and parent chain of each symbol.
+fn symbols(symbols: &[SymbolReport]) -> Vec<(&str, Vec<&str>)> {
+    symbols
+        .iter()
+        .map(|symbol| {
+            (
+                symbol.name.as_str(),
+                symbol.parents.iter().map(String::as_str).collect(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn report_describes_output_modules() {
+    let output = split(GraphFormat::Dot, GraphLevel::Modules);
+    let report = output.report.as_ref().unwrap();
+    let modules: Vec<(&str, ModuleKind, Vec<&str>)> = report
+        .modules
+        .iter()
+        .map(|module| {
+            (
+                module.name.as_str(),
+                module.kind,
+                module.splits.iter().map(String::as_str).collect(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        modules,
+        [
+            ("main", ModuleKind::Main, vec![]),
+            ("a", ModuleKind::Split, vec![]),
+            ("b", ModuleKind::Split, vec![]),
+            ("a_b", ModuleKind::Chunk, vec!["a", "b"]),
+        ]
+    );
+    for (module, output_module) in report.modules.iter().zip(output.modules.iter()) {
+        assert_eq!(module.size, output_module.wasm.len());
+        assert_eq!(
+            module.code_size,
+            module
+                .functions
+                .iter()
+                .map(|symbol| symbol.size)
+                .sum::<usize>()
+        );
+        assert_eq!(
+            module.data_size,
+            module
+                .data_symbols
+                .iter()
+                .map(|symbol| symbol.size)
+                .sum::<usize>()
+        );
+    }
+
+    let [main, a, b, a_b] = &report.modules[..] else {
+        unreachable!();
+    };
+    assert_eq!(
+        symbols(&main.functions),
+        [
+            ("log", vec![]),
+            ("main", vec![]),
+            ("callback", vec!["main"])
+        ]
+    );
+    assert_eq!(main.shared_imports, ["a_entry", "b_entry"]);
+    assert_eq!(
+        symbols(&a.functions),
+        [("a_entry", vec![]), ("a_helper", vec!["a_entry"])]
+    );
+    assert_eq!(symbols(&a.data_symbols), [("a_data", vec!["a_entry"])]);
+    assert_eq!(a.data_size, 8);
+    assert_eq!(a.shared_imports, ["shared"]);
+    assert_eq!(symbols(&b.functions), [("b_entry", vec![])]);
+    assert_eq!(symbols(&a_b.functions), [("shared", vec![])]);
+    assert_eq!(a_b.shared_imports, ["log", "callback"]);
+
+    // Table ranges are disjoint and in the order of the modules.
+    let ranges: Vec<_> = report
+        .modules
+        .iter()
+        .map(|module| module.indirect_table_range.clone().unwrap())
+        .collect();
+    for pair in ranges.windows(2) {
+        assert_eq!(pair[0].end, pair[1].start);
+    }
+
+    let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+    assert_eq!(json["modules"][3]["kind"], "chunk");
+    assert_eq!(json["modules"][3]["splits"], serde_json::json!(["a", "b"]));
+    // Empty lists of splits and absent parents are omitted.
+    assert!(json["modules"][1].get("splits").is_none());
+    assert!(json["modules"][1].get("parent").is_none());
+    assert_eq!(json["chunk_merges"], serde_json::json!([]));
+}
+
+#[test]
+fn module_graph_summarizes_dependencies_between_modules() {
+    let output = split(GraphFormat::Dot, GraphLevel::Modules);
+    assert_eq!(
+        output.graph.unwrap().contents,
+        r##"digraph wasm_split {
+  node [shape=box, style=filled];
+  n0 [label="main\n29 B", fillcolor="#8dd3c7"];
+  n1 [label="a\n31 B", fillcolor="#ffffb3"];
+  n2 [label="b\n15 B", fillcolor="#bebada"];
+  n3 [label="a_b\n14 B", fillcolor="#fb8072"];
+  n0 -> n1 [label=1, color=blue];
+  n0 -> n2 [label=1, color=blue];
+  n1 -> n3 [label=1];
+  n2 -> n0 [label=1, style=dashed];
+  n2 -> n3 [label=1];
+  n3 -> n0 [label=2];
+}
+"##
+    );
+}
+
+#[test]
+fn symbol_graph_groups_symbols_by_module() {
+    let output = split(GraphFormat::Dot, GraphLevel::Symbols);
+    let graph = output.graph.unwrap();
+    assert_eq!(graph.format.file_name(), "graph.dot");
+    let lines: Vec<&str> = graph.contents.lines().map(str::trim).collect();
+    for expected in [
+        "subgraph cluster_3 {",
+        "label=\"a_b\";",
+        "n6 [label=\"shared\\n14 B\", tooltip=\"shared\", fillcolor=\"#fb8072\", width=0.59, height=0.29];",
+        // Split point call, from `main` to `a_entry`.
+        "n1 -> n3 [color=blue, penwidth=2];",
+        // Address of `callback` taken within main.
+        "n1 -> n2 [style=dashed];",
+        // Call from `shared` in `a_b` to `callback` in main.
+        "n6 -> n2 [color=red, penwidth=2];",
+        // Address of `callback` taken by `b_entry`.
+        "n4 -> n2 [color=red, penwidth=2, style=dashed];",
+    ] {
+        assert!(
+            lines.contains(&expected),
+            "Missing {expected:?} in\n{}",
+            graph.contents
+        );
+    }
+}
+
+#[test]
+fn html_graph_embeds_graph_data() {
+    let output = split(GraphFormat::Html, GraphLevel::Modules);
+    let graph = output.graph.unwrap();
+    assert_eq!(graph.format.file_name(), "graph.html");
+    assert!(graph.contents.starts_with("<!DOCTYPE html>"));
+    assert!(!graph.contents.contains("{{GRAPH}}"));
+    assert!(graph
+        .contents
+        .contains(r##"{"name":"a_b","color":"#fb8072"}"##));
+}
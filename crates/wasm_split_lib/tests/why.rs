@@ -0,0 +1,106 @@
+//! Dependency paths explaining why symbols are included.
+
+mod common;
+
+use common::{call, function, split_id, split_import, Fixture};
+use wasm_split_lib::{why, WhyResult, WhyRoot};
+
+/// Builds a module in which `target` is reached from the `main` export over
+/// two paths of different lengths, from split module `a`, and from a function
+/// that is not reachable itself.
+fn fixture() -> Vec<u8> {
+    let mut fixture = Fixture {
+        imports: vec![("env".to_string(), "log".to_string())],
+        functions: vec![
+            function(
+                "main",
+                vec![
+                    call(&split_import("a", &split_id(0))),
+                    call("long_1"),
+                    call("short"),
+                ],
+            ),
+            function("short", vec![call("target")]),
+            function("long_1", vec![call("long_2")]),
+            function("long_2", vec![call("target")]),
+            function("target", vec![call("log")]),
+            function("unreachable", vec![call("target")]),
+        ],
+        exports: vec![("main".to_string(), "main".to_string())],
+        ..Default::default()
+    };
+    fixture.add_split_point("a", &split_id(0), function("a_entry", vec![call("target")]));
+    fixture.encode()
+}
+
+/// Returns the root and symbols of each path.
+fn paths(result: &WhyResult) -> Vec<(WhyRoot, Vec<&str>)> {
+    result
+        .paths
+        .iter()
+        .map(|path| {
+            (
+                path.root.clone(),
+                path.symbols.iter().map(String::as_str).collect(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn paths_are_listed_shortest_first() {
+    let results = why(&fixture(), "^target$", 5).unwrap();
+    let [result] = &results[..] else {
+        panic!("Unexpected results {results:?}");
+    };
+    assert_eq!(result.symbol, "target");
+    assert_eq!(
+        paths(result),
+        [
+            (
+                WhyRoot::SplitPoint("a".to_string()),
+                vec!["a_entry", "target"]
+            ),
+            (
+                WhyRoot::Export("main".to_string()),
+                vec!["main", "short", "target"]
+            ),
+            (
+                WhyRoot::Export("main".to_string()),
+                vec!["main", "long_1", "long_2", "target"]
+            ),
+        ]
+    );
+}
+
+#[test]
+fn paths_are_limited_to_max_paths() {
+    let results = why(&fixture(), "^target$", 2).unwrap();
+    let roots: Vec<String> = results[0]
+        .paths
+        .iter()
+        .map(|path| path.root.to_string())
+        .collect();
+    assert_eq!(roots, ["split point of \"a\"", "export \"main\""]);
+}
+
+#[test]
+fn roots_and_unreachable_symbols() {
+    let results = why(&fixture(), "^(log|unreachable)$", 5).unwrap();
+    let [log, unreachable] = &results[..] else {
+        panic!("Unexpected results {results:?}");
+    };
+    // Imports are roots themselves.
+    assert_eq!(log.symbol, "log");
+    assert_eq!(
+        paths(log)[0],
+        (WhyRoot::Import("env::log".to_string()), vec!["log"])
+    );
+    assert_eq!(unreachable.symbol, "unreachable");
+    assert!(unreachable.paths.is_empty());
+}
+
+#[test]
+fn invalid_pattern_is_rejected() {
+    assert!(why(&fixture(), "(", 5).is_err());
+}
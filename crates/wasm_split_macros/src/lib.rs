@@ -1,90 +1,763 @@
 use proc_macro::TokenStream;
+use proc_macro2::Span;
 
 use digest::Digest;
 use quote::{format_ident, quote};
-use syn::{parse_macro_input, Ident, ItemFn, Signature};
+use syn::{
+    parenthesized,
+    parse::{Parse, ParseStream},
+    parse_macro_input, parse_quote,
+    punctuated::Punctuated,
+    spanned::Spanned,
+    visit_mut::VisitMut,
+    Ident, ItemFn, ItemImpl, LitStr, Signature, Token, Type,
+};
+
+/// Arguments to the `wasm_split` attribute:
+///
+/// ```ignore
+/// #[wasm_split(module_name)]
+/// #[wasm_split(module_name, instantiate(Type1, Type2, ...))]
+/// #[wasm_split(module_name, fallible)]
+/// #[wasm_split(module_name, import_module = "./loader.cjs")]
+/// ```
+///
+/// For functions with more than one type parameter, each instantiation is
+/// specified as a tuple with one element per type parameter. Calling a generic
+/// split function with type parameters that are not listed is a compile error.
+///
+/// With `fallible`, the wrapper returns `Result<T, wasm_split::LoadError>`
+/// rather than panicking if the split module fails to load.
+///
+/// `import_module` overrides the module from which the loader functions are
+/// imported. It defaults to the `WASM_SPLIT_IMPORT_MODULE` environment
+/// variable at build time, if set, and otherwise to `./__wasm_split.js`.
+/// `wasm-split` names the loader after it, so every split function must use
+/// the same module, e.g. for two split programs on one page to have separate
+/// loaders.
+///
+/// Each split function `name` is accompanied by a synchronous
+/// `try_call_name` that returns `None` if the split module is not yet loaded.
+/// This is not generated for methods in trait impls.
+///
+/// To split methods, the enclosing `impl` block must also be annotated with
+/// `#[wasm_split]` (without arguments).
+struct SplitArgs {
+    module_ident: Ident,
+    instantiate: Vec<Type>,
+    fallible: bool,
+    import_module: LitStr,
+}
+
+const DEFAULT_IMPORT_MODULE: &str = "./__wasm_split.js";
+
+/// Returns the default module from which the loader functions are imported.
+fn default_import_module() -> LitStr {
+    let import_module = std::env::var("WASM_SPLIT_IMPORT_MODULE")
+        .unwrap_or_else(|_| DEFAULT_IMPORT_MODULE.to_string());
+    LitStr::new(&import_module, Span::call_site())
+}
+
+/// Item that makes rustc rebuild the crate when `WASM_SPLIT_IMPORT_MODULE`
+/// changes, which it does not track for proc macros.
+fn track_import_module_env() -> proc_macro2::TokenStream {
+    quote! {
+        const _: ::core::option::Option<&str> = ::core::option_env!("WASM_SPLIT_IMPORT_MODULE");
+    }
+}
+
+fn parse_import_module(input: ParseStream) -> syn::Result<LitStr> {
+    input.parse::<Token![=]>()?;
+    input.parse()
+}
+
+impl Parse for SplitArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let module_ident = input.parse()?;
+        let mut instantiate = Vec::new();
+        let mut fallible = false;
+        let mut import_module = default_import_module();
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            let option: Ident = input.parse()?;
+            match option.to_string().as_str() {
+                "instantiate" => {
+                    let content;
+                    parenthesized!(content in input);
+                    instantiate.extend(Punctuated::<Type, Token![,]>::parse_terminated(&content)?);
+                }
+                "fallible" => fallible = true,
+                "import_module" => import_module = parse_import_module(input)?,
+                _ => {
+                    return Err(syn::Error::new(
+                        option.span(),
+                        format!("unknown wasm_split option `{option}`"),
+                    ))
+                }
+            }
+        }
+        Ok(Self {
+            module_ident,
+            instantiate,
+            fallible,
+            import_module,
+        })
+    }
+}
+
+/// Replaces `impl Trait` argument types with named type parameters.
+struct ImplTraitToParam {
+    params: Vec<syn::TypeParam>,
+}
+
+impl VisitMut for ImplTraitToParam {
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+        syn::visit_mut::visit_type_mut(self, ty);
+        if let Type::ImplTrait(impl_trait) = ty {
+            let ident = format_ident!("__WasmSplitImpl{}", self.params.len());
+            let bounds = &impl_trait.bounds;
+            self.params.push(parse_quote!(#ident: #bounds));
+            *ty = parse_quote!(#ident);
+        }
+    }
+}
+
+/// The `impl` block enclosing a split method.
+struct ImplContext<'a> {
+    self_ty: &'a Type,
+    trait_path: Option<&'a syn::Path>,
+}
+
+/// Replaces `Self` with the self type of the enclosing `impl` block, for use
+/// outside of the `impl` block.
+struct ReplaceSelf<'a> {
+    impl_context: &'a ImplContext<'a>,
+}
+
+impl VisitMut for ReplaceSelf<'_> {
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+        if let Type::Path(type_path) = ty {
+            if type_path.qself.is_none()
+                && type_path
+                    .path
+                    .segments
+                    .first()
+                    .is_some_and(|segment| segment.ident == "Self")
+            {
+                let self_ty = self.impl_context.self_ty;
+                let rest: Vec<_> = type_path.path.segments.iter().skip(1).collect();
+                *ty = if rest.is_empty() {
+                    self_ty.clone()
+                } else if let Some(trait_path) = self.impl_context.trait_path {
+                    parse_quote!(<#self_ty as #trait_path>::#(#rest)::*)
+                } else {
+                    parse_quote!(<#self_ty>::#(#rest)::*)
+                };
+                return;
+            }
+        }
+        syn::visit_mut::visit_type_mut(self, ty);
+    }
+}
+
+fn unique_identifier(name: &Ident, instantiation: Option<&Type>) -> String {
+    let instantiation = instantiation.map(|ty| quote!(#ty).to_string());
+    base16::encode_lower(
+        &sha2::Sha256::digest(format!(
+            "{name} {span:?} {instantiation:?}",
+            span = name.span()
+        ))[..16],
+    )
+}
 
 #[proc_macro_attribute]
 pub fn wasm_split(args: TokenStream, input: TokenStream) -> TokenStream {
-    let module_ident = parse_macro_input!(args as Ident);
+    if let Ok(item_impl) = syn::parse::<ItemImpl>(input.clone()) {
+        if !args.is_empty() {
+            return syn::Error::new(
+                item_impl.impl_token.span,
+                "#[wasm_split] on an impl block does not take arguments; \
+                 specify the module on each method instead",
+            )
+            .into_compile_error()
+            .into();
+        }
+        return expand_wasm_split_impl(item_impl)
+            .unwrap_or_else(syn::Error::into_compile_error)
+            .into();
+    }
+    let split_args = parse_macro_input!(args as SplitArgs);
     let item_fn = parse_macro_input!(input as ItemFn);
+    expand_split_fn(
+        &split_args,
+        &item_fn.attrs,
+        &item_fn.sig,
+        &item_fn.block,
+        None,
+    )
+    .map(
+        |SplitFnExpansion {
+             wrapper_sig,
+             wrapper_body,
+             try_call_sig,
+             try_call_body,
+             items,
+         }| {
+            let vis = &item_fn.vis;
+            quote! {
+                #items
 
-    let name = &item_fn.sig.ident;
+                #vis #wrapper_sig {
+                    #wrapper_body
+                }
 
-    let unique_identifier = base16::encode_lower(
-        &sha2::Sha256::digest(format!("{name} {span:?}", span = name.span()))[..16],
-    );
+                #[allow(dead_code)]
+                #vis #try_call_sig {
+                    #try_call_body
+                }
+            }
+        },
+    )
+    .unwrap_or_else(syn::Error::into_compile_error)
+    .into()
+}
 
-    let load_module_ident = format_ident!("__wasm_split_load_{module_ident}");
-    let split_loader_ident = format_ident!("__wasm_split_loader");
-    let impl_import_ident =
-        format_ident!("__wasm_split_00{module_ident}00_import_{unique_identifier}_{name}");
-    let impl_export_ident =
-        format_ident!("__wasm_split_00{module_ident}00_export_{unique_identifier}_{name}");
+fn is_wasm_split_attr(attr: &syn::Attribute) -> bool {
+    attr.path()
+        .segments
+        .last()
+        .is_some_and(|segment| segment.ident == "wasm_split")
+}
 
-    let import_sig = Signature {
-        ident: impl_import_ident.clone(),
-        asyncness: None,
-        ..item_fn.sig.clone()
-    };
-    let export_sig = Signature {
-        ident: impl_export_ident.clone(),
-        asyncness: None,
-        ..item_fn.sig.clone()
+/// Expands `#[wasm_split]` on an `impl` block, which splits each method
+/// annotated with `#[wasm_split(module_name, ...)]`.
+///
+/// Methods must be handled at the level of the `impl` block because the
+/// generated imports and exports cannot be nested within the method body,
+/// where `Self` cannot be named.
+fn expand_wasm_split_impl(mut item_impl: ItemImpl) -> syn::Result<proc_macro2::TokenStream> {
+    let mut items = Vec::new();
+    let mut try_call_methods = Vec::<syn::ImplItem>::new();
+    let self_ty = (*item_impl.self_ty).clone();
+    let trait_path = item_impl.trait_.as_ref().map(|(_, path, _)| path.clone());
+    let impl_context = ImplContext {
+        self_ty: &self_ty,
+        trait_path: trait_path.as_ref(),
     };
+    for impl_item in item_impl.items.iter_mut() {
+        let syn::ImplItem::Fn(method) = impl_item else {
+            continue;
+        };
+        let Some(attr_index) = method.attrs.iter().position(is_wasm_split_attr) else {
+            continue;
+        };
+        let attr = method.attrs.remove(attr_index);
+        if !item_impl.generics.params.is_empty() {
+            return Err(syn::Error::new(
+                item_impl.generics.span(),
+                "wasm_split does not support methods of generic impl blocks",
+            ));
+        }
+        if trait_path.is_some() {
+            if method.sig.asyncness.is_none() {
+                return Err(syn::Error::new(
+                    method.sig.fn_token.span,
+                    "split methods in trait impls must be declared `async fn`",
+                ));
+            }
+            if !method.sig.generics.params.is_empty() {
+                return Err(syn::Error::new(
+                    method.sig.generics.span(),
+                    "wasm_split does not support generic methods in trait impls",
+                ));
+            }
+        }
+        let split_args: SplitArgs = attr.parse_args()?;
+        let SplitFnExpansion {
+            wrapper_sig,
+            wrapper_body,
+            try_call_sig,
+            try_call_body,
+            items: method_items,
+        } = expand_split_fn(
+            &split_args,
+            &method.attrs,
+            &method.sig,
+            &method.block,
+            Some(&impl_context),
+        )?;
+        items.push(method_items);
+        method.attrs.clear();
+        method.sig = wrapper_sig;
+        method.block = parse_quote!({ #wrapper_body });
+        // Trait impls cannot contain additional methods.
+        if trait_path.is_none() {
+            let vis = &method.vis;
+            try_call_methods.push(parse_quote! {
+                #[allow(dead_code)]
+                #vis #try_call_sig {
+                    #try_call_body
+                }
+            });
+        }
+    }
+    item_impl.items.extend(try_call_methods);
+    Ok(quote! {
+        #item_impl
+
+        #(#items)*
+    })
+}
 
-    let mut wrapper_sig = item_fn.sig;
+struct SplitFnExpansion {
+    wrapper_sig: Signature,
+    wrapper_body: proc_macro2::TokenStream,
+    // Synchronous variant that returns `None` if the module is not loaded.
+    try_call_sig: Signature,
+    try_call_body: proc_macro2::TokenStream,
+    // Additional items to emit alongside the wrapper.
+    items: proc_macro2::TokenStream,
+}
+
+fn expand_split_fn(
+    split_args: &SplitArgs,
+    attrs: &[syn::Attribute],
+    sig: &Signature,
+    block: &syn::Block,
+    impl_context: Option<&ImplContext>,
+) -> syn::Result<SplitFnExpansion> {
+    let module_ident = &split_args.module_ident;
+    let import_module = &split_args.import_module;
+    let name = &sig.ident;
+
+    let load_module_ident = format_ident!("__wasm_split_load_{module_ident}");
+    let split_loader_ident = format_ident!("__wasm_split_loader");
+
+    let mut wrapper_sig = sig.clone();
     wrapper_sig.asyncness = Some(Default::default());
+    let mut impl_trait_params = ImplTraitToParam { params: Vec::new() };
+    // Parameter names used by the generated imports and exports.
+    let mut params = Vec::new();
+    // Corresponding arguments passed by the wrapper.
     let mut args = Vec::new();
+    let mut param_types = Vec::new();
     for (i, param) in wrapper_sig.inputs.iter_mut().enumerate() {
+        let param_ident = format_ident!("__wasm_split_arg_{i}");
+        params.push(param_ident.clone());
         match param {
             syn::FnArg::Typed(pat_type) => {
-                let param_ident = format_ident!("__wasm_split_arg_{i}");
-                args.push(param_ident.clone());
-                pat_type.pat = Box::new(syn::Pat::Ident(syn::PatIdent {
+                args.push(quote!(#param_ident));
+                *pat_type.pat = syn::Pat::Ident(syn::PatIdent {
                     attrs: vec![],
                     by_ref: None,
                     mutability: None,
                     ident: param_ident,
                     subpat: None,
-                }));
+                });
+                impl_trait_params.visit_type_mut(&mut pat_type.ty);
+                param_types.push((*pat_type.ty).clone());
             }
-            syn::FnArg::Receiver(_) => {
-                args.push(format_ident!("self"));
+            syn::FnArg::Receiver(receiver) => {
+                if impl_context.is_none() {
+                    return Err(syn::Error::new(
+                        receiver.span(),
+                        "to split a method, also add #[wasm_split] to the enclosing impl block",
+                    ));
+                }
+                args.push(quote!(self));
+                param_types.push((*receiver.ty).clone());
             }
         }
     }
+    let return_type: Type = match &wrapper_sig.output {
+        syn::ReturnType::Default => parse_quote!(()),
+        syn::ReturnType::Type(_, ty) => (**ty).clone(),
+    };
+    if let Some(impl_context) = impl_context {
+        let mut replace_self = ReplaceSelf { impl_context };
+        for ty in param_types.iter_mut() {
+            replace_self.visit_type_mut(ty);
+        }
+    }
 
-    let attrs = item_fn.attrs;
+    let mut type_params = Vec::new();
+    for param in sig.generics.params.iter() {
+        match param {
+            syn::GenericParam::Type(type_param) => type_params.push(type_param.ident.clone()),
+            syn::GenericParam::Lifetime(_) => {}
+            syn::GenericParam::Const(const_param) => {
+                return Err(syn::Error::new(
+                    const_param.span(),
+                    "wasm_split does not support const generic parameters",
+                ));
+            }
+        }
+    }
+    let explicit_type_params = type_params.clone();
+    type_params.extend(
+        impl_trait_params
+            .params
+            .iter()
+            .map(|param| param.ident.clone()),
+    );
+    wrapper_sig.generics.params.extend(
+        impl_trait_params
+            .params
+            .into_iter()
+            .map(syn::GenericParam::Type),
+    );
 
-    let stmts = &item_fn.block.stmts;
+    // Each instantiation gets its own import/export pair. For generic
+    // functions, the wrapper dispatches to the matching import through a trait
+    // implemented for the tuple of type parameters of each instantiation, so
+    // that calls with other type parameters do not compile.
+    let instantiations: Vec<Option<&Type>> = if type_params.is_empty() {
+        if let Some(ty) = split_args.instantiate.first() {
+            return Err(syn::Error::new(
+                ty.span(),
+                "`instantiate` requires a generic function",
+            ));
+        }
+        vec![None]
+    } else {
+        if split_args.instantiate.is_empty() {
+            return Err(syn::Error::new(
+                sig.generics.span(),
+                "generic split functions must list the instantiations to split \
+                 using `instantiate(...)`",
+            ));
+        }
+        split_args.instantiate.iter().map(Some).collect()
+    };
+    let dispatch_mod_ident = format_ident!(
+        "__wasm_split_instantiations_{name}_{}",
+        unique_identifier(name, None)
+    );
 
-    quote! {
-        #wrapper_sig {
-            thread_local! {
-                static #split_loader_ident: ::wasm_split::LazySplitLoader = unsafe { ::wasm_split::LazySplitLoader::new(#load_module_ident) };
+    // The original function body is moved into a separate function that is
+    // only called from the exports.
+    let mut body_sig = Signature {
+        asyncness: None,
+        ..sig.clone()
+    };
+    let (body_items, body_fn) = match impl_context {
+        None => {
+            let body_ident = format_ident!("__wasm_split_impl_{name}");
+            body_sig.ident = body_ident.clone();
+            (
+                quote! {
+                    #(#attrs)*
+                    #body_sig #block
+                },
+                quote!(#body_ident),
+            )
+        }
+        Some(ImplContext { self_ty, .. }) => {
+            let unique_identifier = unique_identifier(name, None);
+            let trait_ident = format_ident!("__WasmSplitMethod_{name}_{unique_identifier}");
+            body_sig.ident = format_ident!("__wasm_split_body");
+            let mut decl_sig = body_sig.clone();
+            for (param, param_ident) in decl_sig.inputs.iter_mut().zip(params.iter()) {
+                if let syn::FnArg::Typed(pat_type) = param {
+                    *pat_type.pat = parse_quote!(#param_ident);
+                }
             }
+            (
+                quote! {
+                    #[doc(hidden)]
+                    #[allow(non_camel_case_types)]
+                    trait #trait_ident {
+                        #decl_sig;
+                    }
 
-            #[link(wasm_import_module = "./__wasm_split.js")]
-            extern "C" {
-                #[no_mangle]
-                fn #load_module_ident (callback: unsafe extern "C" fn(*const ::std::ffi::c_void, bool), data: *const ::std::ffi::c_void) -> ();
+                    impl #trait_ident for #self_ty {
+                        #(#attrs)*
+                        #body_sig #block
+                    }
+                },
+                quote!(<#self_ty as #trait_ident>::__wasm_split_body),
+            )
+        }
+    };
 
-                #[allow(improper_ctypes)]
-                #[no_mangle]
-                #import_sig;
+    // The exports unpack the arguments and call the body through a function
+    // that is generic like the wrapper, so that the argument types need not be
+    // instantiated by the macro.
+    let export_helper_ident = format_ident!(
+        "__wasm_split_export_{name}_{}",
+        unique_identifier(name, None)
+    );
+    let mut export_helper_generics = wrapper_sig.generics.clone();
+    if let Some(impl_context) = impl_context {
+        ReplaceSelf { impl_context }.visit_generics_mut(&mut export_helper_generics);
+    }
+    let (export_helper_generics, _, export_helper_where_clause) =
+        export_helper_generics.split_for_impl();
+    let export_helper = quote! {
+        unsafe fn #export_helper_ident #export_helper_generics (args: *mut ::std::ffi::c_void) -> *mut ::std::ffi::c_void #export_helper_where_clause {
+            let ( #(#params,)* ) = *::std::boxed::Box::from_raw(args as *mut ( #(#param_types,)* ));
+            let result = #body_fn::<#(#explicit_type_params),*>( #(#params),* );
+            ::std::boxed::Box::into_raw(::std::boxed::Box::new(result)) as *mut ::std::ffi::c_void
+        }
+    };
+
+    if !type_params.is_empty() {
+        wrapper_sig
+            .generics
+            .make_where_clause()
+            .predicates
+            .push(parse_quote!((#(#type_params,)*): #dispatch_mod_ident::Instantiation));
+    }
+
+    let mut imports = Vec::new();
+    let mut exports = Vec::new();
+    let mut instantiation_impls = Vec::new();
+    // Calls the import corresponding to the instantiation. Arguments and
+    // results are passed as pointers to heap-allocated values since they are
+    // not in general FFI-safe.
+    let mut call_import = quote! {
+        unsafe { <(#(#type_params,)*) as #dispatch_mod_ident::Instantiation>::call(args) }
+    };
+    for instantiation in instantiations {
+        let types: Vec<Type> = match instantiation {
+            None => Vec::new(),
+            Some(instantiation) if type_params.len() == 1 => vec![instantiation.clone()],
+            Some(instantiation) => match instantiation {
+                Type::Tuple(tuple) if tuple.elems.len() == type_params.len() => {
+                    tuple.elems.iter().cloned().collect()
+                }
+                _ => {
+                    return Err(syn::Error::new(
+                        instantiation.span(),
+                        format!(
+                            "expected a tuple of {} types, one for each type parameter",
+                            type_params.len()
+                        ),
+                    ))
+                }
+            },
+        };
+
+        let unique_identifier = unique_identifier(name, instantiation);
+        let impl_import_ident =
+            format_ident!("__wasm_split_00{module_ident}00_import_{unique_identifier}_{name}");
+        let impl_export_ident =
+            format_ident!("__wasm_split_00{module_ident}00_export_{unique_identifier}_{name}");
+
+        let import = quote! {
+            #[no_mangle]
+            fn #impl_import_ident(args: *mut ::std::ffi::c_void) -> *mut ::std::ffi::c_void;
+        };
+        exports.push(quote! {
+            #[no_mangle]
+            unsafe extern "C" fn #impl_export_ident(args: *mut ::std::ffi::c_void) -> *mut ::std::ffi::c_void {
+                #export_helper_ident::<#(#types),*>(args)
+            }
+        });
+        if instantiation.is_none() {
+            imports.push(import);
+            call_import = quote!(unsafe { #impl_import_ident(args) });
+            continue;
+        }
+        instantiation_impls.push(quote! {
+            impl #dispatch_mod_ident::Instantiation for (#(#types,)*) {
+                unsafe fn call(args: *mut ::std::ffi::c_void) -> *mut ::std::ffi::c_void {
+                    #[link(wasm_import_module = #import_module)]
+                    extern "C" {
+                        #import
+                    }
+                    #impl_import_ident(args)
+                }
+            }
+        });
+    }
+    // The trait is declared in a private module so that it cannot be
+    // implemented outside of the crate.
+    let dispatch_items = if type_params.is_empty() {
+        quote!()
+    } else {
+        let message = format!("`{name}` is not split for the type parameters `{{Self}}`");
+        quote! {
+            #[doc(hidden)]
+            mod #dispatch_mod_ident {
+                #[diagnostic::on_unimplemented(
+                    message = #message,
+                    note = "add the type parameters to `instantiate(...)` in `#[wasm_split]`"
+                )]
+                pub trait Instantiation {
+                    unsafe fn call(args: *mut ::std::ffi::c_void) -> *mut ::std::ffi::c_void;
+                }
             }
 
-            #(#attrs)*
-            #[allow(improper_ctypes_definitions)]
+            #(#instantiation_impls)*
+        }
+    };
+
+    let track_env = track_import_module_env();
+    let loader = quote! {
+        #track_env
+
+        thread_local! {
+            static #split_loader_ident: ::wasm_split::LazySplitLoader = unsafe { ::wasm_split::LazySplitLoader::new(#load_module_ident, ::core::stringify!(#module_ident)) };
+        }
+
+        #[link(wasm_import_module = #import_module)]
+        extern "C" {
             #[no_mangle]
-            pub extern "C" #export_sig {
-                #(#stmts)*
+            fn #load_module_ident (callback: ::wasm_split::LoadCallbackFn, data: *const ::std::ffi::c_void) -> ();
+        }
+    };
+    let import_items = quote! {
+        #[link(wasm_import_module = #import_module)]
+        extern "C" {
+            #(#imports)*
+        }
+    };
+    let export_items = quote! {
+        #body_items
+
+        #export_helper
+
+        #(#exports)*
+    };
+    let call = quote! {
+        let args = ::std::boxed::Box::into_raw(::std::boxed::Box::new(( #(#args,)* ))) as *mut ::std::ffi::c_void;
+        let result = #call_import;
+        let result = *unsafe { ::std::boxed::Box::from_raw(result as *mut #return_type) };
+    };
+
+    let mut try_call_sig = wrapper_sig.clone();
+    try_call_sig.asyncness = None;
+    try_call_sig.ident = format_ident!("try_call_{name}");
+    try_call_sig.output = parse_quote!(-> ::core::option::Option<#return_type>);
+
+    let (ensure_loaded, wrap_result) = if split_args.fallible {
+        wrapper_sig.output =
+            parse_quote!(-> ::core::result::Result<#return_type, ::wasm_split::LoadError>);
+        (
+            quote!(::wasm_split::ensure_loaded(&#split_loader_ident).await?;),
+            quote!(::core::result::Result::Ok(result)),
+        )
+    } else {
+        (
+            quote!(::wasm_split::ensure_loaded(&#split_loader_ident).await.unwrap();),
+            quote!(result),
+        )
+    };
+
+    // Free functions keep the generated items within the wrapper, except for
+    // the dispatch trait that the signature refers to, while methods need them
+    // outside of the `impl` block.
+    let (wrapper_items, try_call_items, items) = match impl_context {
+        None => (
+            quote!(#import_items #export_items),
+            import_items,
+            dispatch_items,
+        ),
+        Some(_) => (
+            quote!(),
+            quote!(),
+            quote!(#import_items #export_items #dispatch_items),
+        ),
+    };
+    Ok(SplitFnExpansion {
+        wrapper_sig,
+        wrapper_body: quote! {
+            #loader
+
+            #wrapper_items
+
+            #ensure_loaded
+            #call
+            #wrap_result
+        },
+        try_call_sig,
+        try_call_body: quote! {
+            #try_call_items
+
+            if ::wasm_split::status(::core::stringify!(#module_ident)) != ::wasm_split::LoadStatus::Loaded {
+                return ::core::option::Option::None;
             }
+            #call
+            ::core::option::Option::Some(result)
+        },
+        items,
+    })
+}
+
+/// Arguments to the `preload` macro.
+struct PreloadArgs {
+    module_ident: Ident,
+    import_module: LitStr,
+}
 
-            ::wasm_split::ensure_loaded(&#split_loader_ident).await.unwrap();
-            unsafe { #impl_import_ident( #(#args),* ) }
+impl Parse for PreloadArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let module_ident = input.parse()?;
+        let mut import_module = default_import_module();
+        if input.parse::<Option<Token![,]>>()?.is_some() && !input.is_empty() {
+            let option: Ident = input.parse()?;
+            if option != "import_module" {
+                return Err(syn::Error::new(
+                    option.span(),
+                    format!("unknown preload option `{option}`"),
+                ));
+            }
+            import_module = parse_import_module(input)?;
+            input.parse::<Option<Token![,]>>()?;
         }
+        Ok(Self {
+            module_ident,
+            import_module,
+        })
+    }
+}
+
+/// Starts loading a split module without calling any of its functions:
+///
+/// ```ignore
+/// wasm_split::preload!(module_name);
+/// wasm_split::preload!(module_name, import_module = "./loader.cjs");
+/// ```
+#[proc_macro]
+pub fn preload(input: TokenStream) -> TokenStream {
+    let PreloadArgs {
+        module_ident,
+        import_module,
+    } = parse_macro_input!(input as PreloadArgs);
+    let load_module_ident = format_ident!("__wasm_split_load_{module_ident}");
+    let track_env = track_import_module_env();
+    quote! {
+        {
+            #track_env
+
+            #[link(wasm_import_module = #import_module)]
+            extern "C" {
+                #[no_mangle]
+                fn #load_module_ident (callback: ::wasm_split::LoadCallbackFn, data: *const ::std::ffi::c_void) -> ();
+            }
+            unsafe { ::wasm_split::preload(#load_module_ident, ::core::stringify!(#module_ident)) }
+        }
+    }
+    .into()
+}
+
+/// Imports the functions of an `extern` block from the loader, for use by the
+/// `wasm_split` crate.
+#[doc(hidden)]
+#[proc_macro_attribute]
+pub fn loader_import(_args: TokenStream, input: TokenStream) -> TokenStream {
+    let foreign_mod = parse_macro_input!(input as syn::ItemForeignMod);
+    let import_module = default_import_module();
+    let track_env = track_import_module_env();
+    quote! {
+        #track_env
+
+        #[link(wasm_import_module = #import_module)]
+        #foreign_mod
     }
     .into()
 }